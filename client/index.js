@@ -11,18 +11,104 @@ colorButtons.forEach(button => button.addEventListener("click", () => canvas.set
 
 colors.addEventListener("input", () => canvas.set_stroke_color(colors.value));
 
+document.querySelector("#eraser").addEventListener("click", () => canvas.set_eraser());
+
+document.querySelector("#stroke-alpha").addEventListener("input", (e) => canvas.set_stroke_alpha(e.target.value / 100));
+
+document.querySelector("#new-transparent").addEventListener("click", () => {
+  location.search = "?transparent";
+});
+
 document.querySelector("#straight").addEventListener("click", () => canvas.set_straight_line());
 
 document.querySelector("#circle").addEventListener("click", () => canvas.set_circle());
 
+document.querySelector("#rectangle").addEventListener("click", () => canvas.set_rectangle());
+
+document.querySelector("#rounded-rectangle").addEventListener("click", () => canvas.set_rounded_rectangle());
+
+document.querySelector("#ellipse").addEventListener("click", () => canvas.set_ellipse());
+
+document.querySelector("#shape-style").addEventListener("change", (e) => canvas.set_shape_style(e.target.value));
+
+document.querySelector("#shape-fill").addEventListener("input", (e) => canvas.set_shape_fill_color(e.target.value));
+
 document.querySelector("#pen").addEventListener("click", () => canvas.set_default_stroke());
 
 document.querySelector("#fill").addEventListener("click", () => canvas.set_fill());
 
+document.querySelector("#fill-tolerance").addEventListener("input", (e) => canvas.set_fill_tolerance(e.target.value));
+
+document.querySelector("#fill-global").addEventListener("change", (e) => canvas.set_fill_contiguous(!e.target.checked));
+
+document.querySelector("#fill-antialias").addEventListener("change", (e) => canvas.set_fill_antialias(e.target.checked));
+
 document.querySelector("#crop").addEventListener("click", () => canvas.set_crop());
 
+document.querySelector("#lasso").addEventListener("click", () => canvas.set_lasso());
+
+document.querySelector("#polygon").addEventListener("click", () => canvas.set_polygon());
+
+document.querySelector("#magic-wand").addEventListener("click", () => canvas.set_magic_wand());
+
+document.querySelector("#color-picker").addEventListener("click", () => canvas.set_color_picker());
+
+document.querySelector("#picker-size").addEventListener("change", (e) => canvas.set_color_picker_size(e.target.value));
+
 document.querySelector("#text").addEventListener("click", () => canvas.set_text());
 
+document.querySelector("#object-select").addEventListener("click", () => canvas.set_object_select());
+
+const applyFont = () => canvas.set_font(
+  document.querySelector("#font-family").value,
+  Number(document.querySelector("#font-size").value),
+  document.querySelector("#font-bold").checked ? 700 : 400,
+  document.querySelector("#font-italic").checked,
+);
+for (const id of ["#font-family", "#font-size", "#font-bold", "#font-italic"]) {
+  document.querySelector(id).addEventListener("change", applyFont);
+}
+
+document.querySelector("#text-align").addEventListener("change", (e) => canvas.set_text_align(e.target.value));
+
+document.querySelector("#text-baseline").addEventListener("change", (e) => canvas.set_text_baseline(e.target.value));
+
+document.querySelector("#line-height").addEventListener("change", (e) => canvas.set_line_height(Number(e.target.value)));
+
+const applyOutline = () => canvas.set_text_outline(
+  Number(document.querySelector("#text-outline").value),
+  document.querySelector("#text-outline-color").value,
+);
+document.querySelector("#text-outline").addEventListener("change", applyOutline);
+document.querySelector("#text-outline-color").addEventListener("input", applyOutline);
+
+document.querySelector("#flatten-text").addEventListener("click", () => canvas.flatten_text());
+
+// These throw while nothing is floating, which leaves nothing to do.
+const onSelection = (id, action) => document.querySelector(id).addEventListener("click", () => {
+  try {
+    action();
+  } catch (_) {}
+});
+onSelection("#flip-horizontal", () => canvas.flip_selection_horizontal());
+onSelection("#flip-vertical", () => canvas.flip_selection_vertical());
+onSelection("#rotate-selection", () => canvas.rotate_selection(90));
+onSelection("#float-selection", () => canvas.float_selection());
+onSelection("#place-selection", () => canvas.place_selection());
+onSelection("#select-none", () => canvas.select_none());
+// Likewise while no shape is picked.
+onSelection("#raise-shape", () => canvas.raise_selected_shape());
+onSelection("#lower-shape", () => canvas.lower_selected_shape());
+onSelection("#delete-shape", () => canvas.delete_selected_shape());
+
+document.querySelector("#apply-filter").addEventListener("click", () => {
+  canvas.apply_filter(document.querySelector("#filter").value, {
+    radius: Number(document.querySelector("#filter-radius").value),
+    brightness: document.querySelector("#filter-brightness").value / 100,
+    contrast: document.querySelector("#filter-contrast").value / 100,
+  });
+});
+
 document.querySelector("#export").addEventListener("click", () => {
   const url = canvas.export();
   const download = document.createElement("a");
@@ -31,6 +117,15 @@ document.querySelector("#export").addEventListener("click", () => {
   download.click();
 });
 
+document.querySelector("#export-svg").addEventListener("click", () => {
+  const blob = new Blob([canvas.export_svg()], { type: "image/svg+xml" });
+  const download = document.createElement("a");
+  download.download = "paint_assemble_export.svg";
+  download.href = URL.createObjectURL(blob);
+  download.click();
+  URL.revokeObjectURL(download.href);
+});
+
 document.querySelector("#import").addEventListener("change", (event) => {
   const file = event.target.files[0];
   const reader = new FileReader();
@@ -47,6 +142,152 @@ document.querySelector("#import").addEventListener("change", (event) => {
   }
 });
 
-const canvas = Canvas.new_canvas(window.innerHeight * 0.97, window.innerWidth * 0.86);
+document.querySelector("#save-project").addEventListener("click", () => {
+  const blob = new Blob([canvas.save_project()], { type: "application/octet-stream" });
+  const download = document.createElement("a");
+  download.download = "paint_assemble.pnta";
+  download.href = URL.createObjectURL(blob);
+  download.click();
+  URL.revokeObjectURL(download.href);
+});
+
+document.querySelector("#open-project").addEventListener("change", async (event) => {
+  const file = event.target.files[0];
+  if (!file) return;
+
+  try {
+    canvas.load_project(new Uint8Array(await file.arrayBuffer()));
+  } catch (e) {
+    alert(e);
+  }
+  event.target.value = "";
+  renderLayers();
+});
+
+document.addEventListener("keydown", (event) => {
+  if (!(event.ctrlKey || event.metaKey)) return;
+
+  const key = event.key.toLowerCase();
+  if (key === "z" && !event.shiftKey) {
+    event.preventDefault();
+    canvas.undo();
+  } else if (key === "y" || (key === "z" && event.shiftKey)) {
+    event.preventDefault();
+    canvas.redo();
+  } else if (key === "c" || key === "x") {
+    event.preventDefault();
+    try {
+      canvas.copy_to_system_clipboard().catch(console.warn);
+      if (key === "x") canvas.cut();
+    } catch (e) {
+      console.warn(e);
+    }
+  } else if (key === "v") {
+    event.preventDefault();
+    // Fall back to the in-app clipboard when the browser refuses or holds no image.
+    canvas.paste_from_system_clipboard().catch(() => {
+      try {
+        canvas.paste();
+      } catch (e) {
+        console.warn(e);
+      }
+    });
+  } else if (key === "d") {
+    event.preventDefault();
+    try {
+      canvas.duplicate();
+    } catch (e) {
+      console.warn(e);
+    }
+  } else if (key === "0") {
+    event.preventDefault();
+    canvas.zoom_to_fit();
+  } else if (key === "=" || key === "+" || key === "-") {
+    event.preventDefault();
+    const step = key === "-" ? 1 / 1.25 : 1.25;
+    canvas.zoom_to(canvasWidth / 2, canvasHeight / 2, canvas.zoom() * step);
+  }
+});
+
+const layerList = document.querySelector("#layers");
+
+const renderLayers = () => {
+  layerList.replaceChildren(...canvas.layers().map(layer => {
+    const item = document.createElement("li");
+    item.classList.toggle("active", layer.active);
+    item.addEventListener("click", () => {
+      canvas.set_active_layer(layer.id);
+      renderLayers();
+    });
+
+    const visible = document.createElement("input");
+    visible.type = "checkbox";
+    visible.checked = layer.visible;
+    visible.addEventListener("click", (e) => e.stopPropagation());
+    visible.addEventListener("change", () => canvas.set_layer_visible(layer.id, visible.checked));
+
+    const name = document.createElement("span");
+    name.textContent = layer.vector ? `${layer.name} (vector)` : layer.name;
+    name.addEventListener("dblclick", () => {
+      const value = prompt("Layer name", layer.name);
+      if (value) {
+        canvas.rename_layer(layer.id, value);
+        renderLayers();
+      }
+    });
+
+    const opacity = document.createElement("input");
+    opacity.type = "range";
+    opacity.min = 0;
+    opacity.max = 100;
+    opacity.value = layer.opacity * 100;
+    opacity.addEventListener("click", (e) => e.stopPropagation());
+    opacity.addEventListener("input", () => canvas.set_layer_opacity(layer.id, opacity.value / 100));
+
+    item.append(visible, name, opacity);
+    return item;
+  }));
+};
+
+document.querySelector("#add-layer").addEventListener("click", () => {
+  canvas.add_layer(`Layer ${canvas.layers().length}`);
+  renderLayers();
+});
+
+document.querySelector("#add-vector-layer").addEventListener("click", () => {
+  canvas.add_vector_layer(`Layer ${canvas.layers().length}`);
+  renderLayers();
+});
+
+document.querySelector("#remove-layer").addEventListener("click", () => {
+  try {
+    canvas.remove_layer(canvas.active_layer());
+  } catch (e) {
+    console.warn(e);
+  }
+  renderLayers();
+});
+
+document.querySelector("#merge-layer").addEventListener("click", () => {
+  try {
+    canvas.merge_down(canvas.active_layer());
+  } catch (e) {
+    console.warn(e);
+  }
+  renderLayers();
+});
+
+const canvasHeight = Math.floor(window.innerHeight * 0.97);
+const canvasWidth = Math.floor(window.innerWidth * 0.86);
+const container = document.querySelector("#paint-assemble");
+const canvas = new URLSearchParams(location.search).has("transparent")
+  ? Canvas.new_transparent_canvas(canvasHeight, canvasWidth, container)
+  : Canvas.new_canvas(canvasHeight, canvasWidth, container);
 canvas.set_stroke_width(8)
 canvas.set_stroke_color(colors.value);
+canvas.on("color-changed", ({ color, alpha }) => {
+  colors.value = color;
+  document.querySelector("#stroke-alpha").value = Math.round(alpha * 100);
+});
+canvas.on("document-modified", () => renderLayers());
+renderLayers();
@@ -0,0 +1,70 @@
+use js_sys::{Array, Function, Object, Promise, Reflect};
+use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, ClipboardItem, ImageBitmap};
+
+use crate::{
+    raster::{Rect, Region, Surface},
+    utils::{create_canvas, region_canvas},
+};
+
+/// Puts `region` on the system clipboard as a png. The returned promise
+/// settles once the browser has accepted it.
+pub fn write_image(region: &Region) -> Result<Promise, JsValue> {
+    let canvas = region_canvas(region)?;
+
+    // Handing the clipboard a promise keeps the write inside the user
+    // gesture while the png is still being encoded.
+    let png = Promise::new(&mut |resolve: Function, reject: Function| {
+        let failed = reject.clone();
+        let callback = Closure::once_into_js(move |blob: JsValue| {
+            let _ = if blob.is_null() {
+                failed.call1(&JsValue::NULL, &"could not encode the selection".into())
+            } else {
+                resolve.call1(&JsValue::NULL, &blob)
+            };
+        });
+        if let Err(error) = canvas.to_blob(callback.unchecked_ref()) {
+            let _ = reject.call1(&JsValue::NULL, &error);
+        }
+    });
+
+    let items = Object::new();
+    Reflect::set(&items, &"image/png".into(), &png)?;
+    let item = ClipboardItem::new_with_record_from_str_to_blob_promise(&items)?;
+
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window"))?;
+    Ok(window.navigator().clipboard().write(&Array::of1(&item)))
+}
+
+/// Reads the first image on the system clipboard.
+pub async fn read_image() -> Result<Region, JsValue> {
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window"))?;
+    let items: Array = JsFuture::from(window.navigator().clipboard().read())
+        .await?
+        .dyn_into()?;
+
+    for item in items.iter() {
+        let item: ClipboardItem = item.dyn_into()?;
+        let image_type = item
+            .types()
+            .iter()
+            .filter_map(|kind| kind.as_string())
+            .find(|kind| kind.starts_with("image/"));
+
+        if let Some(image_type) = image_type {
+            let blob: Blob = JsFuture::from(item.get_type(&image_type))
+                .await?
+                .dyn_into()?;
+            let bitmap: ImageBitmap = JsFuture::from(window.create_image_bitmap_with_blob(&blob)?)
+                .await?
+                .dyn_into()?;
+
+            let (_, context) = create_canvas(bitmap.width(), bitmap.height())?;
+            context.draw_image_with_image_bitmap(&bitmap, 0.0, 0.0)?;
+            return context.read(&Rect::new(0, 0, bitmap.width(), bitmap.height()));
+        }
+    }
+
+    Err(JsValue::from_str("no image on the clipboard"))
+}
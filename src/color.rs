@@ -0,0 +1,421 @@
+//! CSS colour parsing, following CSS Color Level 4 for named colours, hex
+//! colours and the `rgb()`, `hsl()` and `hwb()` functions, so colours are
+//! read the same way the browser reads them.
+
+use std::fmt;
+
+use wasm_bindgen::JsValue;
+
+pub type Rgba = (u8, u8, u8, u8);
+
+#[derive(Debug, PartialEq)]
+pub enum ParseColorError {
+    /// Neither a colour name, a hex colour nor a colour function.
+    Unknown(String),
+    /// A `#` not followed by 3, 4, 6 or 8 hex digits.
+    BadHex(String),
+    /// A colour function with the wrong number or kind of arguments.
+    BadArguments(String),
+}
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseColorError::Unknown(input) => write!(f, "unknown colour \"{}\"", input),
+            ParseColorError::BadHex(input) => write!(f, "invalid hex colour \"{}\"", input),
+            ParseColorError::BadArguments(input) => {
+                write!(f, "invalid colour arguments in \"{}\"", input)
+            }
+        }
+    }
+}
+
+impl From<ParseColorError> for JsValue {
+    fn from(error: ParseColorError) -> JsValue {
+        JsValue::from_str(&error.to_string())
+    }
+}
+
+/// Parses a css colour, ignoring case and surrounding whitespace.
+pub fn parse(input: &str) -> Result<Rgba, ParseColorError> {
+    let css = input.trim().to_ascii_lowercase();
+
+    if let Some(hex) = css.strip_prefix('#') {
+        return parse_hex(hex).ok_or_else(|| ParseColorError::BadHex(input.to_owned()));
+    }
+
+    if let Some(open) = css.find('(') {
+        if !css.ends_with(')') {
+            return Err(ParseColorError::Unknown(input.to_owned()));
+        }
+        let name = css[..open].trim_end();
+        let arguments = &css[open + 1..css.len() - 1];
+        let bad_arguments = || ParseColorError::BadArguments(input.to_owned());
+
+        return match name {
+            "rgb" | "rgba" => parse_rgb(arguments).ok_or_else(bad_arguments),
+            "hsl" | "hsla" => parse_hsl(arguments).ok_or_else(bad_arguments),
+            "hwb" => parse_hwb(arguments).ok_or_else(bad_arguments),
+            _ => Err(ParseColorError::Unknown(input.to_owned())),
+        };
+    }
+
+    if css == "transparent" {
+        return Ok((0, 0, 0, 0));
+    }
+    NAMED_COLORS
+        .binary_search_by_key(&css.as_str(), |(name, _)| name)
+        .map(|index| {
+            let [_, r, g, b] = NAMED_COLORS[index].1.to_be_bytes();
+            (r, g, b, 255)
+        })
+        .map_err(|_| ParseColorError::Unknown(input.to_owned()))
+}
+
+fn parse_hex(hex: &str) -> Option<Rgba> {
+    if !hex.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digit = |index: usize| u8::from_str_radix(&hex[index..index + 1], 16).ok();
+    let pair = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+
+    match hex.len() {
+        3 | 4 => {
+            let short = |index: usize| digit(index).map(|value| value * 17);
+            let alpha = if hex.len() == 4 { short(3)? } else { 255 };
+            Some((short(0)?, short(1)?, short(2)?, alpha))
+        }
+        6 | 8 => {
+            let alpha = if hex.len() == 8 { pair(6)? } else { 255 };
+            Some((pair(0)?, pair(2)?, pair(4)?, alpha))
+        }
+        _ => None,
+    }
+}
+
+/// A function argument, `none` reading as zero as CSS Color 4 says.
+#[derive(Clone, Copy)]
+enum Value {
+    Number(f64),
+    Percentage(f64),
+    /// In degrees.
+    Angle(f64),
+    None,
+}
+
+/// Splits the arguments into three components and an optional alpha,
+/// accepting both the legacy comma separated syntax and the space
+/// separated one with a `/` before the alpha.
+fn split_arguments(arguments: &str) -> Option<(Vec<Value>, Option<Value>)> {
+    let (parts, alpha): (Vec<&str>, Option<&str>) = if arguments.contains(',') {
+        let mut parts: Vec<&str> = arguments.split(',').map(str::trim).collect();
+        let alpha = if parts.len() == 4 { parts.pop() } else { None };
+        (parts, alpha)
+    } else {
+        let mut halves = arguments.splitn(2, '/');
+        let parts = halves.next()?.split_whitespace().collect();
+        let alpha = halves.next().map(str::trim);
+        (parts, alpha)
+    };
+    if parts.len() != 3 {
+        return None;
+    }
+
+    let values = parts
+        .into_iter()
+        .map(parse_value)
+        .collect::<Option<Vec<_>>>()?;
+    let alpha = match alpha {
+        Some(alpha) => Some(parse_value(alpha)?),
+        None => None,
+    };
+    Some((values, alpha))
+}
+
+fn parse_value(token: &str) -> Option<Value> {
+    if token == "none" {
+        return Some(Value::None);
+    }
+    if let Some(number) = token.strip_suffix('%') {
+        return parse_number(number).map(Value::Percentage);
+    }
+
+    let units: [(&str, f64); 4] = [
+        ("deg", 1.0),
+        ("grad", 0.9),
+        ("rad", 180.0 / std::f64::consts::PI),
+        ("turn", 360.0),
+    ];
+    for (unit, degrees) in units.iter() {
+        if let Some(number) = token.strip_suffix(unit) {
+            return parse_number(number).map(|value| Value::Angle(value * degrees));
+        }
+    }
+
+    parse_number(token).map(Value::Number)
+}
+
+/// Reads a css number, which unlike Rust floats has no `inf` or `NaN`.
+fn parse_number(token: &str) -> Option<f64> {
+    let allowed = |c: char| c.is_ascii_digit() || matches!(c, '.' | '+' | '-' | 'e');
+    if token.is_empty() || !token.chars().all(allowed) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+fn to_channel(value: f64) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+fn alpha_channel(alpha: Option<Value>) -> Option<u8> {
+    let alpha = match alpha {
+        None => 1.0,
+        Some(Value::Number(value)) => value,
+        Some(Value::Percentage(value)) => value / 100.0,
+        Some(Value::None) => 0.0,
+        Some(Value::Angle(_)) => return None,
+    };
+    Some(to_channel(alpha.clamp(0.0, 1.0) * 255.0))
+}
+
+fn parse_rgb(arguments: &str) -> Option<Rgba> {
+    let (values, alpha) = split_arguments(arguments)?;
+    // The legacy syntax can't mix numbers and percentages.
+    if arguments.contains(',') {
+        let percentages = values
+            .iter()
+            .filter(|value| matches!(value, Value::Percentage(_)))
+            .count();
+        if percentages != 0 && percentages != 3 {
+            return None;
+        }
+    }
+
+    let channel = |value: Value| match value {
+        Value::Number(value) => Some(to_channel(value)),
+        Value::Percentage(value) => Some(to_channel(value / 100.0 * 255.0)),
+        Value::None => Some(0),
+        Value::Angle(_) => None,
+    };
+    Some((
+        channel(values[0])?,
+        channel(values[1])?,
+        channel(values[2])?,
+        alpha_channel(alpha)?,
+    ))
+}
+
+fn hue(value: Value) -> Option<f64> {
+    match value {
+        Value::Number(degrees) | Value::Angle(degrees) => Some(degrees.rem_euclid(360.0)),
+        Value::None => Some(0.0),
+        Value::Percentage(_) => None,
+    }
+}
+
+/// Reads a saturation, lightness, whiteness or blackness as a fraction.
+fn fraction(value: Value) -> Option<f64> {
+    match value {
+        Value::Percentage(value) | Value::Number(value) => Some((value / 100.0).clamp(0.0, 1.0)),
+        Value::None => Some(0.0),
+        Value::Angle(_) => None,
+    }
+}
+
+/// Converts hue, saturation and lightness to red, green and blue from 0 to 1.
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> (f64, f64, f64) {
+    let channel = |offset: f64| {
+        let k = (offset + hue / 30.0) % 12.0;
+        let a = saturation * lightness.min(1.0 - lightness);
+        lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+    (channel(0.0), channel(8.0), channel(4.0))
+}
+
+fn parse_hsl(arguments: &str) -> Option<Rgba> {
+    let (values, alpha) = split_arguments(arguments)?;
+    let (r, g, b) = hsl_to_rgb(hue(values[0])?, fraction(values[1])?, fraction(values[2])?);
+    Some((
+        to_channel(r * 255.0),
+        to_channel(g * 255.0),
+        to_channel(b * 255.0),
+        alpha_channel(alpha)?,
+    ))
+}
+
+fn parse_hwb(arguments: &str) -> Option<Rgba> {
+    // Only the space separated syntax exists for hwb().
+    if arguments.contains(',') {
+        return None;
+    }
+    let (values, alpha) = split_arguments(arguments)?;
+    let (hue, white, black) = (hue(values[0])?, fraction(values[1])?, fraction(values[2])?);
+
+    let (r, g, b) = if white + black >= 1.0 {
+        let gray = white / (white + black);
+        (gray, gray, gray)
+    } else {
+        let (r, g, b) = hsl_to_rgb(hue, 1.0, 0.5);
+        let scale = |value: f64| value * (1.0 - white - black) + white;
+        (scale(r), scale(g), scale(b))
+    };
+    Some((
+        to_channel(r * 255.0),
+        to_channel(g * 255.0),
+        to_channel(b * 255.0),
+        alpha_channel(alpha)?,
+    ))
+}
+
+/// The css named colours, sorted by name for binary search.
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
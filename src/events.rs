@@ -0,0 +1,253 @@
+//! Events the page subscribes to with `Canvas::on`.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use js_sys::{Function, Object, Reflect};
+use wasm_bindgen::JsValue;
+
+use crate::{history::History, raster::Rect, selection::Mask, Color, CurrentMode};
+
+/// Names `Canvas::on` accepts.
+pub const EVENT_NAMES: [&str; 5] = [
+    "mode-changed",
+    "color-changed",
+    "stroke-committed",
+    "document-modified",
+    "selection-changed",
+];
+
+/// Something that happened inside the canvas, reported to the page.
+pub enum Event {
+    /// `{ mode }`, the name of the new tool.
+    ModeChanged(&'static str),
+    /// `{ color, alpha }`, the stroke colour as `#rrggbb` and its opacity
+    /// from 0 to 1.
+    ColorChanged(Color),
+    /// `{ layer, x, y, width, height }`, the area a pen, eraser or shape
+    /// stroke drew into, or covers once kept as an object on a vector
+    /// layer.
+    StrokeCommitted(u32, Rect),
+    /// `{ canUndo, canRedo }`, sent whenever the pixels or layers change.
+    DocumentModified(bool, bool),
+    /// `{ x, y, width, height, floating }` around the selection, or `null`
+    /// once nothing is selected.
+    SelectionChanged(Option<(f64, f64, f64, f64)>, bool),
+}
+
+impl Event {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::ModeChanged(_) => "mode-changed",
+            Event::ColorChanged(_) => "color-changed",
+            Event::StrokeCommitted(..) => "stroke-committed",
+            Event::DocumentModified(..) => "document-modified",
+            Event::SelectionChanged(..) => "selection-changed",
+        }
+    }
+
+    fn payload(&self) -> Result<JsValue, JsValue> {
+        let object = Object::new();
+        let set = |key: &str, value: JsValue| Reflect::set(&object, &key.into(), &value);
+        match self {
+            Event::ModeChanged(mode) => {
+                set("mode", (*mode).into())?;
+            }
+            Event::ColorChanged(color) => {
+                let (r, g, b, a) = color.value();
+                set("color", Color::Custom(r, g, b, 255).to_css().into())?;
+                set("alpha", (a as f64 / 255.0).into())?;
+            }
+            Event::StrokeCommitted(layer, rect) => {
+                set("layer", (*layer).into())?;
+                set("x", rect.x.into())?;
+                set("y", rect.y.into())?;
+                set("width", rect.width.into())?;
+                set("height", rect.height.into())?;
+            }
+            Event::DocumentModified(can_undo, can_redo) => {
+                set("canUndo", (*can_undo).into())?;
+                set("canRedo", (*can_redo).into())?;
+            }
+            Event::SelectionChanged(None, _) => return Ok(JsValue::NULL),
+            Event::SelectionChanged(Some((x, y, width, height)), floating) => {
+                set("x", (*x).into())?;
+                set("y", (*y).into())?;
+                set("width", (*width).into())?;
+                set("height", (*height).into())?;
+                set("floating", (*floating).into())?;
+            }
+        }
+
+        Ok(object.into())
+    }
+}
+
+/// What the page was last told about, to only report changes.
+#[derive(Default)]
+struct Seen {
+    mode: Option<&'static str>,
+    color: Option<Color>,
+    revision: u64,
+    selection: Option<Mask>,
+    floating: Option<[(f64, f64); 4]>,
+}
+
+#[derive(Default)]
+struct Listeners {
+    callbacks: HashMap<&'static str, Vec<Function>>,
+    seen: Seen,
+}
+
+/// Subscriptions to canvas events, along with the state they report on.
+#[derive(Clone)]
+pub struct Notifier {
+    listeners: Rc<RefCell<Listeners>>,
+    mode: Rc<RefCell<CurrentMode>>,
+    selection: Rc<RefCell<Option<Mask>>>,
+    history: Rc<RefCell<History>>,
+    color: Rc<RefCell<Color>>,
+}
+
+impl Notifier {
+    pub fn new(
+        mode: &Rc<RefCell<CurrentMode>>,
+        selection: &Rc<RefCell<Option<Mask>>>,
+        history: &Rc<RefCell<History>>,
+        color: &Rc<RefCell<Color>>,
+    ) -> Notifier {
+        let notifier = Notifier {
+            listeners: Rc::new(RefCell::new(Listeners::default())),
+            mode: mode.clone(),
+            selection: selection.clone(),
+            history: history.clone(),
+            color: color.clone(),
+        };
+        // Nobody needs telling about the state the canvas starts in.
+        notifier.changes();
+        notifier
+    }
+
+    pub fn subscribe(&self, name: &str, callback: Function) -> Result<(), JsValue> {
+        let name = EVENT_NAMES
+            .iter()
+            .find(|known| **known == name)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown event {}", name)))?;
+        self.listeners
+            .borrow_mut()
+            .callbacks
+            .entry(name)
+            .or_default()
+            .push(callback);
+        Ok(())
+    }
+
+    pub fn unsubscribe(&self, name: &str, callback: &Function) {
+        if let Some(callbacks) = self.listeners.borrow_mut().callbacks.get_mut(name) {
+            callbacks.retain(|known| known != callback);
+        }
+    }
+
+    /// Drops every subscription.
+    pub fn clear(&self) {
+        self.listeners.borrow_mut().callbacks.clear();
+    }
+
+    /// Calls the listeners of `event`. Must not be called while the canvas
+    /// state is borrowed, as listeners may call back into the canvas.
+    pub fn emit(&self, event: Event) {
+        let callbacks = match self.listeners.borrow().callbacks.get(event.name()) {
+            Some(callbacks) if !callbacks.is_empty() => callbacks.clone(),
+            _ => return,
+        };
+        if let Ok(payload) = event.payload() {
+            for callback in callbacks {
+                let _ = callback.call1(&JsValue::NULL, &payload);
+            }
+        }
+    }
+
+    /// Reports the tool, colour, document and selection changes made since
+    /// the last call.
+    pub fn sync(&self) {
+        for event in self.changes() {
+            self.emit(event);
+        }
+    }
+
+    fn changes(&self) -> Vec<Event> {
+        let mut listeners = self.listeners.borrow_mut();
+        let seen = &mut listeners.seen;
+        let mut events = vec![];
+
+        let mode = self.mode.borrow();
+        let name = mode.name();
+        if seen.mode != Some(name) {
+            seen.mode = Some(name);
+            events.push(Event::ModeChanged(name));
+        }
+
+        let color = self.color.borrow();
+        if seen.color.as_ref() != Some(&*color) {
+            seen.color = Some(color.clone());
+            events.push(Event::ColorChanged(color.clone()));
+        }
+
+        let history = self.history.borrow();
+        if seen.revision != history.revision() {
+            seen.revision = history.revision();
+            events.push(Event::DocumentModified(
+                history.can_undo(),
+                history.can_redo(),
+            ));
+        }
+
+        let selection = self.selection.borrow();
+        let floating = match &*mode {
+            CurrentMode::CropPlace(_, transform, _) => Some(transform.corners()),
+            _ => None,
+        };
+        if seen.selection != *selection || seen.floating != floating {
+            seen.selection = selection.clone();
+            seen.floating = floating;
+            events.push(selection_event(&selection, floating));
+        }
+
+        events
+    }
+}
+
+fn selection_event(selection: &Option<Mask>, floating: Option<[(f64, f64); 4]>) -> Event {
+    if let Some(corners) = floating {
+        let min_x = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+        let min_y = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+        let max_x = corners
+            .iter()
+            .map(|c| c.0)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let max_y = corners
+            .iter()
+            .map(|c| c.1)
+            .fold(f64::NEG_INFINITY, f64::max);
+        return Event::SelectionChanged(Some((min_x, min_y, max_x - min_x, max_y - min_y)), true);
+    }
+
+    let bounds = selection.as_ref().map(|mask| {
+        let rect = mask.rect;
+        (
+            rect.x as f64,
+            rect.y as f64,
+            rect.width as f64,
+            rect.height as f64,
+        )
+    });
+    Event::SelectionChanged(bounds, false)
+}
+
+/// Wraps an event handler to report what it changed once it returns.
+pub fn notifying<E>(notifier: &Notifier, mut handler: impl FnMut(E)) -> impl FnMut(E) {
+    let notifier = notifier.clone();
+    move |event| {
+        handler(event);
+        notifier.sync();
+    }
+}
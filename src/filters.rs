@@ -0,0 +1,190 @@
+//! Image adjustments working in place on RGBA buffers laid out like
+//! `ImageData`, four bytes per pixel, row by row.
+
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    /// Blur with a standard deviation of `radius` pixels, like the css
+    /// `blur()` filter.
+    GaussianBlur {
+        radius: f64,
+    },
+    /// Adds `amount` times the difference from a blurred copy, ignoring
+    /// differences not above `threshold`.
+    UnsharpMask {
+        radius: f64,
+        amount: f64,
+        threshold: f64,
+    },
+    Grayscale,
+    Sepia,
+    Invert,
+    /// Both range from -1 to 1, 0 leaving the image unchanged.
+    BrightnessContrast {
+        brightness: f64,
+        contrast: f64,
+    },
+}
+
+impl Filter {
+    /// Builds a filter from its name, looking its parameters up with `param`
+    /// and using defaults for missing ones.
+    pub fn from_name(name: &str, param: impl Fn(&str) -> Option<f64>) -> Option<Filter> {
+        let get = |key: &str, default: f64| param(key).unwrap_or(default);
+
+        match name {
+            "blur" => Some(Filter::GaussianBlur {
+                radius: get("radius", 2.0).max(0.0),
+            }),
+            "sharpen" => Some(Filter::UnsharpMask {
+                radius: get("radius", 1.0).max(0.0),
+                amount: get("amount", 1.0),
+                threshold: get("threshold", 0.0).max(0.0),
+            }),
+            "grayscale" => Some(Filter::Grayscale),
+            "sepia" => Some(Filter::Sepia),
+            "invert" => Some(Filter::Invert),
+            "brightness-contrast" => Some(Filter::BrightnessContrast {
+                brightness: get("brightness", 0.0).clamp(-1.0, 1.0),
+                contrast: get("contrast", 0.0).clamp(-1.0, 1.0),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, data: &mut [u8], width: u32, height: u32) {
+        match *self {
+            Filter::GaussianBlur { radius } => {
+                let blurred = gaussian_blur(data, width as usize, height as usize, radius);
+                data.copy_from_slice(&blurred);
+            }
+            Filter::UnsharpMask {
+                radius,
+                amount,
+                threshold,
+            } => {
+                let blurred = gaussian_blur(data, width as usize, height as usize, radius);
+                for (pixel, soft) in data.chunks_exact_mut(4).zip(blurred.chunks_exact(4)) {
+                    for channel in 0..3 {
+                        let diff = pixel[channel] as f64 - soft[channel] as f64;
+                        if diff.abs() > threshold {
+                            pixel[channel] = clamp_channel(pixel[channel] as f64 + amount * diff);
+                        }
+                    }
+                }
+            }
+            Filter::Grayscale => map_rgb(data, |r, g, b| {
+                let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+                (luma, luma, luma)
+            }),
+            Filter::Sepia => map_rgb(data, |r, g, b| {
+                (
+                    0.393 * r + 0.769 * g + 0.189 * b,
+                    0.349 * r + 0.686 * g + 0.168 * b,
+                    0.272 * r + 0.534 * g + 0.131 * b,
+                )
+            }),
+            Filter::Invert => map_rgb(data, |r, g, b| (255.0 - r, 255.0 - g, 255.0 - b)),
+            Filter::BrightnessContrast {
+                brightness,
+                contrast,
+            } => {
+                // Contrast turns into the slope of the curve through mid gray,
+                // from flat at -1 to a hard threshold at 1.
+                let slope = ((contrast + 1.0) * PI / 4.0).tan();
+                let adjust = |value: f64| (value - 127.5) * slope + 127.5 + brightness * 255.0;
+                map_rgb(data, |r, g, b| (adjust(r), adjust(g), adjust(b)))
+            }
+        }
+    }
+}
+
+fn clamp_channel(value: f64) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+fn map_rgb(data: &mut [u8], map: impl Fn(f64, f64, f64) -> (f64, f64, f64)) {
+    for pixel in data.chunks_exact_mut(4) {
+        let (r, g, b) = map(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64);
+        pixel[0] = clamp_channel(r);
+        pixel[1] = clamp_channel(g);
+        pixel[2] = clamp_channel(b);
+    }
+}
+
+fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    let half = (sigma * 3.0).ceil() as i64;
+    let weights: Vec<f64> = (-half..=half)
+        .map(|offset| (-((offset * offset) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f64 = weights.iter().sum();
+
+    weights.iter().map(|weight| weight / total).collect()
+}
+
+/// Blurs on premultiplied colour so transparent pixels don't bleed their
+/// hidden colour into their neighbours. Edges repeat the border pixels.
+fn gaussian_blur(data: &[u8], width: usize, height: usize, sigma: f64) -> Vec<u8> {
+    if sigma <= 0.0 || width == 0 || height == 0 {
+        return data.to_vec();
+    }
+
+    let kernel = gaussian_kernel(sigma);
+    let half = (kernel.len() / 2) as i64;
+
+    let premultiplied: Vec<f64> = data
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let alpha = pixel[3] as f64 / 255.0;
+            vec![
+                pixel[0] as f64 * alpha,
+                pixel[1] as f64 * alpha,
+                pixel[2] as f64 * alpha,
+                pixel[3] as f64,
+            ]
+        })
+        .collect();
+
+    let pass = |source: &[f64], horizontal: bool| -> Vec<f64> {
+        let mut target = vec![0.0; source.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0.0; 4];
+                for (tap, weight) in kernel.iter().enumerate() {
+                    let offset = tap as i64 - half;
+                    let (sx, sy) = if horizontal {
+                        ((x as i64 + offset).clamp(0, width as i64 - 1) as usize, y)
+                    } else {
+                        (x, (y as i64 + offset).clamp(0, height as i64 - 1) as usize)
+                    };
+                    let index = (sy * width + sx) * 4;
+                    for channel in 0..4 {
+                        sum[channel] += source[index + channel] * weight;
+                    }
+                }
+                let index = (y * width + x) * 4;
+                target[index..index + 4].copy_from_slice(&sum);
+            }
+        }
+        target
+    };
+
+    let blurred = pass(&pass(&premultiplied, true), false);
+
+    blurred
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let alpha = pixel[3] / 255.0;
+            if alpha <= 0.0 {
+                return vec![0, 0, 0, 0];
+            }
+            vec![
+                clamp_channel(pixel[0] / alpha),
+                clamp_channel(pixel[1] / alpha),
+                clamp_channel(pixel[2] / alpha),
+                clamp_channel(pixel[3]),
+            ]
+        })
+        .collect()
+}
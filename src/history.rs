@@ -0,0 +1,315 @@
+use std::{collections::VecDeque, mem};
+
+use wasm_bindgen::JsValue;
+
+use crate::{
+    project::LayerData,
+    raster::{Rect, Region, Surface},
+    shapes::{Shape, ShapeObject},
+    text::TextObject,
+};
+
+pub const DEFAULT_HISTORY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// What undoing or redoing puts back on a layer.
+#[derive(Clone, Debug)]
+pub enum Change {
+    /// Pixels of part of the layer.
+    Pixels(Region),
+    /// Pixels of part of the layer a stroke or shape got painted over,
+    /// with it, for exports to write it as geometry.
+    Drawn(Region, ShapeObject),
+    /// Every shape object of the layer.
+    Shapes(Vec<ShapeObject>),
+    /// Every text object of the layer.
+    Texts(Vec<TextObject>),
+    /// The whole layer at its index in the stack, or `None` for no layer,
+    /// to undo adding, removing or merging layers.
+    Layer(Option<(usize, LayerData)>),
+}
+
+impl Change {
+    fn size(&self) -> usize {
+        match self {
+            Change::Pixels(region) => region.size(),
+            Change::Drawn(region, shape) => {
+                region.size() + shapes_size(std::slice::from_ref(shape))
+            }
+            Change::Shapes(shapes) => shapes_size(shapes),
+            Change::Texts(texts) => texts_size(texts),
+            Change::Layer(layer) => layer.as_ref().map_or(0, |(_, data)| {
+                data.pixels.len() + shapes_size(&data.shapes) + texts_size(&data.texts)
+            }),
+        }
+    }
+}
+
+fn shapes_size(shapes: &[ShapeObject]) -> usize {
+    shapes
+        .iter()
+        .map(|shape| {
+            mem::size_of::<ShapeObject>()
+                + match &shape.shape {
+                    Shape::Pen { segments, .. } => mem::size_of_val(&segments[..]),
+                    _ => 0,
+                }
+        })
+        .sum()
+}
+
+fn texts_size(texts: &[TextObject]) -> usize {
+    texts
+        .iter()
+        .map(|text| mem::size_of::<TextObject>() + text.content.len())
+        .sum()
+}
+
+/// Changes undone or redone in one step, each with the id of its layer,
+/// in the order they were made.
+pub type Entry = Vec<(u32, Change)>;
+
+struct Pending {
+    layer: u32,
+    /// What the layer held under everything touched so far, read just
+    /// before it got drawn over.
+    snapshot: Region,
+}
+
+/// Undo/redo stacks of what layers held before each committed operation,
+/// bounded by a memory budget in bytes.
+pub struct History {
+    undo: VecDeque<Entry>,
+    redo: Vec<Entry>,
+    pending: Option<Pending>,
+    limit: usize,
+    used: usize,
+    revision: u64,
+}
+
+impl History {
+    pub fn new(limit: usize) -> History {
+        History {
+            undo: VecDeque::new(),
+            redo: vec![],
+            pending: None,
+            limit,
+            used: 0,
+            revision: 0,
+        }
+    }
+
+    /// Starts an operation whose affected area is only known once it ends,
+    /// like a pen stroke. See `touch`.
+    pub fn begin(&mut self, layer: u32) {
+        self.pending = Some(Pending {
+            layer,
+            snapshot: Region {
+                rect: Rect::new(0, 0, 0, 0),
+                data: vec![],
+            },
+        });
+    }
+
+    /// Adds `rect` to the area of the pending operation, reading what
+    /// `surface` holds there that wasn't read yet. Must come before drawing
+    /// into `rect`.
+    pub fn touch<S: Surface>(&mut self, surface: &S, rect: Rect) -> Result<(), JsValue> {
+        let pending = match &mut self.pending {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        let covered = pending.snapshot.rect;
+        let bounds = covered.union(&rect);
+        if bounds == covered {
+            return Ok(());
+        }
+
+        let mut grown = Region {
+            rect: bounds,
+            data: vec![0; (bounds.width * bounds.height * 4) as usize],
+        };
+        for strip in uncovered(&bounds, &covered) {
+            grown.paste(&surface.read(&strip)?);
+        }
+        grown.paste(&pending.snapshot);
+        pending.snapshot = grown;
+        Ok(())
+    }
+
+    /// Finishes the pending operation, recording what its area held.
+    /// Returns that area, if anything was touched.
+    pub fn commit(&mut self) -> Option<Rect> {
+        self.finish(None)
+    }
+
+    /// Like `commit`, for a stroke painted as `shape`.
+    pub fn commit_drawn(&mut self, shape: ShapeObject) -> Option<Rect> {
+        self.finish(Some(shape))
+    }
+
+    /// Abandons the pending operation, returning what the touched part of
+    /// its layer held before so the caller can put it back.
+    pub fn cancel(&mut self) -> Option<(u32, Region)> {
+        let pending = self.pending.take()?;
+        if pending.snapshot.rect.is_empty() {
+            return None;
+        }
+        Some((pending.layer, pending.snapshot))
+    }
+
+    /// Pushes the content of a region before it gets modified. Any redo
+    /// history is discarded.
+    pub fn record(&mut self, layer: u32, region: Region) {
+        self.record_entry(vec![(layer, Change::Pixels(region))]);
+    }
+
+    /// Like `record`, for a stroke or shape about to be painted as `shape`.
+    pub fn record_drawn(&mut self, layer: u32, region: Region, shape: ShapeObject) {
+        self.record_entry(vec![(layer, Change::Drawn(region, shape))]);
+    }
+
+    /// Pushes what an operation is about to change. Any redo history is
+    /// discarded.
+    pub fn record_entry(&mut self, entry: Entry) {
+        for entry in self.redo.drain(..) {
+            self.used -= entry_size(&entry);
+        }
+        self.push_undo(entry);
+    }
+
+    pub fn take_undo(&mut self) -> Option<Entry> {
+        let entry = self.undo.pop_back()?;
+        self.used -= entry_size(&entry);
+        Some(entry)
+    }
+
+    pub fn take_redo(&mut self) -> Option<Entry> {
+        let entry = self.redo.pop()?;
+        self.used -= entry_size(&entry);
+        Some(entry)
+    }
+
+    pub fn push_undo(&mut self, entry: Entry) {
+        self.revision += 1;
+        self.used += entry_size(&entry);
+        self.undo.push_back(entry);
+        self.enforce_limit();
+    }
+
+    pub fn push_redo(&mut self, entry: Entry) {
+        self.revision += 1;
+        self.used += entry_size(&entry);
+        self.redo.push(entry);
+        self.enforce_limit();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Counts the changes recorded, undone and redone, and documents
+    /// replaced, to tell whether anything happened since an earlier call.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Strokes and shapes painted into the pixels of `layer` since anything
+    /// else last changed them, newest first, each with what the pixels held
+    /// under it. Those whose entries got dropped for the limit are left
+    /// out, along with any before them.
+    pub fn drawn(&self, layer: u32) -> Vec<(&Region, &ShapeObject)> {
+        let mut drawn = vec![];
+        let changes = self.undo.iter().rev().flat_map(|entry| entry.iter().rev());
+        for (_, change) in changes.filter(|(id, _)| *id == layer) {
+            match change {
+                Change::Drawn(region, shape) => drawn.push((region, shape)),
+                Change::Pixels(_) | Change::Layer(_) => break,
+                Change::Shapes(_) | Change::Texts(_) => {}
+            }
+        }
+        drawn
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Drops every entry, e.g. when another document is loaded.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.pending = None;
+        self.used = 0;
+        self.revision += 1;
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.enforce_limit();
+    }
+
+    fn finish(&mut self, shape: Option<ShapeObject>) -> Option<Rect> {
+        let pending = self.pending.take()?;
+        let rect = pending.snapshot.rect;
+        if rect.is_empty() {
+            return None;
+        }
+        let change = match shape {
+            Some(shape) => Change::Drawn(pending.snapshot, shape),
+            None => Change::Pixels(pending.snapshot),
+        };
+        self.record_entry(vec![(pending.layer, change)]);
+        Some(rect)
+    }
+
+    fn enforce_limit(&mut self) {
+        while self.used > self.limit {
+            if let Some(entry) = self.undo.pop_front() {
+                self.used -= entry_size(&entry);
+            } else if !self.redo.is_empty() {
+                let entry = self.redo.remove(0);
+                self.used -= entry_size(&entry);
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+fn entry_size(entry: &Entry) -> usize {
+    entry.iter().map(|(_, change)| change.size()).sum()
+}
+
+/// Parts of `bounds` outside `inner`, which it contains: full width strips
+/// above and below, and what is left on either side.
+fn uncovered(bounds: &Rect, inner: &Rect) -> Vec<Rect> {
+    if inner.is_empty() {
+        return vec![*bounds];
+    }
+    let right = inner.x + inner.width;
+    let bottom = inner.y + inner.height;
+    [
+        Rect::new(bounds.x, bounds.y, bounds.width, inner.y - bounds.y),
+        Rect::new(
+            bounds.x,
+            bottom,
+            bounds.width,
+            bounds.y + bounds.height - bottom,
+        ),
+        Rect::new(bounds.x, inner.y, inner.x - bounds.x, inner.height),
+        Rect::new(
+            right,
+            inner.y,
+            bounds.x + bounds.width - right,
+            inner.height,
+        ),
+    ]
+    .iter()
+    .copied()
+    .filter(|strip| !strip.is_empty())
+    .collect()
+}
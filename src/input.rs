@@ -0,0 +1,1151 @@
+//! Pointer, keyboard and wheel handlers of the editor, over the state
+//! they share.
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use js_sys::Array;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{
+    CanvasRenderingContext2d, CompositionEvent, HtmlCanvasElement, HtmlTextAreaElement, InputEvent,
+    KeyboardEvent, PointerEvent, WheelEvent,
+};
+
+use crate::{
+    events::{Event, Notifier},
+    history::History,
+    layers::{LayerStack, Stroke, StrokeKind},
+    raster::{stroke_line, Rect, Surface},
+    selection::{Combine, Mask},
+    shapes::{Shape, ShapeSettings},
+    text::{self, TextEdit, TextObject},
+    transform::HANDLE_SIZE,
+    utils::{
+        define_distance, define_postition, fill, region_mask, two_point_distance, FillOptions,
+    },
+    viewport::Viewport,
+};
+
+use super::{
+    apply_stroke_color, combine_modifier, delete_picked_shape, draw_overlay, drop_text,
+    edit_picked_shape, keep_shape, lift_selection, pen_dynamics, pen_padding, pick_color,
+    place_text_input, record_drawn, record_shapes, settle_floating, settle_text, stroke_padding,
+    typed_into, view_point, Color, CurrentMode, HandleDrag, PickedShape, ShapeGrab, TextChange,
+    TextData, TextGrab, TextSession,
+};
+
+/// What the canvas and text field listeners work on.
+pub struct Input {
+    pub mode: Rc<RefCell<CurrentMode>>,
+    pub color: Rc<RefCell<Color>>,
+    pub history: Rc<RefCell<History>>,
+    pub layers: Rc<RefCell<LayerStack>>,
+    pub viewport: Rc<Cell<Viewport>>,
+    pub fill_options: Rc<Cell<FillOptions>>,
+    pub shape_settings: Rc<RefCell<ShapeSettings>>,
+    pub selection: Rc<RefCell<Option<Mask>>>,
+    pub picker_size: Rc<Cell<u32>>,
+    pub notifier: Notifier,
+    pub top_layer: HtmlCanvasElement,
+    pub text_input: HtmlTextAreaElement,
+    /// Context of the display canvas the layers are composited onto.
+    pub display: CanvasRenderingContext2d,
+    /// Context of the overlay drawn over the display, in view coordinates.
+    pub top_context: CanvasRenderingContext2d,
+    /// Whether the press in progress drags something.
+    pub pressed: Cell<bool>,
+    /// Pointer pressing the canvas, others being ignored until it lifts.
+    pub active_pointer: Cell<Option<i32>>,
+    pub space_held: Cell<bool>,
+    /// View point the pan in progress last moved to.
+    pub panning: Cell<Option<(f64, f64)>>,
+    /// Handle grabbed on the floating selection, with the transform and
+    /// document point the drag started from.
+    pub dragging: Cell<Option<HandleDrag>>,
+    pub moving_text: Cell<Option<TextGrab>>,
+    pub moving_shape: RefCell<Option<ShapeGrab>>,
+    /// Document point the press started from, or the stroke last reached.
+    pub line_start_x: Cell<f64>,
+    pub line_start_y: Cell<f64>,
+}
+
+impl Input {
+    /// Starts a press with the current tool, or a pan.
+    pub fn pointer_down(&self, event: PointerEvent) {
+        let Input {
+            mode,
+            color,
+            history,
+            layers,
+            viewport,
+            fill_options,
+            shape_settings,
+            selection,
+            picker_size,
+            top_layer,
+            display,
+            top_context,
+            pressed,
+            active_pointer,
+            space_held,
+            panning,
+            dragging,
+            moving_text,
+            moving_shape,
+            line_start_x,
+            line_start_y,
+            ..
+        } = self;
+
+        // Another finger or pen touching down mid-stroke is ignored.
+        if active_pointer.get().is_some() {
+            return;
+        }
+        active_pointer.set(Some(event.pointer_id()));
+        let _ = top_layer.set_pointer_capture(event.pointer_id());
+
+        // Space-drag and middle-button drag pan the view whatever the tool.
+        if space_held.get() || event.button() == 1 {
+            panning.set(Some((event.offset_x() as f64, event.offset_y() as f64)));
+            return;
+        }
+
+        let (offset_x, offset_y) = viewport
+            .get()
+            .to_document(event.offset_x() as f64, event.offset_y() as f64);
+        let mut mode = mode.borrow_mut();
+        if let CurrentMode::Text(value) = &mut *mode {
+            let point = (offset_x, offset_y);
+            let reach = HANDLE_SIZE / viewport.get().zoom;
+            if value.contains(top_context, point) {
+                // Clicking the text moves the caret, dragging or
+                // shift-clicking selects.
+                let located = value.locate(top_context, point);
+                if let (Some((line, x)), Some(session)) = (located, &mut value.session) {
+                    let measure = text::measure(top_context);
+                    session
+                        .edit
+                        .place_caret(line, x, event.shift_key(), measure);
+                }
+                pressed.set(true);
+            } else if let (true, Some(session)) =
+                (value.on_frame(top_context, point, reach), &value.session)
+            {
+                // Dragging its frame moves the text.
+                moving_text.set(Some((point, session.object.position)));
+                pressed.set(true);
+            } else {
+                // Clicking elsewhere commits it, then opens the
+                // text clicked or starts a new one.
+                let mut layers = layers.borrow_mut();
+                let layer = layers.active_id();
+                let style = value.style.clone();
+                settle_text(&mut mode, &mut layers, history);
+                let session = match layers.take_text_at(layer, point) {
+                    Some((index, object)) => TextSession::reopen(layer, index, object),
+                    None => TextSession {
+                        edit: TextEdit::new(),
+                        object: TextObject {
+                            content: String::new(),
+                            position: point,
+                            style,
+                            color: color.borrow().to_css(),
+                        },
+                        layer,
+                        original: None,
+                    },
+                };
+                if let CurrentMode::Text(value) = &mut *mode {
+                    value.session = Some(Box::new(session));
+                    if let (Some((line, x)), Some(session)) =
+                        (value.locate(top_context, point), &mut value.session)
+                    {
+                        let measure = text::measure(top_context);
+                        session.edit.place_caret(line, x, false, measure);
+                    }
+                }
+                let _ = layers.composite(display, &viewport.get());
+            }
+            draw_overlay(&mode, &selection.borrow(), top_context, &viewport.get());
+            return;
+        }
+
+        if let CurrentMode::ObjectSelect(picked) = &mut *mode {
+            let point = (offset_x, offset_y);
+            let reach = HANDLE_SIZE / viewport.get().zoom;
+            let layers = layers.borrow();
+            let layer = layers.active_id();
+            *picked = layers
+                .shape_at(layer, point, reach)
+                .and_then(|index| PickedShape::new(&layers, layer, index));
+            if let Some(picked) = picked {
+                if let Some(shape) = layers.shape(picked.layer, picked.index) {
+                    *moving_shape.borrow_mut() = Some((point, shape.shape.clone()));
+                    pressed.set(true);
+                }
+            }
+            draw_overlay(&mode, &selection.borrow(), top_context, &viewport.get());
+            return;
+        }
+
+        let mut layers = layers.borrow_mut();
+        let (width, height) = layers.size();
+        let mut context = layers.active_context();
+        let layer = layers.active_id();
+
+        if let CurrentMode::CropPlace(_, transform, _) = &*mode {
+            let point = (offset_x, offset_y);
+            if let Some(handle) = transform.hit(point, viewport.get().zoom) {
+                dragging.set(Some((handle, *transform, point)));
+                pressed.set(true);
+            } else {
+                // Clicking away from the selection places it.
+                settle_floating(&mut mode, &layers, history, selection);
+                draw_overlay(&mode, &selection.borrow(), top_context, &viewport.get());
+                let _ = layers.composite(display, &viewport.get());
+            }
+            return;
+        }
+
+        match &mut *mode {
+            tool @ (CurrentMode::Default | CurrentMode::Eraser) => {
+                let mut object = shape_settings.borrow().object(
+                    Shape::Pen {
+                        start: (offset_x, offset_y),
+                        segments: vec![],
+                    },
+                    &color.borrow(),
+                    context.line_width(),
+                );
+                // Vector layers keep pen strokes as shapes.
+                let kind = match tool {
+                    CurrentMode::Eraser if layers.erases_to_white(layer) => {
+                        object.stroke = "white".to_owned();
+                        StrokeKind::Paint
+                    }
+                    CurrentMode::Eraser => {
+                        object.stroke = "black".to_owned();
+                        StrokeKind::Erase
+                    }
+                    _ if layers.is_vector(layer) => StrokeKind::Shape,
+                    _ => StrokeKind::Paint,
+                };
+                if kind != StrokeKind::Shape {
+                    // Only what the stroke goes over gets read, as it
+                    // goes, see the move handler.
+                    history.borrow_mut().begin(layer);
+                }
+                layers.begin_stroke(Stroke {
+                    layer,
+                    object,
+                    kind,
+                });
+                line_start_x.set(offset_x);
+                line_start_y.set(offset_y);
+                pressed.set(true);
+            }
+            CurrentMode::StraightLine
+            | CurrentMode::Circle
+            | CurrentMode::Rectangle
+            | CurrentMode::RoundedRectangle
+            | CurrentMode::Ellipse => {
+                line_start_x.set(offset_x);
+                line_start_y.set(offset_y);
+                pressed.set(true);
+            }
+            CurrentMode::Fill if viewport.get().contains(offset_x, offset_y) => {
+                if let Ok(Some(before)) = fill(
+                    &mut context,
+                    offset_x as usize,
+                    offset_y as usize,
+                    width,
+                    height,
+                    &color.borrow(),
+                    &fill_options.get(),
+                ) {
+                    history.borrow_mut().record(layer, before);
+                    let _ = layers.composite(display, &viewport.get());
+                }
+            }
+            CurrentMode::ColorPicker if viewport.get().contains(offset_x, offset_y) => {
+                let picked =
+                    pick_color(&layers, offset_x as u32, offset_y as u32, picker_size.get());
+                if let Some((r, g, b, _)) = picked {
+                    let alpha = color.borrow().value().3;
+                    let picked = Color::Custom(r, g, b, alpha);
+                    let css = picked.to_css();
+                    apply_stroke_color(&layers, top_context, color, &css, picked);
+                }
+            }
+            CurrentMode::Crop => {
+                top_context.set_line_width(1.0);
+                top_context.set_stroke_style_str("black");
+                let lines: Array = vec![6].into_iter().map(JsValue::from).collect();
+                let _ = top_context.set_line_dash(&lines);
+                line_start_x.set(offset_x);
+                line_start_y.set(offset_y);
+                top_context.begin_path();
+                pressed.set(true);
+            }
+            CurrentMode::Lasso(points) => {
+                *points = vec![(offset_x, offset_y)];
+                pressed.set(true);
+            }
+            CurrentMode::Wand if viewport.get().contains(offset_x, offset_y) => {
+                if let Ok(image) = context.read(&Rect::new(0, 0, width, height)) {
+                    let coverage = region_mask(
+                        &image.data,
+                        width,
+                        height,
+                        offset_x as u32,
+                        offset_y as u32,
+                        &fill_options.get(),
+                    );
+                    let picked = Mask::from_coverage(coverage, width, height);
+
+                    let mut selection = selection.borrow_mut();
+                    let combined = match (selection.take(), combine_modifier(&event)) {
+                        (_, None) | (None, Some(Combine::Add)) => Some(picked),
+                        (None, Some(_)) => None,
+                        (Some(current), Some(how)) => Some(current.combine(&picked, how)),
+                    };
+                    *selection = combined.filter(|mask| !mask.is_empty());
+                }
+                draw_overlay(&mode, &selection.borrow(), top_context, &viewport.get());
+            }
+            CurrentMode::Polygon(points) => {
+                let point = (offset_x, offset_y);
+                let reach = HANDLE_SIZE / viewport.get().zoom;
+                let closes = points.len() > 3
+                    && two_point_distance(points[0].0, points[0].1, point.0, point.1) <= reach;
+
+                if closes {
+                    points.pop();
+                    let mask = Mask::polygon(points, width, height);
+                    lift_selection(
+                        &mut mode,
+                        &layers,
+                        history,
+                        selection,
+                        mask,
+                        event.alt_key(),
+                    );
+                    draw_overlay(&mode, &selection.borrow(), top_context, &viewport.get());
+                } else {
+                    // Fix the corner under the pointer and start the
+                    // next one there.
+                    points.pop();
+                    points.push(point);
+                    points.push(point);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Follows the pointer with the press in progress.
+    pub fn pointer_move(&self, event: PointerEvent) {
+        let Input {
+            mode,
+            color,
+            history,
+            layers,
+            viewport,
+            shape_settings,
+            selection,
+            display,
+            pressed,
+            active_pointer,
+            panning,
+            dragging,
+            moving_text,
+            moving_shape,
+            line_start_x,
+            line_start_y,
+            ..
+        } = self;
+        let mut top_context = self.top_context.clone();
+
+        // The open polygon's last corner follows the pointer.
+        if let CurrentMode::Polygon(points) = &mut *mode.borrow_mut() {
+            if let Some(last) = points.last_mut() {
+                *last = viewport
+                    .get()
+                    .to_document(event.offset_x() as f64, event.offset_y() as f64);
+            }
+        }
+        if let CurrentMode::Polygon(_) = &*mode.borrow() {
+            draw_overlay(
+                &mode.borrow(),
+                &selection.borrow(),
+                &top_context,
+                &viewport.get(),
+            );
+        }
+        // Without buttons held the press is over, see the release
+        // handler.
+        if active_pointer.get() != Some(event.pointer_id()) || event.buttons() == 0 {
+            return;
+        }
+        if let Some((last_x, last_y)) = panning.get() {
+            let (x, y) = (event.offset_x() as f64, event.offset_y() as f64);
+            let mut moved = viewport.get();
+            moved.pan_by(x - last_x, y - last_y);
+            viewport.set(moved);
+            moved.apply(&top_context);
+            draw_overlay(&mode.borrow(), &selection.borrow(), &top_context, &moved);
+            let _ = layers.borrow().composite(display, &moved);
+            panning.set(Some((x, y)));
+            return;
+        }
+        if pressed.get() {
+            let (offset_x, offset_y) = viewport
+                .get()
+                .to_document(event.offset_x() as f64, event.offset_y() as f64);
+
+            if let Some((handle, from, origin)) = dragging.get() {
+                let mut mode = mode.borrow_mut();
+                if let CurrentMode::CropPlace(_, transform, _) = &mut *mode {
+                    *transform = from.drag(handle, origin, (offset_x, offset_y), event.shift_key());
+                }
+                draw_overlay(&mode, &selection.borrow(), &top_context, &viewport.get());
+                return;
+            }
+
+            if let Some(((from_x, from_y), shape)) = &*moving_shape.borrow() {
+                let mut mode = mode.borrow_mut();
+                let mut layers = layers.borrow_mut();
+                edit_picked_shape(&mut mode, &mut layers, |picked| {
+                    picked.shape = shape.translated(offset_x - from_x, offset_y - from_y)
+                });
+                draw_overlay(&mode, &selection.borrow(), &top_context, &viewport.get());
+                let _ = layers.composite(display, &viewport.get());
+                return;
+            }
+
+            if let CurrentMode::Lasso(points) = &mut *mode.borrow_mut() {
+                let reach = 1.0 / viewport.get().zoom;
+                let far = points.last().is_none_or(|last| {
+                    two_point_distance(last.0, last.1, offset_x, offset_y) >= reach
+                });
+                if far {
+                    points.push((offset_x, offset_y));
+                }
+            }
+            if let CurrentMode::Lasso(_) = &*mode.borrow() {
+                draw_overlay(
+                    &mode.borrow(),
+                    &selection.borrow(),
+                    &top_context,
+                    &viewport.get(),
+                );
+                return;
+            }
+
+            if let CurrentMode::Text(value) = &mut *mode.borrow_mut() {
+                if let (Some(((from_x, from_y), (x, y))), Some(session)) =
+                    (moving_text.get(), &mut value.session)
+                {
+                    session.object.position = (x + offset_x - from_x, y + offset_y - from_y);
+                } else {
+                    let located = value.locate(&top_context, (offset_x, offset_y));
+                    if let (Some((line, x)), Some(session)) = (located, &mut value.session) {
+                        let measure = text::measure(&top_context);
+                        session.edit.place_caret(line, x, true, measure);
+                    }
+                }
+            }
+            if let CurrentMode::Text(_) = &*mode.borrow() {
+                draw_overlay(
+                    &mode.borrow(),
+                    &selection.borrow(),
+                    &top_context,
+                    &viewport.get(),
+                );
+                return;
+            }
+
+            let start = (line_start_x.get(), line_start_y.get());
+            let mut layers = layers.borrow_mut();
+            let (width, height) = layers.size();
+            let context = layers.active_context();
+            match &*mode.borrow() {
+                CurrentMode::Default | CurrentMode::Eraser => {
+                    // Pens report more samples than events fire, use
+                    // them all for a smoother stroke.
+                    let mut samples: Vec<web_sys::PointerEvent> = event
+                        .get_coalesced_events()
+                        .iter()
+                        .filter_map(|sample| sample.dyn_into().ok())
+                        .collect();
+                    if samples.is_empty() {
+                        samples.push(event.clone());
+                    }
+
+                    // The stroke gets painted once the pointer lifts, but
+                    // what it goes over is read before that.
+                    let mut history = history.borrow_mut();
+                    let mut from = start;
+                    for sample in samples {
+                        let to = viewport
+                            .get()
+                            .to_document(sample.offset_x() as f64, sample.offset_y() as f64);
+                        let dynamics = pen_dynamics(&sample);
+                        let _ = history.touch(
+                            &context,
+                            Rect::from_points(
+                                from,
+                                to,
+                                pen_padding(&context, dynamics),
+                                width,
+                                height,
+                            ),
+                        );
+                        if let Some(Shape::Pen { segments, .. }) =
+                            layers.stroke_mut().map(|stroke| &mut stroke.object.shape)
+                        {
+                            segments.push((to, dynamics));
+                        }
+                        from = to;
+                    }
+                    line_start_x.set(from.0);
+                    line_start_y.set(from.1);
+                    let _ = layers.composite(display, &viewport.get());
+                }
+                CurrentMode::StraightLine => {
+                    viewport.get().clear(&top_context);
+                    stroke_line(&mut top_context, start, (offset_x, offset_y));
+                }
+                CurrentMode::Crop => {
+                    viewport.get().clear(&top_context);
+
+                    let x = define_postition(start.0, offset_x);
+                    let y = define_postition(start.1, offset_y);
+                    let w = define_distance(start.0, offset_x);
+                    let h = define_distance(start.1, offset_y);
+
+                    top_context.rect(x, y, w, h);
+
+                    top_context.stroke();
+                    top_context.begin_path();
+                }
+                other => {
+                    let settings = shape_settings.borrow();
+                    if let Some(shape) =
+                        other.drag_shape(start, (offset_x, offset_y), settings.corner_radius)
+                    {
+                        viewport.get().clear(&top_context);
+                        shape.draw(
+                            &mut top_context,
+                            settings.style,
+                            &settings.fill_css(&color.borrow()),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ends the press in progress, committing what it drew.
+    pub fn release(&self, event: &PointerEvent) {
+        let Input {
+            mode,
+            color,
+            history,
+            layers,
+            viewport,
+            shape_settings,
+            selection,
+            notifier,
+            top_layer,
+            text_input,
+            display,
+            top_context,
+            pressed,
+            active_pointer,
+            panning,
+            dragging,
+            moving_text,
+            moving_shape,
+            line_start_x,
+            line_start_y,
+            ..
+        } = self;
+
+        if active_pointer.get() != Some(event.pointer_id()) {
+            return;
+        }
+        active_pointer.set(None);
+        if panning.take().is_some() {
+            return;
+        }
+
+        let was_pressed = pressed.replace(false);
+        let (view_x, view_y) = view_point(top_layer, event);
+        let (offset_x, offset_y) = viewport.get().to_document(view_x, view_y);
+        let start = (line_start_x.get(), line_start_y.get());
+        let mut layers = layers.borrow_mut();
+        let (width, height) = layers.size();
+        let mut context = layers.active_context();
+        let layer = layers.active_id();
+        let mut mode = mode.borrow_mut();
+        let mut committed = None;
+        let stroke = layers.end_stroke();
+        match &*mode {
+            CurrentMode::Default | CurrentMode::Eraser if was_pressed => {
+                if let Some(mut stroke) = stroke {
+                    let dynamics = pen_dynamics(event);
+                    if let Shape::Pen { segments, .. } = &mut stroke.object.shape {
+                        segments.push(((offset_x, offset_y), dynamics));
+                    }
+                    if stroke.kind == StrokeKind::Shape {
+                        committed = keep_shape(&mut layers, history, layer, stroke.object)
+                            .map(|rect| (layer, rect));
+                    } else {
+                        let mut history = history.borrow_mut();
+                        let _ = history.touch(
+                            &context,
+                            Rect::from_points(
+                                start,
+                                (offset_x, offset_y),
+                                pen_padding(&context, dynamics),
+                                width,
+                                height,
+                            ),
+                        );
+                        let _ = layers.paint_stroke(&stroke);
+                        // Pen strokes are kept as geometry for the SVG
+                        // export.
+                        committed = match &*mode {
+                            CurrentMode::Default => history.commit_drawn(stroke.object),
+                            _ => history.commit(),
+                        }
+                        .map(|rect| (layer, rect));
+                    }
+                }
+            }
+            // Vector layers keep the line as an object.
+            CurrentMode::StraightLine if was_pressed && layers.is_vector(layer) => {
+                let shape = Shape::Line {
+                    x1: start.0,
+                    y1: start.1,
+                    x2: offset_x,
+                    y2: offset_y,
+                };
+                let object =
+                    shape_settings
+                        .borrow()
+                        .object(shape, &color.borrow(), context.line_width());
+                committed =
+                    keep_shape(&mut layers, history, layer, object).map(|rect| (layer, rect));
+                viewport.get().clear(top_context);
+            }
+            CurrentMode::StraightLine if was_pressed => {
+                let rect = Rect::from_points(
+                    start,
+                    (offset_x, offset_y),
+                    stroke_padding(&context),
+                    width,
+                    height,
+                );
+                let object = shape_settings.borrow().object(
+                    Shape::Line {
+                        x1: start.0,
+                        y1: start.1,
+                        x2: offset_x,
+                        y2: offset_y,
+                    },
+                    &color.borrow(),
+                    context.line_width(),
+                );
+                record_drawn(history, layer, &context, &rect, object);
+
+                stroke_line(&mut context, start, (offset_x, offset_y));
+                viewport.get().clear(top_context);
+                committed = Some((layer, rect));
+            }
+            CurrentMode::Crop if was_pressed => {
+                let _ = top_context.set_line_dash(&Array::new());
+                top_context.set_line_width(context.line_width());
+                if let Some(style) = context.stroke_style().as_string() {
+                    top_context.set_stroke_style_str(&style);
+                }
+                viewport.get().clear(top_context);
+
+                let x = define_postition(start.0, offset_x);
+                let y = define_postition(start.1, offset_y);
+                let w = define_distance(start.0, offset_x);
+                let h = define_distance(start.1, offset_y);
+
+                let rect = Rect::from_points((x, y), (x + w, y + h), 0.0, width, height);
+                // Alt-drag lifts a copy and leaves the original.
+                lift_selection(
+                    &mut mode,
+                    &layers,
+                    history,
+                    selection,
+                    Mask::full(rect),
+                    event.alt_key(),
+                );
+                draw_overlay(&mode, &selection.borrow(), top_context, &viewport.get());
+            }
+            CurrentMode::Lasso(points) if was_pressed => {
+                let mask = Mask::polygon(points, width, height);
+                lift_selection(
+                    &mut mode,
+                    &layers,
+                    history,
+                    selection,
+                    mask,
+                    event.alt_key(),
+                );
+                viewport.get().clear(top_context);
+                draw_overlay(&mode, &selection.borrow(), top_context, &viewport.get());
+            }
+            CurrentMode::CropPlace(..) => dragging.set(None),
+            CurrentMode::ObjectSelect(picked) => {
+                // Recorded as the shapes were before the drag.
+                let before = moving_shape
+                    .take()
+                    .zip(*picked)
+                    .and_then(|((_, from), picked)| {
+                        let mut shapes = layers.shapes(picked.layer)?.to_vec();
+                        shapes.get_mut(picked.index)?.shape = from;
+                        Some((picked.layer, shapes))
+                    });
+                if let Some((layer, before)) = before {
+                    record_shapes(&layers, history, layer, before);
+                }
+            }
+            // Focused once the press is over, as the press itself
+            // focuses the canvas.
+            CurrentMode::Text(_) => {
+                moving_text.set(None);
+                place_text_input(text_input, &mode, top_context, &viewport.get());
+                let _ = text_input.focus();
+            }
+            other if was_pressed => {
+                let settings = shape_settings.borrow();
+                let shape = other.drag_shape(start, (offset_x, offset_y), settings.corner_radius);
+                if let (Some(shape), true) = (&shape, layers.is_vector(layer)) {
+                    let object =
+                        settings.object(shape.clone(), &color.borrow(), context.line_width());
+                    committed =
+                        keep_shape(&mut layers, history, layer, object).map(|rect| (layer, rect));
+                    viewport.get().clear(top_context);
+                } else if let Some(shape) = shape {
+                    let (start, end) = shape.bounds();
+                    let rect =
+                        Rect::from_points(start, end, stroke_padding(&context), width, height);
+                    let object =
+                        settings.object(shape.clone(), &color.borrow(), context.line_width());
+                    record_drawn(history, layer, &context, &rect, object);
+
+                    shape.draw(
+                        &mut context,
+                        settings.style,
+                        &settings.fill_css(&color.borrow()),
+                    );
+                    viewport.get().clear(top_context);
+                    committed = Some((layer, rect));
+                }
+            }
+            _ => (),
+        }
+        let _ = layers.composite(display, &viewport.get());
+
+        drop(mode);
+        drop(layers);
+        if let Some((layer, rect)) = committed {
+            notifier.emit(Event::StrokeCommitted(layer, rect));
+        }
+    }
+
+    /// Drops the press in progress when the browser takes the pointer
+    /// away or Escape is pressed: strokes are undone, previews cleared
+    /// and dragged selections put back.
+    pub fn cancel(&self) {
+        let Input {
+            mode,
+            history,
+            layers,
+            viewport,
+            selection,
+            display,
+            top_context,
+            pressed,
+            active_pointer,
+            panning,
+            dragging,
+            moving_text,
+            moving_shape,
+            ..
+        } = self;
+
+        if active_pointer.take().is_none() {
+            return;
+        }
+        pressed.set(false);
+        panning.set(None);
+
+        let mut layers = layers.borrow_mut();
+        let context = layers.active_context();
+        let mut mode = mode.borrow_mut();
+        match &mut *mode {
+            CurrentMode::Default | CurrentMode::Eraser => {
+                layers.end_stroke();
+                if let Some((layer, region)) = history.borrow_mut().cancel() {
+                    if let Some(mut context) = layers.context(layer) {
+                        let _ = context.write(&region);
+                    }
+                }
+            }
+            CurrentMode::Crop => {
+                let _ = top_context.set_line_dash(&Array::new());
+                top_context.set_line_width(context.line_width());
+                if let Some(style) = context.stroke_style().as_string() {
+                    top_context.set_stroke_style_str(&style);
+                }
+            }
+            CurrentMode::Lasso(points) => points.clear(),
+            CurrentMode::CropPlace(_, transform, _) => {
+                if let Some((_, start, _)) = dragging.take() {
+                    *transform = start;
+                }
+            }
+            CurrentMode::Text(value) => {
+                if let (Some((_, start)), Some(session)) = (moving_text.take(), &mut value.session)
+                {
+                    session.object.position = start;
+                }
+            }
+            CurrentMode::ObjectSelect(_) => {
+                if let Some((_, start)) = moving_shape.take() {
+                    edit_picked_shape(&mut mode, &mut layers, |picked| picked.shape = start);
+                }
+            }
+            _ => (),
+        }
+
+        viewport.get().clear(top_context);
+        draw_overlay(&mode, &selection.borrow(), top_context, &viewport.get());
+        let _ = layers.composite(display, &viewport.get());
+    }
+
+    /// Handles the tool keys pressed over the canvas.
+    pub fn key_down(&self, event: KeyboardEvent) {
+        let Input {
+            mode,
+            history,
+            layers,
+            viewport,
+            selection,
+            display,
+            top_context,
+            active_pointer,
+            ..
+        } = self;
+
+        if event.ctrl_key() || event.meta_key() {
+            return;
+        }
+        if event.key() == "Escape" && active_pointer.get().is_some() {
+            self.cancel();
+            event.prevent_default();
+            return;
+        }
+
+        if let "Delete" | "Backspace" = event.key().as_str() {
+            let mut mode = mode.borrow_mut();
+            let mut layers = layers.borrow_mut();
+            if delete_picked_shape(&mut mode, &mut layers, history).is_some() {
+                event.prevent_default();
+                draw_overlay(&mode, &selection.borrow(), top_context, &viewport.get());
+                let _ = layers.composite(display, &viewport.get());
+                return;
+            }
+        }
+
+        let layers = layers.borrow();
+        let (width, height) = layers.size();
+
+        let mut mode = mode.borrow_mut();
+        if let CurrentMode::Polygon(points) = &mut *mode {
+            match event.key().as_str() {
+                "Enter" => {
+                    points.pop();
+                    let mask = Mask::polygon(points, width, height);
+                    lift_selection(
+                        &mut mode,
+                        &layers,
+                        history,
+                        selection,
+                        mask,
+                        event.alt_key(),
+                    );
+                }
+                // Drops the last fixed corner, keeping the one
+                // following the pointer.
+                "Backspace" if points.len() > 2 => {
+                    let cursor = points.pop();
+                    points.pop();
+                    points.extend(cursor);
+                }
+                "Backspace" | "Escape" => points.clear(),
+                _ => return,
+            }
+            event.prevent_default();
+            viewport.get().clear(top_context);
+            draw_overlay(&mode, &selection.borrow(), top_context, &viewport.get());
+            return;
+        }
+
+        if let CurrentMode::CropPlace(_, transform, _) = &mut *mode {
+            let step = if event.shift_key() { 10.0 } else { 1.0 };
+            match event.key().as_str() {
+                "ArrowLeft" => transform.nudge(-step, 0.0),
+                "ArrowRight" => transform.nudge(step, 0.0),
+                "ArrowUp" => transform.nudge(0.0, -step),
+                "ArrowDown" => transform.nudge(0.0, step),
+                "Enter" => {
+                    settle_floating(&mut mode, &layers, history, selection);
+                    viewport.get().clear(top_context);
+                    let _ = layers.composite(display, &viewport.get());
+                }
+                _ => return,
+            }
+            event.prevent_default();
+            draw_overlay(&mode, &selection.borrow(), top_context, &viewport.get());
+        }
+    }
+
+    /// Applies a change to the text being typed, then redraws it.
+    pub fn edit_text(&self, change: TextChange) {
+        let Input {
+            mode,
+            viewport,
+            selection,
+            text_input,
+            top_context,
+            ..
+        } = self;
+
+        let mut mode = mode.borrow_mut();
+        if let CurrentMode::Text(value) = &mut *mode {
+            if let Some(session) = &mut value.session {
+                top_context.set_font(&session.object.style.font());
+                change(
+                    &mut session.edit,
+                    &session.object.style,
+                    &text::measure(top_context),
+                );
+            }
+        }
+        draw_overlay(&mode, &selection.borrow(), top_context, &viewport.get());
+        place_text_input(text_input, &mode, top_context, &viewport.get());
+    }
+
+    /// Handles editing and caret keys for the text being typed.
+    pub fn text_key_down(&self, event: KeyboardEvent) {
+        let Input {
+            mode,
+            history,
+            layers,
+            viewport,
+            display,
+            top_context,
+            ..
+        } = self;
+
+        let typing = matches!(
+            &*mode.borrow(),
+            CurrentMode::Text(TextData {
+                session: Some(_),
+                ..
+            })
+        );
+        if !typing || event.is_composing() {
+            return;
+        }
+
+        let extend = event.shift_key();
+        let shortcut = event.ctrl_key() || event.meta_key();
+        match event.key().as_str() {
+            "Enter" if extend => self.edit_text(&|edit, _, _| edit.insert("\n")),
+            "Enter" => {
+                let mut layers = layers.borrow_mut();
+                settle_text(&mut mode.borrow_mut(), &mut layers, history);
+                viewport.get().clear(top_context);
+                let _ = layers.composite(display, &viewport.get());
+            }
+            "Escape" => {
+                let mut layers = layers.borrow_mut();
+                drop_text(&mut mode.borrow_mut(), &mut layers);
+                viewport.get().clear(top_context);
+                let _ = layers.composite(display, &viewport.get());
+            }
+            "Backspace" => self.edit_text(&|edit, _, _| edit.delete_backward()),
+            "Delete" => self.edit_text(&|edit, _, _| edit.delete_forward()),
+            "ArrowLeft" => self.edit_text(&|edit, _, _| edit.move_left(extend)),
+            "ArrowRight" => self.edit_text(&|edit, _, _| edit.move_right(extend)),
+            "ArrowUp" => self
+                .edit_text(&|edit, style, measure| style.move_vertical(edit, -1, extend, measure)),
+            "ArrowDown" => self
+                .edit_text(&|edit, style, measure| style.move_vertical(edit, 1, extend, measure)),
+            "Home" => self.edit_text(&|edit, _, _| edit.move_home(extend)),
+            "End" => self.edit_text(&|edit, _, _| edit.move_end(extend)),
+            "a" | "A" if shortcut => self.edit_text(&|edit, _, _| edit.select_all()),
+            // Characters arrive as input events. Shortcuts like
+            // paste act on the text rather than the document.
+            _ => {
+                if shortcut {
+                    event.stop_propagation();
+                }
+                return;
+            }
+        }
+        event.prevent_default();
+        // Keeps the keys from reaching page shortcuts and panning.
+        event.stop_propagation();
+    }
+
+    /// Types characters into the text being typed.
+    pub fn before_input(&self, event: InputEvent) {
+        // Composed text is taken once the composition ends.
+        if event.is_composing() {
+            return;
+        }
+        let input_type = event.input_type();
+        let typed = match input_type.as_str() {
+            "deleteContentBackward" => {
+                self.edit_text(&|edit, _, _| edit.delete_backward());
+                None
+            }
+            "deleteContentForward" => {
+                self.edit_text(&|edit, _, _| edit.delete_forward());
+                None
+            }
+            "insertLineBreak" | "insertParagraph" => Some("\n".to_owned()),
+            "insertFromComposition" => return,
+            name if name.starts_with("insert") => event.data().or_else(|| {
+                event
+                    .data_transfer()
+                    .and_then(|data| data.get_data("text/plain").ok())
+            }),
+            _ => return,
+        };
+        if let Some(typed) = typed {
+            self.edit_text(&|edit, _, _| edit.insert(&typed));
+        }
+        // The field stays empty, the text lives in the session.
+        event.prevent_default();
+    }
+
+    pub fn composition_update(&self, event: CompositionEvent) {
+        let composed = event.data();
+        self.edit_text(&|edit, _, _| edit.set_composition(composed.clone()));
+    }
+
+    pub fn composition_end(&self, event: CompositionEvent) {
+        let Input { text_input, .. } = self;
+
+        let composed = event.data().unwrap_or_default();
+        self.edit_text(&|edit, _, _| {
+            edit.set_composition(None);
+            edit.insert(&composed);
+        });
+        text_input.set_value("");
+    }
+
+    /// Zooms on pinches and ctrl-wheel, pans otherwise.
+    pub fn wheel(&self, event: WheelEvent) {
+        let Input {
+            mode,
+            layers,
+            viewport,
+            selection,
+            display,
+            top_context,
+            ..
+        } = self;
+
+        event.prevent_default();
+
+        let mut moved = viewport.get();
+        // Trackpad pinches arrive as wheel events with ctrl held.
+        if event.ctrl_key() || event.meta_key() {
+            let factor = (-event.delta_y() * 0.002).exp();
+            moved.zoom_to(
+                event.offset_x() as f64,
+                event.offset_y() as f64,
+                moved.zoom * factor,
+            );
+        } else {
+            moved.pan_by(-event.delta_x(), -event.delta_y());
+        }
+
+        viewport.set(moved);
+        moved.apply(top_context);
+        draw_overlay(&mode.borrow(), &selection.borrow(), top_context, &moved);
+        let _ = layers.borrow().composite(display, &moved);
+    }
+
+    pub fn space_down(&self, event: KeyboardEvent) {
+        let Input {
+            mode, space_held, ..
+        } = self;
+
+        // Space is a character while typing text, here or in the
+        // page's own fields.
+        if event.code() == "Space"
+            && !matches!(*mode.borrow(), CurrentMode::Text(_))
+            && !typed_into(&event)
+        {
+            space_held.set(true);
+        }
+    }
+
+    pub fn space_up(&self, event: KeyboardEvent) {
+        let Input { space_held, .. } = self;
+
+        if event.code() == "Space" {
+            space_held.set(false);
+        }
+    }
+
+    /// Keeps the marching ants moving, around the outline being
+    /// selected or the current selection, and the caret blinking.
+    /// Dragging out a rectangle draws its own preview.
+    pub fn animate(&self) {
+        let Input {
+            mode,
+            viewport,
+            selection,
+            top_context,
+            pressed,
+            ..
+        } = self;
+
+        let (mode, selection) = match (mode.try_borrow(), selection.try_borrow()) {
+            (Ok(mode), Ok(selection)) => (mode, selection),
+            _ => return,
+        };
+        let outlining = matches!(
+            &*mode,
+            CurrentMode::Lasso(points) | CurrentMode::Polygon(points) if !points.is_empty()
+        );
+        let showing = mode.selects() && selection.is_some() && !pressed.get();
+        let typing = matches!(
+            &*mode,
+            CurrentMode::Text(TextData {
+                session: Some(_),
+                ..
+            })
+        );
+        if outlining || showing || typing {
+            draw_overlay(&mode, &selection, top_context, &viewport.get());
+        }
+    }
+}
@@ -0,0 +1,726 @@
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+
+use crate::{
+    history::{Change, History},
+    project::LayerData,
+    raster::{swap_region, Rect, Region, Surface},
+    shapes::ShapeObject,
+    svg::Svg,
+    text::TextObject,
+    utils::{create_canvas, get_document},
+    viewport::Viewport,
+};
+
+/// Size of a checkerboard square showing through transparent areas, in view
+/// pixels.
+const CHECKER_SIZE: u32 = 8;
+
+pub struct Layer {
+    pub id: u32,
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f64,
+    /// Whether shapes drawn on the layer are kept as objects instead of
+    /// being painted into its pixels.
+    pub vector: bool,
+    canvas: HtmlCanvasElement,
+    context: CanvasRenderingContext2d,
+    /// Shapes kept as objects, drawn over the pixels, bottom first.
+    shapes: Vec<ShapeObject>,
+    /// Text kept editable, drawn over the pixels and shapes, bottom first.
+    texts: Vec<TextObject>,
+}
+
+/// Pen or eraser stroke in progress on a layer. It is shown with the layer
+/// but only painted into the pixels once done, so that a translucent stroke
+/// covers each pixel once wherever its segments meet.
+pub struct Stroke {
+    pub layer: u32,
+    pub object: ShapeObject,
+    pub kind: StrokeKind,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StrokeKind {
+    /// Painted over the pixels.
+    Paint,
+    /// Cutting the pixels away to transparency.
+    Erase,
+    /// Kept as a shape over the layer's others.
+    Shape,
+}
+
+impl Layer {
+    fn new(id: u32, name: String, width: u32, height: u32) -> Result<Layer, JsValue> {
+        let canvas = get_document()?
+            .create_element("canvas")?
+            .dyn_into::<HtmlCanvasElement>()?;
+        canvas.set_width(width);
+        canvas.set_height(height);
+
+        let context = canvas
+            .get_context("2d")?
+            .unwrap()
+            .dyn_into::<CanvasRenderingContext2d>()?;
+
+        Ok(Layer {
+            id,
+            name,
+            visible: true,
+            opacity: 1.0,
+            vector: false,
+            canvas,
+            context,
+            shapes: vec![],
+            texts: vec![],
+        })
+    }
+
+    /// Rebuilds a saved layer.
+    fn load(data: &LayerData, width: u32, height: u32) -> Result<Layer, JsValue> {
+        let mut layer = Layer::new(data.id, data.name.clone(), width, height)?;
+        layer.visible = data.visible;
+        layer.opacity = data.opacity;
+        layer.vector = data.vector;
+        layer.context.write(&Region {
+            rect: Rect::new(0, 0, width, height),
+            data: data.pixels.clone(),
+        })?;
+        layer.shapes = data.shapes.clone();
+        layer.texts = data.texts.clone();
+        Ok(layer)
+    }
+
+    fn save(&self, width: u32, height: u32) -> Result<LayerData, JsValue> {
+        Ok(LayerData {
+            id: self.id,
+            name: self.name.clone(),
+            visible: self.visible,
+            opacity: self.opacity,
+            pixels: self.context.read(&Rect::new(0, 0, width, height))?.data,
+            vector: self.vector,
+            shapes: self.shapes.clone(),
+            texts: self.texts.clone(),
+        })
+    }
+
+    /// Takes on the line width, cap, font and colours of `template`.
+    fn copy_drawing_state(&self, template: &CanvasRenderingContext2d) {
+        self.context.set_line_width(template.line_width());
+        self.context.set_line_cap(&template.line_cap());
+        self.context.set_font(&template.font());
+        if let Some(style) = template.stroke_style().as_string() {
+            self.context.set_stroke_style_str(&style);
+        }
+        if let Some(style) = template.fill_style().as_string() {
+            self.context.set_fill_style_str(&style);
+        }
+    }
+
+    /// Draws the pixels, shapes and text onto `ctx` at the layer's opacity,
+    /// along with `stroke` if it is in progress on the layer, going through
+    /// `scratch` when there are objects so they fade along with the pixels.
+    fn draw(
+        &self,
+        ctx: &CanvasRenderingContext2d,
+        scratch: &Scratch,
+        stroke: Option<&Stroke>,
+    ) -> Result<(), JsValue> {
+        let stroke = stroke.filter(|stroke| stroke.layer == self.id);
+        ctx.save();
+        ctx.set_global_alpha(self.opacity);
+        if self.shapes.is_empty() && self.texts.is_empty() && stroke.is_none() {
+            ctx.draw_image_with_html_canvas_element(&self.canvas, 0.0, 0.0)?;
+        } else {
+            let (canvas, context) = scratch;
+            context.clear_rect(0.0, 0.0, canvas.width() as f64, canvas.height() as f64);
+            context.draw_image_with_html_canvas_element(&self.canvas, 0.0, 0.0)?;
+            let mut kept = None;
+            match stroke {
+                Some(stroke) if stroke.kind == StrokeKind::Shape => kept = Some(&stroke.object),
+                Some(stroke) => self.paint(context, stroke),
+                None => {}
+            }
+            self.draw_objects(context, kept)?;
+            ctx.draw_image_with_html_canvas_element(canvas, 0.0, 0.0)?;
+        }
+        ctx.restore();
+        Ok(())
+    }
+
+    /// Draws the shapes, then `extra` over them, then the text, onto `ctx`
+    /// in the layer's drawing state.
+    fn draw_objects(
+        &self,
+        ctx: &CanvasRenderingContext2d,
+        extra: Option<&ShapeObject>,
+    ) -> Result<(), JsValue> {
+        let mut surface = ctx.clone();
+        surface.set_line_cap(&self.context.line_cap());
+        for shape in self.shapes.iter().chain(extra) {
+            shape.draw(&mut surface);
+        }
+        for text in &self.texts {
+            text.draw(ctx)?;
+        }
+        Ok(())
+    }
+
+    /// Paints `stroke` onto `ctx` in the layer's drawing state.
+    fn paint(&self, ctx: &CanvasRenderingContext2d, stroke: &Stroke) {
+        ctx.save();
+        ctx.set_line_cap(&self.context.line_cap());
+        if stroke.kind == StrokeKind::Erase {
+            let _ = ctx.set_global_composite_operation("destination-out");
+        }
+        stroke.object.draw(&mut ctx.clone());
+        ctx.restore();
+    }
+
+    fn to_js(&self, active: bool) -> Result<JsValue, JsValue> {
+        let object = Object::new();
+        Reflect::set(&object, &"id".into(), &self.id.into())?;
+        Reflect::set(&object, &"name".into(), &self.name.as_str().into())?;
+        Reflect::set(&object, &"visible".into(), &self.visible.into())?;
+        Reflect::set(&object, &"opacity".into(), &self.opacity.into())?;
+        Reflect::set(&object, &"vector".into(), &self.vector.into())?;
+        Reflect::set(&object, &"active".into(), &active.into())?;
+
+        Ok(object.into())
+    }
+}
+
+/// Offscreen canvas of the document's size to combine a layer with its
+/// objects.
+type Scratch = (HtmlCanvasElement, CanvasRenderingContext2d);
+
+/// Ordered stack of offscreen layers, bottom first. Tools draw into the
+/// active layer and the visible ones are composited onto the display canvas.
+pub struct LayerStack {
+    layers: Vec<Layer>,
+    active: u32,
+    next_id: u32,
+    width: u32,
+    height: u32,
+    /// Whether the background starts out and erases to transparent rather
+    /// than white.
+    transparent: bool,
+    checkerboard: HtmlCanvasElement,
+    scratch: Scratch,
+    stroke: Option<Stroke>,
+}
+
+impl LayerStack {
+    pub fn new(width: u32, height: u32, transparent: bool) -> Result<LayerStack, JsValue> {
+        let background = Layer::new(0, "Background".to_owned(), width, height)?;
+        if !transparent {
+            background.context.set_fill_style_str("white");
+            background
+                .context
+                .fill_rect(0.0, 0.0, width as f64, height as f64);
+        }
+
+        Ok(LayerStack {
+            layers: vec![background],
+            active: 0,
+            next_id: 1,
+            width,
+            height,
+            transparent,
+            checkerboard: checkerboard_tile()?,
+            scratch: create_canvas(width, height)?,
+            stroke: None,
+        })
+    }
+
+    /// Rebuilds a stack from saved layers, bottom first.
+    pub fn load(
+        width: u32,
+        height: u32,
+        saved: &[LayerData],
+        active: u32,
+        transparent: bool,
+    ) -> Result<LayerStack, JsValue> {
+        let layers = saved
+            .iter()
+            .map(|data| Layer::load(data, width, height))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let next_id = saved.iter().map(|data| data.id + 1).max().unwrap_or(0);
+        Ok(LayerStack {
+            layers,
+            active,
+            next_id,
+            width,
+            height,
+            transparent,
+            checkerboard: checkerboard_tile()?,
+            scratch: create_canvas(width, height)?,
+            stroke: None,
+        })
+    }
+
+    /// Reads every layer back for saving, bottom first.
+    pub fn save(&self) -> Result<Vec<LayerData>, JsValue> {
+        self.layers
+            .iter()
+            .map(|layer| layer.save(self.width, self.height))
+            .collect()
+    }
+
+    /// Saves layer `id` along with its index in the stack, e.g. for undo.
+    pub fn snapshot(&self, id: u32) -> Result<(usize, LayerData), JsValue> {
+        let index = self.index_of(id)?;
+        Ok((index, self.layers[index].save(self.width, self.height)?))
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.transparent
+    }
+
+    /// Creates a layer right above the active one, copying its drawing
+    /// state, and makes it active.
+    pub fn add(&mut self, name: String) -> Result<u32, JsValue> {
+        let id = self.next_id;
+        let layer = Layer::new(id, name, self.width, self.height)?;
+        layer.copy_drawing_state(&self.active_context());
+
+        let index = self.index_of(self.active)? + 1;
+        self.layers.insert(index, layer);
+        self.next_id += 1;
+        self.active = id;
+
+        Ok(id)
+    }
+
+    /// Like `add`, for a layer keeping the shapes drawn on it as objects.
+    pub fn add_vector(&mut self, name: String) -> Result<u32, JsValue> {
+        let id = self.add(name)?;
+        self.get_mut(id)?.vector = true;
+        Ok(id)
+    }
+
+    /// Removes layer `id`, returning it as saved with the index it had so
+    /// `restore` can put it back.
+    pub fn remove(&mut self, id: u32) -> Result<(usize, LayerData), JsValue> {
+        if self.layers.len() == 1 {
+            return Err(JsValue::from_str("cannot remove the last layer"));
+        }
+
+        let index = self.index_of(id)?;
+        let removed = self.layers.remove(index).save(self.width, self.height)?;
+
+        if self.active == id {
+            self.active = self.layers[index.saturating_sub(1)].id;
+        }
+
+        Ok((index, removed))
+    }
+
+    /// Puts a saved layer at `index` in the stack, replacing any layer
+    /// with its id.
+    pub fn restore(&mut self, index: usize, data: &LayerData) -> Result<(), JsValue> {
+        let layer = Layer::load(data, self.width, self.height)?;
+        layer.copy_drawing_state(&self.active_context());
+        if let Ok(current) = self.index_of(data.id) {
+            self.layers.remove(current);
+        }
+        self.layers.insert(index.min(self.layers.len()), layer);
+        self.next_id = self.next_id.max(data.id + 1);
+        Ok(())
+    }
+
+    /// Puts back what `change` holds for layer `id`, returning what it
+    /// replaced.
+    pub fn apply(&mut self, id: u32, change: &Change) -> Result<Change, JsValue> {
+        match change {
+            Change::Pixels(region) => {
+                let mut context = self.get_mut(id)?.context.clone();
+                Ok(Change::Pixels(swap_region(&mut context, region)?))
+            }
+            Change::Drawn(region, shape) => {
+                let mut context = self.get_mut(id)?.context.clone();
+                Ok(Change::Drawn(
+                    swap_region(&mut context, region)?,
+                    shape.clone(),
+                ))
+            }
+            Change::Shapes(shapes) => Ok(Change::Shapes(std::mem::replace(
+                &mut self.get_mut(id)?.shapes,
+                shapes.clone(),
+            ))),
+            Change::Texts(texts) => Ok(Change::Texts(std::mem::replace(
+                &mut self.get_mut(id)?.texts,
+                texts.clone(),
+            ))),
+            Change::Layer(Some((index, data))) => {
+                let current = self.snapshot(id).ok();
+                self.restore(*index, data)?;
+                Ok(Change::Layer(current))
+            }
+            Change::Layer(None) => Ok(Change::Layer(Some(self.remove(id)?))),
+        }
+    }
+
+    /// Moves a layer to `index` in the stack, 0 being the bottom.
+    pub fn reorder(&mut self, id: u32, index: usize) -> Result<(), JsValue> {
+        let current = self.index_of(id)?;
+        let layer = self.layers.remove(current);
+        let index = index.min(self.layers.len());
+        self.layers.insert(index, layer);
+
+        Ok(())
+    }
+
+    pub fn rename(&mut self, id: u32, name: String) -> Result<(), JsValue> {
+        self.get_mut(id)?.name = name;
+        Ok(())
+    }
+
+    pub fn set_visible(&mut self, id: u32, visible: bool) -> Result<(), JsValue> {
+        self.get_mut(id)?.visible = visible;
+        Ok(())
+    }
+
+    pub fn set_opacity(&mut self, id: u32, opacity: f64) -> Result<(), JsValue> {
+        self.get_mut(id)?.opacity = opacity.clamp(0.0, 1.0);
+        Ok(())
+    }
+
+    pub fn set_active(&mut self, id: u32) -> Result<(), JsValue> {
+        self.index_of(id)?;
+        self.active = id;
+        Ok(())
+    }
+
+    pub fn active_id(&self) -> u32 {
+        self.active
+    }
+
+    pub fn active_context(&self) -> CanvasRenderingContext2d {
+        self.layers
+            .iter()
+            .find(|layer| layer.id == self.active)
+            .map(|layer| layer.context.clone())
+            .unwrap()
+    }
+
+    /// Returns the id of the layer right below `id`, if there is one.
+    pub fn below(&self, id: u32) -> Result<Option<u32>, JsValue> {
+        let index = self.index_of(id)?;
+        Ok(index.checked_sub(1).map(|below| self.layers[below].id))
+    }
+
+    /// Draws layer `id` onto the one below it and removes it. The shapes
+    /// and text of both layers are flattened on the way. Returns the id of
+    /// the layer it was merged into.
+    pub fn merge_down(&mut self, id: u32) -> Result<u32, JsValue> {
+        let index = self.index_of(id)?;
+        if index == 0 {
+            return Err(JsValue::from_str("no layer below to merge into"));
+        }
+
+        let upper = self.layers.remove(index);
+        let lower = &mut self.layers[index - 1];
+        lower.draw_objects(&lower.context, None)?;
+        lower.shapes.clear();
+        lower.texts.clear();
+        if upper.visible {
+            upper.draw(&lower.context, &self.scratch, None)?;
+        }
+
+        let lower_id = lower.id;
+        if self.active == id {
+            self.active = lower_id;
+        }
+
+        Ok(lower_id)
+    }
+
+    pub fn is_background(&self, id: u32) -> bool {
+        self.layers.first().map(|layer| layer.id) == Some(id)
+    }
+
+    /// Whether erasing on layer `id` paints white instead of leaving
+    /// transparency, which is only the case on an opaque background.
+    pub fn erases_to_white(&self, id: u32) -> bool {
+        !self.transparent && self.is_background(id)
+    }
+
+    pub fn is_vector(&self, id: u32) -> bool {
+        self.layers
+            .iter()
+            .any(|layer| layer.id == id && layer.vector)
+    }
+
+    /// Ids of every layer, bottom first.
+    pub fn ids(&self) -> Vec<u32> {
+        self.layers.iter().map(|layer| layer.id).collect()
+    }
+
+    /// Puts `text` on layer `id`, at `index` among its text or on top.
+    pub fn insert_text(
+        &mut self,
+        id: u32,
+        index: Option<usize>,
+        text: TextObject,
+    ) -> Result<(), JsValue> {
+        let texts = &mut self.get_mut(id)?.texts;
+        let index = index.map_or(texts.len(), |index| index.min(texts.len()));
+        texts.insert(index, text);
+        Ok(())
+    }
+
+    /// Text objects of layer `id`, bottom first.
+    pub fn texts(&self, id: u32) -> Option<&[TextObject]> {
+        self.layers
+            .iter()
+            .find(|layer| layer.id == id)
+            .map(|layer| &layer.texts[..])
+    }
+
+    /// Takes the topmost text of layer `id` under `point` off the layer,
+    /// with the index it had.
+    pub fn take_text_at(&mut self, id: u32, point: (f64, f64)) -> Option<(usize, TextObject)> {
+        let layer = self.get_mut(id).ok()?;
+        let index = layer
+            .texts
+            .iter()
+            .rposition(|text| text.contains(&layer.context, point))?;
+        Some((index, layer.texts.remove(index)))
+    }
+
+    /// Takes all text off layer `id`, e.g. to draw it into the pixels.
+    pub fn take_texts(&mut self, id: u32) -> Vec<TextObject> {
+        self.get_mut(id)
+            .map(|layer| std::mem::take(&mut layer.texts))
+            .unwrap_or_default()
+    }
+
+    /// Puts `shape` on layer `id`, at `index` among its shapes or on top.
+    /// Returns the index it got.
+    pub fn insert_shape(
+        &mut self,
+        id: u32,
+        index: Option<usize>,
+        shape: ShapeObject,
+    ) -> Result<usize, JsValue> {
+        let shapes = &mut self.get_mut(id)?.shapes;
+        let index = index.map_or(shapes.len(), |index| index.min(shapes.len()));
+        shapes.insert(index, shape);
+        Ok(index)
+    }
+
+    /// Shape objects of layer `id`, bottom first.
+    pub fn shapes(&self, id: u32) -> Option<&[ShapeObject]> {
+        self.layers
+            .iter()
+            .find(|layer| layer.id == id)
+            .map(|layer| &layer.shapes[..])
+    }
+
+    /// Index of the topmost shape of layer `id` within `reach` of `point`.
+    pub fn shape_at(&self, id: u32, point: (f64, f64), reach: f64) -> Option<usize> {
+        self.layers
+            .iter()
+            .find(|layer| layer.id == id)?
+            .shapes
+            .iter()
+            .rposition(|shape| shape.contains(point, reach))
+    }
+
+    pub fn shape(&self, id: u32, index: usize) -> Option<&ShapeObject> {
+        self.layers
+            .iter()
+            .find(|layer| layer.id == id)?
+            .shapes
+            .get(index)
+    }
+
+    pub fn shape_mut(&mut self, id: u32, index: usize) -> Option<&mut ShapeObject> {
+        self.get_mut(id).ok()?.shapes.get_mut(index)
+    }
+
+    pub fn remove_shape(&mut self, id: u32, index: usize) -> Option<ShapeObject> {
+        let shapes = &mut self.get_mut(id).ok()?.shapes;
+        if index >= shapes.len() {
+            return None;
+        }
+        Some(shapes.remove(index))
+    }
+
+    /// Moves shape `index` of layer `id` to `to` among its shapes, 0 being
+    /// the bottom. Returns the index it ends up at.
+    pub fn reorder_shape(&mut self, id: u32, index: usize, to: usize) -> Option<usize> {
+        let shape = self.remove_shape(id, index)?;
+        self.insert_shape(id, Some(to), shape).ok()
+    }
+
+    pub fn context(&self, id: u32) -> Option<CanvasRenderingContext2d> {
+        self.layers
+            .iter()
+            .find(|layer| layer.id == id)
+            .map(|layer| layer.context.clone())
+    }
+
+    pub fn contexts(&self) -> impl Iterator<Item = &CanvasRenderingContext2d> {
+        self.layers.iter().map(|layer| &layer.context)
+    }
+
+    /// Redraws every visible layer onto `ctx`, bottom to top, as seen
+    /// through `viewport`, over a checkerboard showing transparent areas.
+    pub fn composite(
+        &self,
+        ctx: &CanvasRenderingContext2d,
+        viewport: &Viewport,
+    ) -> Result<(), JsValue> {
+        viewport.clear(ctx);
+        ctx.save();
+
+        // Drawn in view pixels so the squares keep their size when zooming.
+        let (left, top) = viewport.to_view(0.0, 0.0);
+        let (right, bottom) = viewport.to_view(self.width as f64, self.height as f64);
+        if let Some(pattern) =
+            ctx.create_pattern_with_html_canvas_element(&self.checkerboard, "repeat")?
+        {
+            ctx.set_fill_style_canvas_pattern(&pattern);
+            ctx.fill_rect(left, top, right - left, bottom - top);
+        }
+
+        viewport.apply(ctx);
+        self.draw_layers(ctx)?;
+
+        ctx.restore();
+        Ok(())
+    }
+
+    /// Draws the visible layers onto a canvas of the document's size, as
+    /// they would be exported.
+    pub fn flatten(&self) -> Result<HtmlCanvasElement, JsValue> {
+        let (canvas, context) = create_canvas(self.width, self.height)?;
+        self.draw_layers(&context)?;
+        Ok(canvas)
+    }
+
+    /// Reads the pixels of `rect` as the visible layers combine them.
+    pub fn read_flattened(&self, rect: &Rect) -> Result<Region, JsValue> {
+        let (_, context) = create_canvas(rect.width, rect.height)?;
+        context.translate(-(rect.x as f64), -(rect.y as f64))?;
+        self.draw_layers(&context)?;
+        context.set_transform(1.0, 0.0, 0.0, 1.0, 0.0, 0.0)?;
+
+        let pixels = context.read(&Rect::new(0, 0, rect.width, rect.height))?;
+        Ok(pixels.translated(rect.x, rect.y))
+    }
+
+    /// Writes the visible layers as an SVG document, each one's pixels as
+    /// an image, unless there are none, under its shapes and text. Strokes
+    /// and shapes painted into the pixels are written as geometry over
+    /// what the pixels held before them, as long as `history` still has
+    /// them and nothing else changed the pixels since.
+    pub fn to_svg(&self, history: &History) -> Result<String, JsValue> {
+        let full = Rect::new(0, 0, self.width, self.height);
+        let mut svg = Svg::new(self.width, self.height);
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            svg.begin_layer(&layer.name, layer.opacity);
+            let drawn = history.drawn(layer.id);
+            let base;
+            let (canvas, context) = if drawn.is_empty() {
+                (&layer.canvas, &layer.context)
+            } else {
+                // Puts back what each was painted over, newest first.
+                base = create_canvas(self.width, self.height)?;
+                base.1
+                    .draw_image_with_html_canvas_element(&layer.canvas, 0.0, 0.0)?;
+                for (region, _) in &drawn {
+                    base.1.clone().write(region)?;
+                }
+                (&base.0, &base.1)
+            };
+            let pixels = context.read(&full)?;
+            if pixels.data.chunks_exact(4).any(|pixel| pixel[3] != 0) {
+                svg.image(self.width, self.height, &canvas.to_data_url()?);
+            }
+            let drawn = drawn.iter().rev().map(|(_, shape)| *shape);
+            for shape in drawn.chain(&layer.shapes) {
+                svg.shape(shape);
+            }
+            for text in &layer.texts {
+                svg.text(text);
+            }
+            svg.end_layer();
+        }
+        Ok(svg.finish())
+    }
+
+    /// Starts showing `stroke` with its layer.
+    pub fn begin_stroke(&mut self, stroke: Stroke) {
+        self.stroke = Some(stroke);
+    }
+
+    pub fn stroke_mut(&mut self) -> Option<&mut Stroke> {
+        self.stroke.as_mut()
+    }
+
+    /// Stops showing the stroke in progress and hands it back, leaving the
+    /// layer as it was.
+    pub fn end_stroke(&mut self) -> Option<Stroke> {
+        self.stroke.take()
+    }
+
+    /// Paints `stroke` into the pixels of its layer.
+    pub fn paint_stroke(&self, stroke: &Stroke) -> Result<(), JsValue> {
+        let layer = &self.layers[self.index_of(stroke.layer)?];
+        layer.paint(&layer.context, stroke);
+        Ok(())
+    }
+
+    /// Describes the stack for the JS side, bottom layer first.
+    pub fn to_js(&self) -> Result<Array, JsValue> {
+        let array = Array::new();
+        for layer in &self.layers {
+            array.push(&layer.to_js(layer.id == self.active)?);
+        }
+
+        Ok(array)
+    }
+
+    fn draw_layers(&self, ctx: &CanvasRenderingContext2d) -> Result<(), JsValue> {
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            layer.draw(ctx, &self.scratch, self.stroke.as_ref())?;
+        }
+        Ok(())
+    }
+
+    fn index_of(&self, id: u32) -> Result<usize, JsValue> {
+        self.layers
+            .iter()
+            .position(|layer| layer.id == id)
+            .ok_or_else(|| JsValue::from_str(&format!("no layer with id {}", id)))
+    }
+
+    fn get_mut(&mut self, id: u32) -> Result<&mut Layer, JsValue> {
+        let index = self.index_of(id)?;
+        Ok(&mut self.layers[index])
+    }
+}
+
+/// Two by two squares of light grays, repeated behind transparent areas.
+fn checkerboard_tile() -> Result<HtmlCanvasElement, JsValue> {
+    let (canvas, context) = create_canvas(CHECKER_SIZE * 2, CHECKER_SIZE * 2)?;
+    let size = CHECKER_SIZE as f64;
+
+    context.set_fill_style_str("#ffffff");
+    context.fill_rect(0.0, 0.0, size * 2.0, size * 2.0);
+    context.set_fill_style_str("#cccccc");
+    context.fill_rect(size, 0.0, size, size);
+    context.fill_rect(0.0, size, size, size);
+
+    Ok(canvas)
+}
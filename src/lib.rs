@@ -1,56 +1,294 @@
-mod utils;
+mod clipboard;
+pub mod color;
+mod events;
+pub mod filters;
+pub mod history;
+mod input;
+mod layers;
+mod listeners;
+pub mod project;
+pub mod raster;
+pub mod selection;
+pub mod shapes;
+pub mod svg;
+pub mod text;
+pub mod transform;
+pub mod utils;
+pub mod viewport;
 
 use std::{
     cell::{Cell, RefCell},
-    f64::consts::PI,
+    convert::TryFrom,
     rc::Rc,
+    str::FromStr,
     vec,
 };
 
-use colors_transform::{Color as CrateColor, Rgb};
-use js_sys::Array;
+use color::ParseColorError;
+use colors_transform::Rgb;
+use events::{notifying, Event, Notifier};
+use filters::Filter;
+use history::{Change, Entry, History, DEFAULT_HISTORY_LIMIT};
+use input::Input;
+use js_sys::{Array, Promise, Reflect};
+use layers::LayerStack;
+use listeners::Listeners;
+use project::{Project, ToolSettings};
+use raster::{Rect, Region, Surface};
+use selection::{draw_ants, draw_mask_ants, Combine, Mask};
+use shapes::{FillStyle, Shape, ShapeObject, ShapeSettings};
+use text::{TextAlign, TextBaseline, TextEdit, TextObject, TextStyle};
+use transform::{Handle, Transform};
 use utils::{
-    define_distance, define_postition, fill, get_client_canvas, get_content_inside_rect,
-    get_document, match_input, two_point_distance,
+    create_canvas, define_distance, define_postition, draw_transformed, get_document,
+    two_point_distance, FillOptions, PenDynamics,
 };
+use viewport::Viewport;
 use wasm_bindgen::prelude::*;
-use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, ImageData};
+use wasm_bindgen_futures::future_to_promise;
+use web_sys::{
+    CanvasRenderingContext2d, Element, HtmlCanvasElement, HtmlElement, HtmlTextAreaElement,
+};
 
 #[derive(Clone)]
 struct TextData {
-    content: String,
-    position: (f64, f64),
-    font_size: u32,
-    font_type: String,
+    /// Style new text is typed in.
+    style: TextStyle,
+    /// Text being typed or edited again, until committed.
+    session: Option<Box<TextSession>>,
+}
+
+/// Text being typed, kept on `layer` as a text object once committed.
+#[derive(Clone)]
+struct TextSession {
+    edit: TextEdit,
+    /// Position, style and colour of the text. Its content is only brought
+    /// up to date on commit.
+    object: TextObject,
+    layer: u32,
+    /// Text object opened again, with its index among the layer's text, to
+    /// put back when editing is dropped.
+    original: Option<(usize, TextObject)>,
+}
+
+impl TextSession {
+    /// Opens the text object at `index` on `layer` for editing.
+    fn reopen(layer: u32, index: usize, object: TextObject) -> TextSession {
+        TextSession {
+            edit: TextEdit::with_content(&object.content),
+            object: object.clone(),
+            layer,
+            original: Some((index, object)),
+        }
+    }
 }
 
 impl TextData {
-    fn append(&self, content: String) -> TextData {
-        TextData {
-            content,
-            position: self.position,
-            font_size: self.font_size,
-            font_type: self.font_type.clone(),
+    /// Line of the text being typed at the height of `point`, and how far
+    /// along that line `point` is, measured with `ctx`.
+    fn locate(&self, ctx: &CanvasRenderingContext2d, point: (f64, f64)) -> Option<(usize, f64)> {
+        let session = self.session.as_ref()?;
+        let object = &session.object;
+        ctx.set_font(&object.style.font());
+        let measure = text::measure(ctx);
+        Some(
+            object
+                .style
+                .locate(&session.edit, object.position, point, measure),
+        )
+    }
+
+    /// Whether `point` is on the text being typed, measured with `ctx`.
+    fn contains(&self, ctx: &CanvasRenderingContext2d, point: (f64, f64)) -> bool {
+        let session = match &self.session {
+            Some(session) => session,
+            None => return false,
+        };
+        let object = &session.object;
+        ctx.set_font(&object.style.font());
+        let measure = text::measure(ctx);
+        object
+            .style
+            .contains(&session.edit, object.position, point, measure)
+    }
+
+    /// Whether `point` is within `reach` of the frame around the text being
+    /// typed, where dragging moves it.
+    fn on_frame(&self, ctx: &CanvasRenderingContext2d, point: (f64, f64), reach: f64) -> bool {
+        let session = match &self.session {
+            Some(session) => session,
+            None => return false,
+        };
+        let object = &session.object;
+        ctx.set_font(&object.style.font());
+        let measure = text::measure(ctx);
+        let [(x0, y0), (x1, y1)] = object.style.frame(&session.edit, object.position, measure);
+        (x0 - reach..=x1 + reach).contains(&point.0) && (y0 - reach..=y1 + reach).contains(&point.1)
+    }
+}
+
+/// Shape picked on a vector layer with `Canvas::set_object_select`.
+#[derive(Clone, Copy)]
+struct PickedShape {
+    layer: u32,
+    index: usize,
+    /// Corners of the area it paints, framed on the overlay.
+    frame: ((f64, f64), (f64, f64)),
+}
+
+impl PickedShape {
+    fn new(layers: &LayerStack, layer: u32, index: usize) -> Option<PickedShape> {
+        Some(PickedShape {
+            layer,
+            index,
+            frame: layers.shape(layer, index)?.bounds(),
+        })
+    }
+}
+
+/// Id of the element editors are created in when not given one.
+const DEFAULT_CONTAINER: &str = "paint-assemble";
+
+/// Milliseconds between redraws of the marching ants.
+const ANTS_INTERVAL: i32 = 100;
+
+/// Handle being dragged on a floating selection, with the transform and
+/// document point the drag started from.
+type HandleDrag = (Handle, Transform, (f64, f64));
+
+/// Point the text being typed was grabbed at by its frame, with where the
+/// text was then.
+type TextGrab = ((f64, f64), (f64, f64));
+
+/// Point the picked shape was grabbed at, with its geometry then.
+type ShapeGrab = ((f64, f64), Shape);
+
+/// Change to the text being typed, given a way to measure text.
+type TextChange<'a> = &'a dyn Fn(&mut TextEdit, &TextStyle, &dyn Fn(&str) -> f64);
+
+/// Tool a selection was made with, to go back to once it is placed.
+#[derive(Clone, Copy)]
+enum SelectionTool {
+    Rectangle,
+    Lasso,
+    Polygon,
+    Wand,
+}
+
+impl SelectionTool {
+    fn of(mode: &CurrentMode) -> SelectionTool {
+        match mode {
+            CurrentMode::Lasso(_) => SelectionTool::Lasso,
+            CurrentMode::Polygon(_) => SelectionTool::Polygon,
+            CurrentMode::Wand => SelectionTool::Wand,
+            CurrentMode::CropPlace(_, _, tool) => *tool,
+            _ => SelectionTool::Rectangle,
         }
     }
 
-    fn format_font(&self) -> String {
-        format!("{}px {}", self.font_size, self.font_type)
+    fn idle(self) -> CurrentMode {
+        match self {
+            SelectionTool::Rectangle => CurrentMode::Crop,
+            SelectionTool::Lasso => CurrentMode::Lasso(vec![]),
+            SelectionTool::Polygon => CurrentMode::Polygon(vec![]),
+            SelectionTool::Wand => CurrentMode::Wand,
+        }
     }
 }
 
 #[derive(Clone)]
 enum CurrentMode {
     Default,
+    /// Pen strokes that remove paint instead of adding it.
+    Eraser,
     StraightLine,
     Circle,
+    Rectangle,
+    RoundedRectangle,
+    Ellipse,
     Fill,
     Crop,
-    CropPlace(ImageData),
+    /// Freehand selection, with the outline traced so far.
+    Lasso(Vec<(f64, f64)>),
+    /// Selection outlined click by click. While drawing, the last point
+    /// follows the pointer.
+    Polygon(Vec<(f64, f64)>),
+    /// Selects by colour similarity, see `Canvas::set_magic_wand`.
+    Wand,
+    /// Takes the stroke colour from the clicked pixels, see
+    /// `Canvas::set_color_picker`.
+    ColorPicker,
+    /// A lifted or pasted selection floating above the active layer.
+    CropPlace(Region, Transform, SelectionTool),
     Text(TextData),
+    /// Picks shapes kept on vector layers to move, restyle or delete them.
+    ObjectSelect(Option<PickedShape>),
 }
 
-#[derive(Clone)]
+impl CurrentMode {
+    /// Tool name reported to the page by `mode-changed` events.
+    fn name(&self) -> &'static str {
+        match self {
+            CurrentMode::Default => "pen",
+            CurrentMode::Eraser => "eraser",
+            CurrentMode::StraightLine => "line",
+            CurrentMode::Circle => "circle",
+            CurrentMode::Rectangle => "rectangle",
+            CurrentMode::RoundedRectangle => "rounded-rectangle",
+            CurrentMode::Ellipse => "ellipse",
+            CurrentMode::Fill => "fill",
+            CurrentMode::Crop => "select",
+            CurrentMode::Lasso(_) => "lasso",
+            CurrentMode::Polygon(_) => "polygon",
+            CurrentMode::Wand => "magic-wand",
+            CurrentMode::ColorPicker => "color-picker",
+            CurrentMode::CropPlace(..) => "floating-selection",
+            CurrentMode::Text(_) => "text",
+            CurrentMode::ObjectSelect(_) => "object-select",
+        }
+    }
+
+    /// Whether this is a selection tool, which shows the current selection.
+    fn selects(&self) -> bool {
+        matches!(
+            self,
+            CurrentMode::Crop | CurrentMode::Lasso(_) | CurrentMode::Polygon(_) | CurrentMode::Wand
+        )
+    }
+
+    /// Shape outlined by dragging from `start` to `end` in a shape mode.
+    fn drag_shape(&self, start: (f64, f64), end: (f64, f64), corner_radius: f64) -> Option<Shape> {
+        let x = define_postition(start.0, end.0);
+        let y = define_postition(start.1, end.1);
+        let w = define_distance(start.0, end.0);
+        let h = define_distance(start.1, end.1);
+
+        match self {
+            CurrentMode::Circle => Some(Shape::Circle {
+                cx: end.0,
+                cy: end.1,
+                radius: two_point_distance(start.0, start.1, end.0, end.1),
+            }),
+            CurrentMode::Rectangle => Some(Shape::Rectangle { x, y, w, h }),
+            CurrentMode::RoundedRectangle => Some(Shape::RoundedRectangle {
+                x,
+                y,
+                w,
+                h,
+                radius: corner_radius,
+            }),
+            CurrentMode::Ellipse => Some(Shape::Ellipse {
+                cx: x + w / 2.0,
+                cy: y + h / 2.0,
+                rx: w / 2.0,
+                ry: h / 2.0,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Color {
     White,
     Black,
@@ -71,145 +309,1054 @@ impl Color {
         Rgb::from(values.0 as f32, values.1 as f32, values.2 as f32).to_css_hex_string()
     }
 
-    fn from_str(color: String) -> Color {
-        match &color[..] {
-            "white" => Color::White,
-            "black" => Color::Black,
-            _ => {
-                let rgb = Rgb::from_hex_str(&color);
-                match rgb {
-                    Ok(value) => {
-                        let r = value.get_red() as u8;
-                        let g = value.get_green() as u8;
-                        let b = value.get_blue() as u8;
-                        Color::Custom(r, g, b, 255)
-                    }
-                    Err(..) => Color::Black,
-                }
-            }
+    /// Hex for opaque colours, `rgba()` otherwise so the alpha is kept.
+    fn to_css(&self) -> String {
+        match self.value() {
+            (_, _, _, 255) => self.to_hex(),
+            (r, g, b, a) => format!("rgba({}, {}, {}, {})", r, g, b, a as f64 / 255.0),
         }
     }
+
+    fn with_alpha(&self, alpha: u8) -> Color {
+        let (r, g, b, _) = self.value();
+        Color::Custom(r, g, b, alpha)
+    }
+}
+
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    fn from_str(css: &str) -> Result<Color, ParseColorError> {
+        Ok(match color::parse(css)? {
+            (255, 255, 255, 255) => Color::White,
+            (0, 0, 0, 255) => Color::Black,
+            (r, g, b, a) => Color::Custom(r, g, b, a),
+        })
+    }
 }
 
 #[wasm_bindgen]
 pub struct Canvas {
+    /// Wrapper holding both canvases inside the container.
+    root: HtmlElement,
     underlying_layer: HtmlCanvasElement,
     top_layer: HtmlCanvasElement,
-    height: u32,
-    width: u32,
+    /// Hidden field taking the keys typed with the text tool, so input
+    /// methods and dead keys work.
+    text_input: HtmlTextAreaElement,
     mode: Rc<RefCell<CurrentMode>>,
     current_color: Rc<RefCell<Color>>,
+    history: Rc<RefCell<History>>,
+    layers: Rc<RefCell<LayerStack>>,
+    shape_settings: Rc<RefCell<ShapeSettings>>,
+    text_style: RefCell<TextStyle>,
+    fill_options: Rc<Cell<FillOptions>>,
+    viewport: Rc<Cell<Viewport>>,
+    palette: Rc<RefCell<Vec<Color>>>,
+    selection: Rc<RefCell<Option<Mask>>>,
+    clipboard: Rc<RefCell<Option<Region>>>,
+    /// Width of the square the colour picker averages, in pixels.
+    picker_size: Rc<Cell<u32>>,
+    /// Listener `set_on_color_picked` subscribed, to replace it.
+    on_color_picked: RefCell<Option<js_sys::Function>>,
+    notifier: Notifier,
+    listeners: RefCell<Listeners>,
 }
 
 #[wasm_bindgen]
 impl Canvas {
-    pub fn new_canvas(height: u32, width: u32) -> Result<Canvas, JsValue> {
-        let document = get_document();
-        let top_canvas = document
-            .create_element("canvas")?
-            .dyn_into::<web_sys::HtmlCanvasElement>()?;
-
-        let client_canvas = get_client_canvas()?;
-        let canvas = Canvas {
-            underlying_layer: client_canvas,
-            top_layer: top_canvas,
-            height,
-            width,
-            mode: Rc::new(RefCell::new(CurrentMode::Default)),
-            current_color: Rc::new(RefCell::new(Color::Black)),
-        };
-
-        canvas.underlying_layer.set_height(height);
-        canvas.underlying_layer.set_width(width);
-        canvas.top_layer.set_height(height);
-        canvas.top_layer.set_width(width);
+    /// Creates an editor inside `container`, or inside the element with id
+    /// `paint-assemble` when left out. Fails when there is no such element.
+    pub fn new_canvas(
+        height: u32,
+        width: u32,
+        container: Option<Element>,
+    ) -> Result<Canvas, JsValue> {
+        Canvas::create(height, width, false, container)
+    }
 
-        let paint_div = document
-            .get_element_by_id("paint-assemble")
-            .unwrap()
-            .dyn_into::<web_sys::HtmlDivElement>()?;
+    /// Creates a canvas whose background starts out transparent, and which
+    /// the eraser clears to transparency instead of white.
+    pub fn new_transparent_canvas(
+        height: u32,
+        width: u32,
+        container: Option<Element>,
+    ) -> Result<Canvas, JsValue> {
+        Canvas::create(height, width, true, container)
+    }
 
-        let _ = paint_div.append_child(&canvas.top_layer);
+    /// Removes the editor from the page along with its event listeners and
+    /// timers. Freeing the canvas from JS does the same.
+    pub fn destroy(&self) {
+        self.listeners.borrow_mut().remove_all();
+        self.notifier.clear();
+        self.root.remove();
+    }
 
-        canvas.setup_initial_canvas()?;
-        canvas.setup_modes()?;
+    /// Calls `callback` with a payload object each time `event` happens:
+    /// "mode-changed", "color-changed", "stroke-committed",
+    /// "document-modified" or "selection-changed".
+    pub fn on(&self, event: String, callback: js_sys::Function) -> Result<(), JsValue> {
+        self.notifier.subscribe(&event, callback)
+    }
 
-        Ok(canvas)
+    /// Stops calling a callback passed to `on`.
+    pub fn off(&self, event: String, callback: js_sys::Function) {
+        self.notifier.unsubscribe(&event, &callback);
     }
 
     pub fn set_stroke_width(&self, width: f64) -> Result<(), JsValue> {
-        let context = self.get_context()?;
         let top_context = self.get_top_context()?;
 
-        context.set_line_width(width);
+        for context in self.layers.borrow().contexts() {
+            context.set_line_width(width);
+        }
         top_context.set_line_width(width);
 
-        Ok(())
+        self.update_picked_shape(|shape| shape.line_width = width)
     }
 
+    /// Sets the stroke colour, keeping the opacity set by `set_stroke_alpha`
+    /// unless `color` is itself translucent.
     pub fn set_stroke_color(&self, color: String) -> Result<(), JsValue> {
-        let context = self.get_context()?;
-        let top_context = self.get_top_context()?;
-
-        context.set_stroke_style(&JsValue::from_str(&color));
-        top_context.set_stroke_style(&JsValue::from_str(&color));
-
-        *self.current_color.borrow_mut() = Color::from_str(color);
+        let mut parsed: Color = color.parse()?;
+        if parsed.value().3 == 255 {
+            parsed = parsed.with_alpha(self.current_color.borrow().value().3);
+        }
+        self.apply_stroke_color(&parsed.to_css(), parsed)?;
+        self.notifier.sync();
+        Ok(())
+    }
 
+    /// Sets the stroke opacity, from 0 for invisible to 1 for opaque.
+    pub fn set_stroke_alpha(&self, alpha: f64) -> Result<(), JsValue> {
+        let alpha = (alpha.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let color = self.current_color.borrow().with_alpha(alpha);
+        self.apply_stroke_color(&color.to_css(), color)?;
+        self.notifier.sync();
         Ok(())
     }
 
     pub fn set_straight_line(&mut self) -> Result<(), JsValue> {
-        *self.mode.borrow_mut() = CurrentMode::StraightLine;
-        Ok(())
+        self.set_mode(CurrentMode::StraightLine)
     }
 
     pub fn set_circle(&mut self) -> Result<(), JsValue> {
-        *self.mode.borrow_mut() = CurrentMode::Circle;
+        self.set_mode(CurrentMode::Circle)
+    }
+
+    pub fn set_rectangle(&mut self) -> Result<(), JsValue> {
+        self.set_mode(CurrentMode::Rectangle)
+    }
+
+    pub fn set_rounded_rectangle(&mut self) -> Result<(), JsValue> {
+        self.set_mode(CurrentMode::RoundedRectangle)
+    }
+
+    pub fn set_ellipse(&mut self) -> Result<(), JsValue> {
+        self.set_mode(CurrentMode::Ellipse)
+    }
+
+    /// Picks shapes kept on vector layers: clicking takes the topmost one
+    /// under the pointer on the active layer and dragging moves it. The
+    /// stroke and shape settings then restyle it and Delete removes it.
+    pub fn set_object_select(&mut self) -> Result<(), JsValue> {
+        self.set_mode(CurrentMode::ObjectSelect(None))
+    }
+
+    /// Removes the picked shape.
+    pub fn delete_selected_shape(&self) -> Result<(), JsValue> {
+        let mut mode = self.mode.borrow_mut();
+        delete_picked_shape(&mut mode, &mut self.layers.borrow_mut(), &self.history)
+            .ok_or_else(|| JsValue::from_str("no shape is selected"))?;
+        drop(mode);
+        self.composite()?;
+        self.draw_overlay()?;
+        self.notifier.sync();
         Ok(())
     }
 
+    /// Brings the picked shape one step closer to the top of its layer.
+    pub fn raise_selected_shape(&self) -> Result<(), JsValue> {
+        self.reorder_picked_shape(|index| index + 1)
+    }
+
+    /// Sends the picked shape one step closer to the bottom of its layer.
+    pub fn lower_selected_shape(&self) -> Result<(), JsValue> {
+        self.reorder_picked_shape(|index| index.saturating_sub(1))
+    }
+
+    /// Sets how shapes are painted: "outline", "filled" or "outline-fill".
+    pub fn set_shape_style(&self, style: String) -> Result<(), JsValue> {
+        let style = FillStyle::from_name(&style)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown shape style {}", style)))?;
+        self.shape_settings.borrow_mut().style = style;
+        self.update_picked_shape(|shape| shape.style = style)
+    }
+
+    /// Sets the colour shapes are filled with. Until called, shapes are
+    /// filled with the stroke colour.
+    pub fn set_shape_fill_color(&self, color: String) -> Result<(), JsValue> {
+        let color: Color = color.parse()?;
+        let css = color.to_css();
+        self.shape_settings.borrow_mut().fill_color = Some(color);
+        self.update_picked_shape(|shape| shape.fill = css.clone())
+    }
+
+    pub fn set_corner_radius(&self, radius: f64) {
+        self.shape_settings.borrow_mut().corner_radius = radius;
+    }
+
     pub fn set_default_stroke(&mut self) -> Result<(), JsValue> {
-        *self.mode.borrow_mut() = CurrentMode::Default;
-        Ok(())
+        self.set_mode(CurrentMode::Default)
+    }
+
+    /// Erases with pen strokes, to transparency or to white on an opaque
+    /// background.
+    pub fn set_eraser(&mut self) -> Result<(), JsValue> {
+        self.set_mode(CurrentMode::Eraser)
     }
 
     pub fn set_fill(&mut self) -> Result<(), JsValue> {
-        *self.mode.borrow_mut() = CurrentMode::Fill;
-        Ok(())
+        self.set_mode(CurrentMode::Fill)
+    }
+
+    /// Sets how far, as an RGBA distance, a colour may be from the clicked
+    /// one and still get filled.
+    pub fn set_fill_tolerance(&self, tolerance: f64) {
+        self.update_fill_options(|options| options.tolerance = tolerance.max(0.0));
+    }
+
+    /// When false the fill replaces every similar pixel of the layer instead
+    /// of only the connected area.
+    pub fn set_fill_contiguous(&self, contiguous: bool) {
+        self.update_fill_options(|options| options.contiguous = contiguous);
+    }
+
+    pub fn set_fill_antialias(&self, antialias: bool) {
+        self.update_fill_options(|options| options.antialias = antialias);
+    }
+
+    /// Sets the stroke colour to the one under the pointer on click, as the
+    /// visible layers combine it. The stroke opacity is kept, and a
+    /// `color-changed` event reports the new colour.
+    pub fn set_color_picker(&mut self) -> Result<(), JsValue> {
+        self.set_mode(CurrentMode::ColorPicker)
+    }
+
+    /// Makes the colour picker average a `size` x `size` square around the
+    /// clicked pixel instead of taking that pixel alone.
+    pub fn set_color_picker_size(&self, size: u32) {
+        self.picker_size.set(size.max(1));
+    }
+
+    /// Calls `callback` with the hex code of the stroke colour whenever it
+    /// changes, the colour picker taking one included, or stops calling
+    /// anything when given `undefined`.
+    ///
+    /// Deprecated, `on("color-changed", ...)` does the same and also
+    /// reports the opacity.
+    pub fn set_on_color_picked(&self, callback: Option<js_sys::Function>) {
+        if let Some(previous) = self.on_color_picked.borrow_mut().take() {
+            self.notifier.unsubscribe("color-changed", &previous);
+        }
+        if let Some(callback) = callback {
+            let forward = Closure::<dyn Fn(JsValue)>::new(move |payload: JsValue| {
+                if let Ok(color) = Reflect::get(&payload, &"color".into()) {
+                    let _ = callback.call1(&JsValue::NULL, &color);
+                }
+            })
+            .into_js_value()
+            .unchecked_into::<js_sys::Function>();
+            let _ = self.notifier.subscribe("color-changed", forward.clone());
+            *self.on_color_picked.borrow_mut() = Some(forward);
+        }
     }
 
     pub fn set_crop(&mut self) -> Result<(), JsValue> {
-        *self.mode.borrow_mut() = CurrentMode::Crop;
+        self.set_mode(CurrentMode::Crop)
+    }
+
+    /// Selects a freehand area traced while the pointer is down.
+    pub fn set_lasso(&mut self) -> Result<(), JsValue> {
+        self.set_mode(CurrentMode::Lasso(vec![]))
+    }
+
+    /// Selects a polygon, one corner per click. Clicking the first corner or
+    /// pressing Enter closes it, Backspace removes the last corner.
+    pub fn set_polygon(&mut self) -> Result<(), JsValue> {
+        self.set_mode(CurrentMode::Polygon(vec![]))
+    }
+
+    /// Selects the area around the clicked pixel with a colour similar to
+    /// it, using the fill tolerance, contiguous and anti-aliasing options.
+    /// Shift adds to the selection, alt subtracts from it and both keep
+    /// only the overlap.
+    pub fn set_magic_wand(&mut self) -> Result<(), JsValue> {
+        self.set_mode(CurrentMode::Wand)
+    }
+
+    pub fn select_none(&self) -> Result<(), JsValue> {
+        *self.selection.borrow_mut() = None;
+        self.viewport.get().clear(&self.get_top_context()?);
+        self.draw_overlay()?;
+        self.notifier.sync();
         Ok(())
     }
 
+    /// Lifts the selected pixels off the active layer to move or transform
+    /// them.
+    pub fn float_selection(&self) -> Result<(), JsValue> {
+        let mask = self
+            .selection
+            .borrow()
+            .clone()
+            .ok_or_else(|| JsValue::from_str("nothing is selected"))?;
+        let mut mode = self.mode.borrow_mut();
+        if let CurrentMode::CropPlace(..) = &*mode {
+            return Ok(());
+        }
+        if !mode.selects() {
+            *mode = CurrentMode::Crop;
+        }
+
+        let layers = self.layers.borrow();
+        lift_selection(
+            &mut mode,
+            &layers,
+            &self.history,
+            &self.selection,
+            mask,
+            false,
+        );
+        drop(mode);
+        drop(layers);
+        self.composite()?;
+        self.draw_overlay()?;
+        self.notifier.sync();
+        Ok(())
+    }
+
+    /// Types text where the canvas is clicked. Shift+Enter starts a new
+    /// line, Enter or clicking elsewhere keeps the text on the active layer
+    /// as an object, which clicking it opens again, until `flatten_text`.
+    /// Escape drops it.
     pub fn set_text(&mut self) -> Result<(), JsValue> {
-        let text_data = TextData {
-            content: String::from(""),
-            position: (0.0, 0.0),
-            font_size: 48,
-            font_type: "serif".to_owned(),
+        self.set_mode(CurrentMode::Text(TextData {
+            style: self.text_style.borrow().clone(),
+            session: None,
+        }))
+    }
+
+    /// Sets the font text is typed in, `size` in document pixels and
+    /// `weight` as in css, 400 for normal and 700 for bold.
+    pub fn set_font(
+        &self,
+        family: String,
+        size: f64,
+        weight: u32,
+        italic: bool,
+    ) -> Result<(), JsValue> {
+        if !(size > 0.0 && size.is_finite()) {
+            return Err(JsValue::from_str(&format!("bad font size {}", size)));
+        }
+        if !(1..=1000).contains(&weight) {
+            return Err(JsValue::from_str(&format!("bad font weight {}", weight)));
+        }
+        self.update_text_style(|style| {
+            style.family = family.clone();
+            style.size = size;
+            style.weight = weight;
+            style.italic = italic;
+        })
+    }
+
+    /// Sets how lines line up with the clicked point: "left", "center" or
+    /// "right".
+    pub fn set_text_align(&self, align: String) -> Result<(), JsValue> {
+        let align = TextAlign::from_name(&align)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown text alignment {}", align)))?;
+        self.update_text_style(|style| style.align = align)
+    }
+
+    /// Sets which line of the first row of text goes through the clicked
+    /// point, as the css names: "top", "hanging", "middle", "alphabetic",
+    /// "ideographic" or "bottom".
+    pub fn set_text_baseline(&self, baseline: String) -> Result<(), JsValue> {
+        let baseline = TextBaseline::from_name(&baseline)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown text baseline {}", baseline)))?;
+        self.update_text_style(|style| style.baseline = baseline)
+    }
+
+    /// Sets the distance between lines of text as a multiple of the font
+    /// size.
+    pub fn set_line_height(&self, line_height: f64) -> Result<(), JsValue> {
+        self.update_text_style(|style| style.line_height = line_height.max(0.5))
+    }
+
+    /// Outlines text with a `width` wide stroke of `color`. A width of 0
+    /// removes the outline.
+    pub fn set_text_outline(&self, width: f64, color: String) -> Result<(), JsValue> {
+        let outline = match width {
+            width if width > 0.0 => Some((width, color.parse::<Color>()?.to_css())),
+            _ => None,
         };
+        self.update_text_style(|style| style.outline = outline.clone())
+    }
+
+    /// Keeps the text being typed on its layer, as Enter does. It stays a
+    /// text object that the text tool can click to edit, move or restyle.
+    pub fn commit_text(&self) -> Result<(), JsValue> {
+        settle_text(
+            &mut self.mode.borrow_mut(),
+            &mut self.layers.borrow_mut(),
+            &self.history,
+        );
+        self.viewport.get().clear(&self.get_top_context()?);
+        self.composite()?;
+        self.notifier.sync();
+        Ok(())
+    }
+
+    /// Drops the text being typed, as Escape does. Text opened again goes
+    /// back as it was.
+    pub fn cancel_text(&self) -> Result<(), JsValue> {
+        drop_text(&mut self.mode.borrow_mut(), &mut self.layers.borrow_mut());
+        self.viewport.get().clear(&self.get_top_context()?);
+        self.composite()
+    }
 
-        let _ = self.top_layer.set_tab_index(1);
-        self.get_top_context()?.set_font(&text_data.format_font());
-        self.get_context()?.set_font(&text_data.format_font());
-        *self.mode.borrow_mut() = CurrentMode::Text(text_data);
+    /// Draws every text object into the pixels of its layer, after which it
+    /// can no longer be edited as text. Can be undone.
+    pub fn flatten_text(&self) -> Result<(), JsValue> {
+        let mut layers = self.layers.borrow_mut();
+        settle_text(&mut self.mode.borrow_mut(), &mut layers, &self.history);
+        let (width, height) = layers.size();
+        let mut entry = vec![];
+        for id in layers.ids() {
+            let texts = layers.take_texts(id);
+            let context = match layers.context(id) {
+                Some(context) if !texts.is_empty() => context,
+                _ => continue,
+            };
+            let rect = texts
+                .iter()
+                .filter_map(|text| text.rect(&context, width, height))
+                .fold(Rect::new(0, 0, 0, 0), |all, rect| all.union(&rect));
+            if !rect.is_empty() {
+                entry.push((id, Change::Pixels(context.read(&rect)?)));
+            }
+            for text in &texts {
+                text.draw(&context)?;
+            }
+            entry.push((id, Change::Texts(texts)));
+        }
+        // One step undoes the flattening of every layer.
+        if !entry.is_empty() {
+            self.history.borrow_mut().record_entry(entry);
+        }
+        drop(layers);
+        self.viewport.get().clear(&self.get_top_context()?);
+        self.composite()?;
+        self.notifier.sync();
         Ok(())
     }
 
+    /// Undoes the last change. Text being typed is kept first, so that is
+    /// what gets undone.
+    pub fn undo(&self) -> Result<(), JsValue> {
+        let mut layers = self.layers.borrow_mut();
+        settle_text(&mut self.mode.borrow_mut(), &mut layers, &self.history);
+        let mut history = self.history.borrow_mut();
+        if let Some(entry) = history.take_undo() {
+            history.push_redo(apply_entry(&mut layers, &entry)?);
+        }
+        drop(history);
+        drop(layers);
+        self.forget_picked_shape()?;
+        self.composite()?;
+        self.notifier.sync();
+        Ok(())
+    }
+
+    pub fn redo(&self) -> Result<(), JsValue> {
+        let mut layers = self.layers.borrow_mut();
+        settle_text(&mut self.mode.borrow_mut(), &mut layers, &self.history);
+        let mut history = self.history.borrow_mut();
+        if let Some(entry) = history.take_redo() {
+            history.push_undo(apply_entry(&mut layers, &entry)?);
+        }
+        drop(history);
+        drop(layers);
+        self.forget_picked_shape()?;
+        self.composite()?;
+        self.notifier.sync();
+        Ok(())
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.borrow().can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.borrow().can_redo()
+    }
+
+    /// Sets how many bytes of pixel data the undo/redo history may hold
+    /// before the oldest entries are dropped.
+    pub fn set_history_limit(&self, bytes: usize) {
+        self.history.borrow_mut().set_limit(bytes);
+    }
+
+    /// Renders the visible layers as a png data url, keeping transparency.
     pub fn export(&self) -> Result<String, JsValue> {
-        self.underlying_layer.to_data_url()
+        self.layers.borrow().flatten()?.to_data_url()
+    }
+
+    /// Exports the visible layers as an SVG document. Shapes and pen
+    /// strokes become SVG shapes and paths and text objects become
+    /// `<text>`, over the pixels of each layer embedded as an image. On
+    /// raster layers that only holds for the strokes and shapes painted
+    /// since anything else, like a fill or the eraser, last changed the
+    /// pixels, and still in the undo history; those before stay in the
+    /// image.
+    pub fn export_svg(&self) -> Result<String, JsValue> {
+        self.layers.borrow().to_svg(&self.history.borrow())
     }
 
     pub fn import(&self, canvas: HtmlCanvasElement) -> Result<(), JsValue> {
-        self.get_context()?
-            .draw_image_with_html_canvas_element(&canvas, 0.0, 0.0)
+        let layers = self.layers.borrow();
+        let context = layers.active_context();
+        let (width, height) = layers.size();
+        let rect = Rect::new(0, 0, width, height).intersect(&Rect::new(
+            0,
+            0,
+            canvas.width(),
+            canvas.height(),
+        ));
+        if !rect.is_empty() {
+            self.history
+                .borrow_mut()
+                .record(layers.active_id(), context.read(&rect)?);
+        }
+
+        context.draw_image_with_html_canvas_element(&canvas, 0.0, 0.0)?;
+        layers.composite(&self.get_context()?, &self.viewport.get())?;
+        drop(layers);
+        self.notifier.sync();
+        Ok(())
+    }
+
+    /// Applies a filter to the floating crop selection if there is one,
+    /// otherwise to the selected part of the active layer, or all of it
+    /// when nothing is selected. `params` is an object such as
+    /// `{ radius: 4 }`, missing values taking their defaults.
+    pub fn apply_filter(&self, name: String, params: JsValue) -> Result<(), JsValue> {
+        let filter = Filter::from_name(&name, |key| {
+            Reflect::get(&params, &key.into())
+                .ok()
+                .and_then(|value| value.as_f64())
+        })
+        .ok_or_else(|| JsValue::from_str(&format!("unknown filter {}", name)))?;
+
+        if let CurrentMode::CropPlace(region, ..) = &mut *self.mode.borrow_mut() {
+            filter.apply(&mut region.data, region.rect.width, region.rect.height);
+        }
+        if let CurrentMode::CropPlace(..) = &*self.mode.borrow() {
+            return self.draw_overlay();
+        }
+
+        let layers = self.layers.borrow();
+        let mut context = layers.active_context();
+        let (width, height) = layers.size();
+        let full = Rect::new(0, 0, width, height);
+        let mut region = context.read(&full)?;
+        // Filtering everything lets blurs pull in pixels from around the
+        // selection.
+        filter.apply(&mut region.data, width, height);
+
+        let (original, region) = match &*self.selection.borrow() {
+            Some(mask) => {
+                let mut changed = region.crop(&mask.rect);
+                let original = context.read(&mask.rect)?;
+                mask.mix(&original, &mut changed);
+                (original, changed)
+            }
+            None => (context.read(&full)?, region),
+        };
+        self.history
+            .borrow_mut()
+            .record(layers.active_id(), original);
+        context.write(&region)?;
+        layers.composite(&self.get_context()?, &self.viewport.get())?;
+        drop(layers);
+        self.notifier.sync();
+        Ok(())
+    }
+
+    /// Copies the floating selection, or the selected area of the active
+    /// layer.
+    pub fn copy(&self) -> Result<(), JsValue> {
+        *self.clipboard.borrow_mut() = Some(self.selected_region()?);
+        Ok(())
+    }
+
+    /// Copies the selection and removes it from the layer.
+    pub fn cut(&self) -> Result<(), JsValue> {
+        self.copy()?;
+
+        let mut mode = self.mode.borrow_mut();
+        if let CurrentMode::CropPlace(..) = &*mode {
+            // Its area was already emptied when the selection got lifted.
+            *mode = SelectionTool::of(&mode).idle();
+            drop(mode);
+            self.viewport.get().clear(&self.get_top_context()?);
+            self.notifier.sync();
+            return Ok(());
+        }
+
+        let layers = self.layers.borrow();
+        if let Some(mask) = &*self.selection.borrow() {
+            let context = layers.active_context();
+            record_region(&self.history, layers.active_id(), &context, &mask.rect);
+            clear_area(&layers, layers.active_id(), &context, mask);
+        }
+        drop(mode);
+        layers.composite(&self.get_context()?, &self.viewport.get())?;
+        drop(layers);
+        self.notifier.sync();
+        Ok(())
+    }
+
+    /// Floats the last copied selection, to be placed with a click.
+    pub fn paste(&self) -> Result<(), JsValue> {
+        let region = self
+            .clipboard
+            .borrow()
+            .clone()
+            .ok_or_else(|| JsValue::from_str("clipboard is empty"))?;
+        float_region(
+            &mut self.mode.borrow_mut(),
+            &self.layers.borrow(),
+            &self.history,
+            &self.selection,
+            region,
+        );
+        self.composite()?;
+        self.draw_overlay()?;
+        self.notifier.sync();
+        Ok(())
+    }
+
+    /// Floats a copy of the selection, leaving the original in place.
+    pub fn duplicate(&self) -> Result<(), JsValue> {
+        let region = self.selected_region()?;
+        float_region(
+            &mut self.mode.borrow_mut(),
+            &self.layers.borrow(),
+            &self.history,
+            &self.selection,
+            region,
+        );
+        self.composite()?;
+        self.draw_overlay()?;
+        self.notifier.sync();
+        Ok(())
+    }
+
+    pub fn flip_selection_horizontal(&self) -> Result<(), JsValue> {
+        self.update_floating(|transform| transform.flip_horizontal())
+    }
+
+    pub fn flip_selection_vertical(&self) -> Result<(), JsValue> {
+        self.update_floating(|transform| transform.flip_vertical())
+    }
+
+    pub fn rotate_selection(&self, degrees: f64) -> Result<(), JsValue> {
+        self.update_floating(|transform| transform.rotation += degrees.to_radians())
+    }
+
+    /// Draws the floating selection into the active layer.
+    pub fn place_selection(&self) -> Result<(), JsValue> {
+        settle_floating(
+            &mut self.mode.borrow_mut(),
+            &self.layers.borrow(),
+            &self.history,
+            &self.selection,
+        );
+        self.viewport.get().clear(&self.get_top_context()?);
+        self.composite()?;
+        self.notifier.sync();
+        Ok(())
+    }
+
+    /// Copies the selection and puts it on the system clipboard as a png.
+    pub fn copy_to_system_clipboard(&self) -> Result<Promise, JsValue> {
+        let region = self.selected_region()?;
+        let written = clipboard::write_image(&region)?;
+        *self.clipboard.borrow_mut() = Some(region);
+        Ok(written)
+    }
+
+    /// Floats an image read from the system clipboard, like `paste`.
+    pub fn paste_from_system_clipboard(&self) -> Result<Promise, JsValue> {
+        let display = self.get_context()?;
+        let mode = self.mode.clone();
+        let history = self.history.clone();
+        let layers = self.layers.clone();
+        let viewport = self.viewport.clone();
+        let selection = self.selection.clone();
+        let copied = self.clipboard.clone();
+        let top_context = self.get_top_context()?;
+        let notifier = self.notifier.clone();
+
+        Ok(future_to_promise(async move {
+            let region = clipboard::read_image().await?;
+            *copied.borrow_mut() = Some(region.clone());
+
+            {
+                let layers = layers.borrow();
+                let mut mode = mode.borrow_mut();
+                float_region(&mut mode, &layers, &history, &selection, region);
+                layers.composite(&display, &viewport.get())?;
+                draw_overlay(&mode, &selection.borrow(), &top_context, &viewport.get());
+            }
+            notifier.sync();
+            Ok(JsValue::UNDEFINED)
+        }))
+    }
+
+    /// Saves the document with its layers, tool settings and palette in the
+    /// native project format.
+    pub fn save_project(&self) -> Result<Vec<u8>, JsValue> {
+        let layers = self.layers.borrow();
+        let (width, height) = layers.size();
+        let settings = self.shape_settings.borrow();
+
+        let project = Project {
+            width,
+            height,
+            transparent: layers.is_transparent(),
+            layers: layers.save()?,
+            active_layer: layers.active_id(),
+            tools: ToolSettings {
+                stroke_width: layers.active_context().line_width(),
+                stroke_color: self.current_color.borrow().value(),
+                shape_style: settings.style,
+                shape_fill: settings.fill_color.as_ref().map(Color::value),
+                corner_radius: settings.corner_radius,
+                fill: self.fill_options.get(),
+            },
+            palette: self.palette.borrow().iter().map(Color::value).collect(),
+            history_limit: self.history.borrow().limit() as u64,
+        };
+
+        Ok(project.encode())
+    }
+
+    /// Replaces the document with one saved by `save_project`. Undo history
+    /// starts over.
+    pub fn load_project(&self, bytes: &[u8]) -> Result<(), JsValue> {
+        let project = Project::decode(bytes)?;
+        let (width, height) = (project.width, project.height);
+        *self.layers.borrow_mut() = LayerStack::load(
+            width,
+            height,
+            &project.layers,
+            project.active_layer,
+            project.transparent,
+        )?;
+        // Before the tool settings below would restyle it.
+        self.forget_picked_shape()?;
+
+        {
+            let mut history = self.history.borrow_mut();
+            history.clear();
+            // Limits past what wasm can address mean no limit.
+            history.set_limit(usize::try_from(project.history_limit).unwrap_or(usize::MAX));
+        }
+
+        let tools = project.tools;
+        let (r, g, b, a) = tools.stroke_color;
+        self.set_stroke_width(tools.stroke_width)?;
+        let color = Color::Custom(r, g, b, a);
+        self.apply_stroke_color(&color.to_css(), color)?;
+
+        *self.shape_settings.borrow_mut() = ShapeSettings {
+            style: tools.shape_style,
+            fill_color: tools
+                .shape_fill
+                .map(|(r, g, b, a)| Color::Custom(r, g, b, a)),
+            corner_radius: tools.corner_radius,
+        };
+        self.fill_options.set(tools.fill);
+        *self.palette.borrow_mut() = project
+            .palette
+            .into_iter()
+            .map(|(r, g, b, a)| Color::Custom(r, g, b, a))
+            .collect();
+
+        // Drop any preview or floating selection from the old document.
+        let mut mode = self.mode.borrow_mut();
+        match &*mode {
+            CurrentMode::CropPlace(..) | CurrentMode::Lasso(_) | CurrentMode::Polygon(_) => {
+                *mode = SelectionTool::of(&mode).idle()
+            }
+            CurrentMode::Text(value) => {
+                *mode = CurrentMode::Text(TextData {
+                    session: None,
+                    ..value.clone()
+                });
+            }
+            _ => (),
+        }
+        drop(mode);
+        *self.selection.borrow_mut() = None;
+        self.viewport.get().clear(&self.get_top_context()?);
+
+        self.setup_initial_canvas()?;
+        self.update_viewport(|viewport| {
+            viewport.set_document_size(width, height);
+            viewport.zoom_to_fit();
+        })?;
+        self.notifier.sync();
+        Ok(())
+    }
+
+    /// Sets the swatches saved along with the project.
+    pub fn set_palette(&self, colors: Vec<String>) -> Result<(), JsValue> {
+        *self.palette.borrow_mut() = colors
+            .iter()
+            .map(|color| color.parse())
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    pub fn palette(&self) -> Vec<String> {
+        self.palette.borrow().iter().map(Color::to_css).collect()
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.viewport.get().zoom
+    }
+
+    pub fn pan_x(&self) -> f64 {
+        self.viewport.get().pan_x
+    }
+
+    pub fn pan_y(&self) -> f64 {
+        self.viewport.get().pan_y
+    }
+
+    /// Zooms to `factor` keeping the document point under the on-screen
+    /// point (`x`, `y`) still.
+    pub fn zoom_to(&self, x: f64, y: f64, factor: f64) -> Result<(), JsValue> {
+        self.update_viewport(|viewport| viewport.zoom_to(x, y, factor))
+    }
+
+    pub fn zoom_to_fit(&self) -> Result<(), JsValue> {
+        self.update_viewport(|viewport| viewport.zoom_to_fit())
+    }
+
+    pub fn set_pan(&self, x: f64, y: f64) -> Result<(), JsValue> {
+        self.update_viewport(|viewport| {
+            viewport.pan_x = x;
+            viewport.pan_y = y;
+        })
+    }
+
+    /// Adds an empty layer above the active one and makes it active. Can
+    /// be undone.
+    pub fn add_layer(&self, name: String) -> Result<u32, JsValue> {
+        let id = self.layers.borrow_mut().add(name)?;
+        self.history
+            .borrow_mut()
+            .record_entry(vec![(id, Change::Layer(None))]);
+        self.composite()?;
+        self.notifier.sync();
+        Ok(id)
+    }
+
+    /// Adds a layer above the active one and makes it active, keeping the
+    /// shapes drawn on it as objects that `set_object_select` can pick
+    /// later on. They are painted into the pixels once it is merged down.
+    /// Can be undone.
+    pub fn add_vector_layer(&self, name: String) -> Result<u32, JsValue> {
+        let id = self.layers.borrow_mut().add_vector(name)?;
+        self.history
+            .borrow_mut()
+            .record_entry(vec![(id, Change::Layer(None))]);
+        self.composite()?;
+        self.notifier.sync();
+        Ok(id)
+    }
+
+    /// Removes a layer. Can be undone.
+    pub fn remove_layer(&self, id: u32) -> Result<(), JsValue> {
+        let removed = self.layers.borrow_mut().remove(id)?;
+        self.history
+            .borrow_mut()
+            .record_entry(vec![(id, Change::Layer(Some(removed)))]);
+        self.forget_picked_shape()?;
+        self.composite()?;
+        self.notifier.sync();
+        Ok(())
+    }
+
+    /// Moves a layer to `index` in the stack, 0 being the bottom.
+    pub fn move_layer(&self, id: u32, index: usize) -> Result<(), JsValue> {
+        self.layers.borrow_mut().reorder(id, index)?;
+        self.composite()?;
+        self.layers_modified();
+        Ok(())
+    }
+
+    pub fn rename_layer(&self, id: u32, name: String) -> Result<(), JsValue> {
+        self.layers.borrow_mut().rename(id, name)?;
+        self.layers_modified();
+        Ok(())
+    }
+
+    pub fn set_layer_visible(&self, id: u32, visible: bool) -> Result<(), JsValue> {
+        self.layers.borrow_mut().set_visible(id, visible)?;
+        self.composite()?;
+        self.layers_modified();
+        Ok(())
+    }
+
+    pub fn set_layer_opacity(&self, id: u32, opacity: f64) -> Result<(), JsValue> {
+        self.layers.borrow_mut().set_opacity(id, opacity)?;
+        self.composite()?;
+        self.layers_modified();
+        Ok(())
+    }
+
+    /// Merges a layer into the one below it. Can be undone.
+    pub fn merge_down(&self, id: u32) -> Result<(), JsValue> {
+        let mut layers = self.layers.borrow_mut();
+        let below = layers
+            .below(id)?
+            .ok_or_else(|| JsValue::from_str("no layer below to merge into"))?;
+        let entry = vec![
+            (below, Change::Layer(Some(layers.snapshot(below)?))),
+            (id, Change::Layer(Some(layers.snapshot(id)?))),
+        ];
+        layers.merge_down(id)?;
+        drop(layers);
+        self.history.borrow_mut().record_entry(entry);
+        self.forget_picked_shape()?;
+        self.composite()?;
+        self.notifier.sync();
+        Ok(())
+    }
+
+    pub fn set_active_layer(&self, id: u32) -> Result<(), JsValue> {
+        self.layers.borrow_mut().set_active(id)
+    }
+
+    pub fn active_layer(&self) -> u32 {
+        self.layers.borrow().active_id()
+    }
+
+    /// Lists the layers bottom first as
+    /// `{ id, name, visible, opacity, vector, active }`.
+    pub fn layers(&self) -> Result<Array, JsValue> {
+        self.layers.borrow().to_js()
     }
 }
 
 impl Canvas {
+    fn create(
+        height: u32,
+        width: u32,
+        transparent: bool,
+        container: Option<Element>,
+    ) -> Result<Canvas, JsValue> {
+        let document = get_document()?;
+        let container = match container {
+            Some(container) => container,
+            None => document
+                .get_element_by_id(DEFAULT_CONTAINER)
+                .ok_or_else(|| {
+                    JsValue::from_str(&format!("no element with id {}", DEFAULT_CONTAINER))
+                })?,
+        };
+
+        // The top canvas takes the input and overlays the display canvas
+        // inside a wrapper of their size.
+        let root = document.create_element("div")?.dyn_into::<HtmlElement>()?;
+        root.style().set_property("position", "relative")?;
+        root.style().set_property("display", "inline-block")?;
+        let (client_canvas, _) = create_canvas(width, height)?;
+        client_canvas.style().set_property("display", "block")?;
+        let (top_canvas, _) = create_canvas(width, height)?;
+        top_canvas.style().set_property("position", "absolute")?;
+        top_canvas.style().set_property("left", "0")?;
+        top_canvas.style().set_property("top", "0")?;
+        let text_input = document
+            .create_element("textarea")?
+            .dyn_into::<HtmlTextAreaElement>()?;
+        for (name, value) in [
+            ("position", "absolute"),
+            ("opacity", "0"),
+            ("width", "1px"),
+            ("padding", "0"),
+            ("border", "0"),
+            ("resize", "none"),
+            ("overflow", "hidden"),
+            ("pointer-events", "none"),
+        ]
+        .iter()
+        {
+            text_input.style().set_property(name, value)?;
+        }
+        text_input.set_tab_index(-1);
+        let mode = Rc::new(RefCell::new(CurrentMode::Default));
+        let current_color = Rc::new(RefCell::new(Color::Black));
+        let history = Rc::new(RefCell::new(History::new(DEFAULT_HISTORY_LIMIT)));
+        let selection = Rc::new(RefCell::new(None));
+        let notifier = Notifier::new(&mode, &selection, &history, &current_color);
+        let canvas = Canvas {
+            root,
+            underlying_layer: client_canvas,
+            top_layer: top_canvas,
+            text_input,
+            mode,
+            current_color,
+            history,
+            layers: Rc::new(RefCell::new(LayerStack::new(width, height, transparent)?)),
+            shape_settings: Rc::new(RefCell::new(ShapeSettings::default())),
+            text_style: RefCell::new(TextStyle::default()),
+            fill_options: Rc::new(Cell::new(FillOptions::default())),
+            viewport: Rc::new(Cell::new(Viewport::new(width, height, width, height))),
+            palette: Rc::new(RefCell::new(vec![])),
+            selection,
+            clipboard: Rc::new(RefCell::new(None)),
+            picker_size: Rc::new(Cell::new(1)),
+            on_color_picked: RefCell::new(None),
+            notifier,
+            listeners: RefCell::new(Listeners::default()),
+        };
+
+        // Keep touch and pen input on the canvas instead of scrolling the page.
+        canvas
+            .top_layer
+            .style()
+            .set_property("touch-action", "none")?;
+        // Focusable so typing and selection nudging reach its key handler.
+        canvas.top_layer.set_tab_index(1);
+
+        canvas.root.append_child(&canvas.underlying_layer)?;
+        canvas.root.append_child(&canvas.top_layer)?;
+        canvas.root.append_child(&canvas.text_input)?;
+        container.append_child(&canvas.root)?;
+
+        canvas.setup_initial_canvas()?;
+        canvas.setup_modes()?;
+
+        Ok(canvas)
+    }
+
     fn get_context(&self) -> Result<CanvasRenderingContext2d, js_sys::Object> {
         self.underlying_layer
             .get_context("2d")?
@@ -228,310 +1375,745 @@ impl Canvas {
         let top_context = self.get_top_context()?;
         top_context.set_line_cap("round");
 
-        let context = self.get_context()?;
-        context.set_line_cap("round");
-        context.set_fill_style(&JsValue::from_str("white"));
-        context.fill_rect(0.0, 0.0, self.width as f64, self.height as f64);
+        for context in self.layers.borrow().contexts() {
+            context.set_line_cap("round");
+        }
+
+        self.composite()
+    }
+
+    fn selected_region(&self) -> Result<Region, JsValue> {
+        if let CurrentMode::CropPlace(region, ..) = &*self.mode.borrow() {
+            return Ok(region.clone());
+        }
 
+        let selection = self.selection.borrow();
+        let mask = selection
+            .as_ref()
+            .ok_or_else(|| JsValue::from_str("nothing is selected"))?;
+        let mut region = self.layers.borrow().active_context().read(&mask.rect)?;
+        mask.keep(&mut region);
+        Ok(region)
+    }
+
+    fn update_floating(&self, update: impl FnOnce(&mut Transform)) -> Result<(), JsValue> {
+        match &mut *self.mode.borrow_mut() {
+            CurrentMode::CropPlace(_, transform, _) => update(transform),
+            _ => return Err(JsValue::from_str("no floating selection")),
+        }
+        self.draw_overlay()?;
+        self.notifier.sync();
         Ok(())
     }
 
-    fn setup_modes(&self) -> Result<(), JsValue> {
-        let context = Rc::new(self.get_context()?);
-        let top_context = Rc::new(self.get_top_context()?);
+    /// Switches tools, placing any floating selection or text being typed
+    /// first.
+    fn set_mode(&self, mode: CurrentMode) -> Result<(), JsValue> {
+        let mut current = self.mode.borrow_mut();
+        let mut layers = self.layers.borrow_mut();
+        settle_floating(&mut current, &layers, &self.history, &self.selection);
+        settle_text(&mut current, &mut layers, &self.history);
+        drop(layers);
+        *current = mode;
+        drop(current);
+
+        self.viewport.get().clear(&self.get_top_context()?);
+        self.composite()?;
+        self.draw_overlay()?;
+        self.notifier.sync();
+        Ok(())
+    }
 
-        let pressed = Rc::new(Cell::new(false));
+    /// Reports a change to the layer stack itself, which undo history
+    /// doesn't track.
+    fn layers_modified(&self) {
+        let history = self.history.borrow();
+        let event = Event::DocumentModified(history.can_undo(), history.can_redo());
+        drop(history);
+        self.notifier.sync();
+        self.notifier.emit(event);
+    }
 
-        let line_start_x = Rc::new(Cell::new(0.0));
-        let line_start_y = Rc::new(Cell::new(0.0));
+    fn draw_overlay(&self) -> Result<(), JsValue> {
+        draw_overlay(
+            &self.mode.borrow(),
+            &self.selection.borrow(),
+            &self.get_top_context()?,
+            &self.viewport.get(),
+        );
+        Ok(())
+    }
 
+    fn apply_stroke_color(&self, css: &str, color: Color) -> Result<(), JsValue> {
+        apply_stroke_color(
+            &self.layers.borrow(),
+            &self.get_top_context()?,
+            &self.current_color,
+            css,
+            color,
+        );
+        if let CurrentMode::Text(TextData {
+            session: Some(session),
+            ..
+        }) = &mut *self.mode.borrow_mut()
         {
-            let context = context.clone();
-            let top_context = top_context.clone();
-            let pressed = pressed.clone();
-            let line_start_x = line_start_x.clone();
-            let line_start_y = line_start_y.clone();
-            let mode = self.mode.clone();
-            let height = self.height;
-            let width = self.width;
-            let color = self.current_color.clone();
-
-            let closure = Closure::<dyn FnMut(_)>::new(move |event: web_sys::MouseEvent| {
-                let offset_x = event.offset_x() as f64;
-                let offset_y = event.offset_y() as f64;
-                match &*mode.borrow() {
-                    CurrentMode::Default => {
-                        context.begin_path();
-                        context.move_to(offset_x, offset_y);
-                        pressed.set(true);
-                    }
-                    CurrentMode::StraightLine => {
-                        top_context.begin_path();
-                        line_start_x.set(offset_x);
-                        line_start_y.set(offset_y);
-                        top_context.move_to(offset_x, offset_y);
-                        pressed.set(true);
-                    }
-                    CurrentMode::Circle => {
-                        top_context.begin_path();
-                        line_start_x.set(offset_x);
-                        line_start_y.set(offset_y);
-                        pressed.set(true);
-                    }
-                    CurrentMode::Fill => {
-                        let _ = fill(
-                            context.clone(),
-                            offset_x as usize,
-                            offset_y as usize,
-                            width,
-                            height,
-                            &color.borrow(),
-                        );
-                    }
-                    CurrentMode::Crop => {
-                        let _ = top_context.set_line_width(1.0);
-                        let _ = top_context.set_stroke_style(&JsValue::from_str("black"));
-                        let lines: Array = vec![6].into_iter().map(JsValue::from).collect();
-                        let _ = top_context.set_line_dash(&lines);
-                        line_start_x.set(offset_x);
-                        line_start_y.set(offset_y);
-                        top_context.begin_path();
-                        pressed.set(true);
-                    }
-                    CurrentMode::CropPlace(value) => {
-                        top_context.begin_path();
-                        let _ = top_context.put_image_data(&value, offset_x, offset_y);
-                        pressed.set(true);
-                    }
-                    _ => (),
-                }
-            });
+            session.object.color = css.to_owned();
+        }
+        self.update_picked_shape(|shape| shape.stroke = css.to_owned())
+    }
 
-            self.top_layer
-                .add_event_listener_with_callback("mousedown", closure.as_ref().unchecked_ref())?;
-            closure.forget();
+    /// Changes the text style, restyling any text being typed.
+    fn update_text_style(&self, update: impl Fn(&mut TextStyle)) -> Result<(), JsValue> {
+        update(&mut self.text_style.borrow_mut());
+        let mut mode = self.mode.borrow_mut();
+        if let CurrentMode::Text(value) = &mut *mode {
+            update(&mut value.style);
+            if let Some(session) = &mut value.session {
+                update(&mut session.object.style);
+            }
         }
-        {
-            let context = context.clone();
-            let top_context = top_context.clone();
-            let pressed = pressed.clone();
-            let mode = self.mode.clone();
-            let line_start_x = line_start_x.clone();
-            let line_start_y = line_start_y.clone();
-            let height = self.height;
-            let width = self.width;
-
-            let closure = Closure::<dyn FnMut(_)>::new(move |event: web_sys::MouseEvent| {
-                if pressed.get() {
-                    let offset_x = event.offset_x() as f64;
-                    let offset_y = event.offset_y() as f64;
-                    match &*mode.borrow() {
-                        CurrentMode::Default => {
-                            context.line_to(offset_x, offset_y);
-                            context.stroke();
-                            context.begin_path();
-                            context.move_to(offset_x, offset_y);
-                        }
-                        CurrentMode::StraightLine => {
-                            top_context.clear_rect(0.0, 0.0, width as f64, height as f64);
-                            top_context.line_to(offset_x, offset_y);
-                            top_context.stroke();
-                            top_context.begin_path();
-                            top_context.move_to(line_start_x.get(), line_start_y.get());
-                        }
-                        CurrentMode::Circle => {
-                            top_context.clear_rect(0.0, 0.0, width as f64, height as f64);
-                            let radius = two_point_distance(
-                                line_start_x.get() as f64,
-                                line_start_y.get() as f64,
-                                offset_x,
-                                offset_y,
-                            );
-                            let _ = top_context.arc(offset_x, offset_y, radius, 0.0, 2.0 * PI);
-                            top_context.stroke();
-                            top_context.begin_path();
-                        }
-                        CurrentMode::Crop => {
-                            top_context.clear_rect(0.0, 0.0, width as f64, height as f64);
-
-                            let x = define_postition(line_start_x.get(), offset_x);
-                            let y = define_postition(line_start_y.get(), offset_y);
-                            let w = define_distance(line_start_x.get(), offset_x);
-                            let h = define_distance(line_start_y.get(), offset_y);
-
-                            let _ = top_context.rect(x, y, w, h);
-
-                            top_context.stroke();
-                            top_context.begin_path();
-                        }
-                        CurrentMode::CropPlace(value) => {
-                            top_context.clear_rect(0.0, 0.0, width as f64, height as f64);
-                            top_context.stroke();
-                            let _ = top_context.put_image_data(&value, offset_x, offset_y);
-                            top_context.begin_path();
-                        }
-                        _ => (),
-                    }
-                }
-            });
+        place_text_input(
+            &self.text_input,
+            &mode,
+            &self.get_top_context()?,
+            &self.viewport.get(),
+        );
+        drop(mode);
+        self.draw_overlay()
+    }
 
-            self.top_layer
-                .add_event_listener_with_callback("mousemove", closure.as_ref().unchecked_ref())?;
-            closure.forget();
+    /// Changes the shape picked with `set_object_select`, if any.
+    fn update_picked_shape(&self, update: impl FnOnce(&mut ShapeObject)) -> Result<(), JsValue> {
+        let mut mode = self.mode.borrow_mut();
+        let mut layers = self.layers.borrow_mut();
+        let before = picked_layer_shapes(&mode, &layers);
+        edit_picked_shape(&mut mode, &mut layers, update);
+        let changed = before
+            .is_some_and(|(layer, before)| record_shapes(&layers, &self.history, layer, before));
+        drop(layers);
+        drop(mode);
+        if changed {
+            self.composite()?;
+            self.notifier.sync();
         }
-        {
-            let mode = self.mode.clone();
-            let context = context.clone();
-            let top_context = top_context.clone();
-            let height = self.height;
-            let width = self.width;
-            let closure = Closure::<dyn FnMut(_)>::new(move |event: web_sys::MouseEvent| {
-                pressed.set(false);
-                let mut mode = mode.borrow_mut();
-                match &*mode {
-                    CurrentMode::Default => {
-                        context.line_to(event.offset_x() as f64, event.offset_y() as f64);
-                        context.stroke();
-                    }
-                    CurrentMode::StraightLine => {
-                        context.begin_path();
-                        context.move_to(line_start_x.get(), line_start_y.get());
-                        context.line_to(event.offset_x() as f64, event.offset_y() as f64);
-                        context.stroke();
-                        top_context.clear_rect(0.0, 0.0, width as f64, height as f64);
-                    }
-                    CurrentMode::Circle => {
-                        context.begin_path();
-                        let radius = two_point_distance(
-                            line_start_x.get() as f64,
-                            line_start_y.get() as f64,
-                            event.offset_x() as f64,
-                            event.offset_y() as f64,
-                        );
-                        let _ = context.arc(
-                            event.offset_x() as f64,
-                            event.offset_y() as f64,
-                            radius,
-                            0.0,
-                            2.0 * PI,
-                        );
-                        context.stroke();
-                        top_context.clear_rect(0.0, 0.0, width as f64, height as f64);
-                    }
-                    CurrentMode::Crop => {
-                        let _ = top_context.set_line_dash(&Array::new());
+        self.draw_overlay()
+    }
 
-                        let x = define_postition(line_start_x.get(), event.offset_x() as f64);
-                        let y = define_postition(line_start_y.get(), event.offset_y() as f64);
-                        let w = define_distance(line_start_x.get(), event.offset_x() as f64);
-                        let h = define_distance(line_start_y.get(), event.offset_y() as f64);
+    /// Moves the picked shape among the shapes of its layer, to the index
+    /// `to` gives for its current one.
+    fn reorder_picked_shape(&self, to: impl FnOnce(usize) -> usize) -> Result<(), JsValue> {
+        let mut mode = self.mode.borrow_mut();
+        let picked = match &mut *mode {
+            CurrentMode::ObjectSelect(Some(picked)) => picked,
+            _ => return Err(JsValue::from_str("no shape is selected")),
+        };
+        let mut layers = self.layers.borrow_mut();
+        let before = layers.shapes(picked.layer).map(<[_]>::to_vec);
+        if let Some(index) = layers.reorder_shape(picked.layer, picked.index, to(picked.index)) {
+            picked.index = index;
+        }
+        if let Some(before) = before {
+            record_shapes(&layers, &self.history, picked.layer, before);
+        }
+        drop(layers);
+        drop(mode);
+        self.composite()?;
+        self.notifier.sync();
+        Ok(())
+    }
 
-                        let image = get_content_inside_rect(context.clone(), x, y, w, h);
+    /// Lets go of the picked shape, e.g. once its layer is gone.
+    fn forget_picked_shape(&self) -> Result<(), JsValue> {
+        if let CurrentMode::ObjectSelect(picked) = &mut *self.mode.borrow_mut() {
+            *picked = None;
+        }
+        self.draw_overlay()
+    }
 
-                        if let Ok(value) = image {
-                            let _ = top_context.put_image_data(&value, x, y);
-                            *mode = CurrentMode::CropPlace(value);
+    fn update_fill_options(&self, update: impl FnOnce(&mut FillOptions)) {
+        let mut options = self.fill_options.get();
+        update(&mut options);
+        self.fill_options.set(options);
+    }
 
-                            let _ = context.fill_rect(x, y, w, h);
-                        }
-                        top_context.stroke();
-                    }
-                    CurrentMode::CropPlace(value) => {
-                        top_context.stroke();
-                        let _ = context.put_image_data(
-                            &value,
-                            event.offset_x() as f64,
-                            event.offset_y() as f64,
-                        );
-
-                        let _ = top_context.set_line_width(context.line_width());
-                        let _ = top_context.set_stroke_style(&context.stroke_style());
-
-                        *mode = CurrentMode::Crop;
-                        top_context.clear_rect(0.0, 0.0, width as f64, height as f64);
+    fn composite(&self) -> Result<(), JsValue> {
+        self.layers
+            .borrow()
+            .composite(&self.get_context()?, &self.viewport.get())
+    }
+
+    fn update_viewport(&self, update: impl FnOnce(&mut Viewport)) -> Result<(), JsValue> {
+        let mut viewport = self.viewport.get();
+        update(&mut viewport);
+        self.viewport.set(viewport);
+
+        viewport.apply(&self.get_top_context()?);
+        self.draw_overlay()?;
+        self.composite()
+    }
+
+    fn setup_modes(&self) -> Result<(), JsValue> {
+        let mut listeners = self.listeners.borrow_mut();
+        let document = get_document()?;
+        let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window"))?;
+        let input = Rc::new(Input {
+            mode: self.mode.clone(),
+            color: self.current_color.clone(),
+            history: self.history.clone(),
+            layers: self.layers.clone(),
+            viewport: self.viewport.clone(),
+            fill_options: self.fill_options.clone(),
+            shape_settings: self.shape_settings.clone(),
+            selection: self.selection.clone(),
+            picker_size: self.picker_size.clone(),
+            notifier: self.notifier.clone(),
+            top_layer: self.top_layer.clone(),
+            text_input: self.text_input.clone(),
+            display: self.get_context()?,
+            top_context: self.get_top_context()?,
+            pressed: Cell::new(false),
+            active_pointer: Cell::new(None),
+            space_held: Cell::new(false),
+            panning: Cell::new(None),
+            dragging: Cell::new(None),
+            moving_text: Cell::new(None),
+            moving_shape: RefCell::new(None),
+            line_start_x: Cell::new(0.0),
+            line_start_y: Cell::new(0.0),
+        });
+
+        {
+            let input = input.clone();
+            let closure = Closure::<dyn FnMut(_)>::new(notifying(
+                &self.notifier,
+                move |event: web_sys::PointerEvent| input.pointer_down(event),
+            ));
+            listeners.listen(&self.top_layer, "pointerdown", closure)?;
+        }
+        {
+            let input = input.clone();
+            let closure = Closure::<dyn FnMut(_)>::new(notifying(
+                &self.notifier,
+                move |event: web_sys::PointerEvent| input.pointer_move(event),
+            ));
+            listeners.listen(&self.top_layer, "pointermove", closure)?;
+        }
+        {
+            let input = input.clone();
+            let closure = Closure::<dyn FnMut(_)>::new(notifying(
+                &self.notifier,
+                move |event: web_sys::PointerEvent| input.release(&event),
+            ));
+            listeners.listen(&window, "pointerup", closure)?;
+        }
+        {
+            // A move without buttons held means the release happened where
+            // it couldn't be seen, like outside the browser window.
+            let input = input.clone();
+            let closure = Closure::<dyn FnMut(_)>::new(notifying(
+                &self.notifier,
+                move |event: web_sys::PointerEvent| {
+                    if event.buttons() == 0 {
+                        input.release(&event);
                     }
-                    CurrentMode::Text(value) => {
-                        *mode = CurrentMode::Text(TextData {
-                            content: value.content.clone(),
-                            position: (event.offset_x() as f64, event.offset_y() as f64),
-                            font_size: value.font_size,
-                            font_type: value.font_type.clone(),
-                        });
+                },
+            ));
+            listeners.listen(&self.top_layer, "pointermove", closure)?;
+        }
+        for name in ["pointercancel", "lostpointercapture"].iter() {
+            let input = input.clone();
+            let closure = Closure::<dyn FnMut(_)>::new(notifying(
+                &self.notifier,
+                move |event: web_sys::PointerEvent| {
+                    if input.active_pointer.get() == Some(event.pointer_id()) {
+                        input.cancel();
                     }
-                    _ => (),
-                }
+                },
+            ));
+            listeners.listen(&self.top_layer, name, closure)?;
+        }
+        {
+            let input = input.clone();
+            let closure = Closure::<dyn FnMut(_)>::new(notifying(
+                &self.notifier,
+                move |event: web_sys::KeyboardEvent| input.key_down(event),
+            ));
+            listeners.listen(&self.top_layer, "keydown", closure)?;
+        }
+        {
+            let input = input.clone();
+            let closure = Closure::<dyn FnMut(_)>::new(notifying(
+                &self.notifier,
+                move |event: web_sys::KeyboardEvent| input.text_key_down(event),
+            ));
+            listeners.listen(&self.text_input, "keydown", closure)?;
+        }
+        {
+            let input = input.clone();
+            let closure = Closure::<dyn FnMut(_)>::new(move |event: web_sys::InputEvent| {
+                input.before_input(event)
             });
-
-            self.top_layer
-                .add_event_listener_with_callback("mouseup", closure.as_ref().unchecked_ref())?;
-            closure.forget();
+            listeners.listen(&self.text_input, "beforeinput", closure)?;
         }
-
         {
-            let mode = self.mode.clone();
-            let height = self.height;
-            let width = self.width;
-            let color = self.current_color.clone();
+            let input = input.clone();
+            let closure = Closure::<dyn FnMut(_)>::new(move |event: web_sys::CompositionEvent| {
+                input.composition_update(event)
+            });
+            listeners.listen(&self.text_input, "compositionupdate", closure)?;
+        }
+        {
+            let input = input.clone();
+            let closure = Closure::<dyn FnMut(_)>::new(notifying(
+                &self.notifier,
+                move |event: web_sys::CompositionEvent| input.composition_end(event),
+            ));
+            listeners.listen(&self.text_input, "compositionend", closure)?;
+        }
+        {
+            let input = input.clone();
+            let closure =
+                Closure::<dyn FnMut(_)>::new(move |event: web_sys::WheelEvent| input.wheel(event));
+            listeners.listen(&self.top_layer, "wheel", closure)?;
+        }
+        {
+            let input = input.clone();
             let closure = Closure::<dyn FnMut(_)>::new(move |event: web_sys::KeyboardEvent| {
-                let mut mode = mode.borrow_mut();
-                match &*mode {
-                    CurrentMode::Text(value) => {
-                        let color_fill = &JsValue::from_str(&color.borrow().to_hex());
-                        top_context.set_fill_style(color_fill);
-
-                        let mut curr_content = value.content.clone();
-
-                        let key = event.key();
-                        match key.as_str() {
-                            "Enter" => {
-                                top_context.clear_rect(0.0, 0.0, width as f64, height as f64);
-                                context.set_fill_style(color_fill);
-                                let _ = context.fill_text(
-                                    &value.content,
-                                    value.position.0,
-                                    value.position.1,
-                                );
-                                context.set_fill_style(&JsValue::from_str("white"));
-                                *mode = CurrentMode::Text(value.append("".to_string()));
-                            }
-                            "Backspace" => {
-                                top_context.clear_rect(0.0, 0.0, width as f64, height as f64);
-                                curr_content.pop();
-                                let _ = top_context.fill_text(
-                                    &curr_content,
-                                    value.position.0,
-                                    value.position.1,
-                                );
-
-                                *mode = CurrentMode::Text(value.append(curr_content));
-                            }
-                            other => {
-                                if !match_input(other) {
-                                    return;
-                                }
-
-                                top_context.clear_rect(0.0, 0.0, width as f64, height as f64);
-
-                                curr_content.push_str(&key);
-
-                                let _ = top_context.fill_text(
-                                    &curr_content,
-                                    value.position.0,
-                                    value.position.1,
-                                );
-
-                                *mode = CurrentMode::Text(value.append(curr_content));
-                            }
-                        };
-                    }
-                    _ => (),
-                }
+                input.space_down(event)
             });
-
-            self.top_layer
-                .add_event_listener_with_callback("keydown", closure.as_ref().unchecked_ref())?;
-            closure.forget();
+            listeners.listen(&document, "keydown", closure)?;
+        }
+        {
+            let input = input.clone();
+            let closure = Closure::<dyn FnMut(_)>::new(move |event: web_sys::KeyboardEvent| {
+                input.space_up(event)
+            });
+            listeners.listen(&document, "keyup", closure)?;
         }
+        let closure = Closure::<dyn FnMut()>::new(move || input.animate());
+        listeners.every(ANTS_INTERVAL, closure)?;
         Ok(())
     }
 }
+
+impl Drop for Canvas {
+    fn drop(&mut self) {
+        self.destroy();
+    }
+}
+
+/// Where `event` happened relative to the top left of `canvas`, in view
+/// pixels, even when it reached another element.
+fn view_point(canvas: &HtmlCanvasElement, event: &web_sys::PointerEvent) -> (f64, f64) {
+    let bounds = canvas.get_bounding_client_rect();
+    (
+        event.client_x() as f64 - bounds.left(),
+        event.client_y() as f64 - bounds.top(),
+    )
+}
+
+/// Whether `event` goes to an element of the page that takes typing, like
+/// an input or an editable area.
+fn typed_into(event: &web_sys::KeyboardEvent) -> bool {
+    event
+        .target()
+        .and_then(|target| target.dyn_into::<HtmlElement>().ok())
+        .is_some_and(|element| {
+            element.is_content_editable()
+                || matches!(element.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT")
+        })
+}
+
+fn pen_dynamics(event: &web_sys::PointerEvent) -> PenDynamics {
+    PenDynamics::from_input(
+        &event.pointer_type(),
+        event.pressure(),
+        event.tilt_x(),
+        event.tilt_y(),
+    )
+}
+
+/// How a magic wand click merges with the selection: shift adds, alt
+/// subtracts and both intersect.
+fn combine_modifier(event: &web_sys::PointerEvent) -> Option<Combine> {
+    match (event.shift_key(), event.alt_key()) {
+        (true, true) => Some(Combine::Intersect),
+        (true, false) => Some(Combine::Add),
+        (false, true) => Some(Combine::Subtract),
+        (false, false) => None,
+    }
+}
+
+fn stroke_padding<S: Surface>(ctx: &S) -> f64 {
+    ctx.line_width() / 2.0 + 1.0
+}
+
+/// How far a pen segment drawn on `ctx` with `dynamics` reaches past its
+/// ends.
+fn pen_padding<S: Surface>(ctx: &S, dynamics: PenDynamics) -> f64 {
+    ctx.line_width() * dynamics.width_factor / 2.0 + 1.0
+}
+
+/// Strokes every layer and the overlay with `css`, the css of `color`, and
+/// makes it the current colour.
+fn apply_stroke_color(
+    layers: &LayerStack,
+    top_context: &CanvasRenderingContext2d,
+    current_color: &RefCell<Color>,
+    css: &str,
+    color: Color,
+) {
+    for context in layers.contexts() {
+        context.set_stroke_style_str(css);
+    }
+    top_context.set_stroke_style_str(css);
+    *current_color.borrow_mut() = color;
+}
+
+/// Average colour of the `size` x `size` square of the document centred on
+/// (`x`, `y`), or `None` where nothing is painted.
+fn pick_color(layers: &LayerStack, x: u32, y: u32, size: u32) -> Option<(u8, u8, u8, u8)> {
+    let (width, height) = layers.size();
+    let reach = (size - 1) / 2;
+    let (left, top) = (x.saturating_sub(reach), y.saturating_sub(reach));
+    let square = Rect::new(left, top, x + size - reach - left, y + size - reach - top);
+    let rect = square.intersect(&Rect::new(0, 0, width, height));
+
+    layers.read_flattened(&rect).ok()?.average()
+}
+
+/// Empties the area under `mask` on a layer, back to white on an opaque
+/// background and to transparent elsewhere.
+fn clear_area(layers: &LayerStack, layer: u32, ctx: &CanvasRenderingContext2d, mask: &Mask) {
+    let mut ctx = ctx.clone();
+    if let Ok(mut region) = ctx.read(&mask.rect) {
+        let background = Some(Color::White.value()).filter(|_| layers.erases_to_white(layer));
+        mask.erase(&mut region, background);
+        let _ = ctx.write(&region);
+    }
+}
+
+/// Lifts the pixels under `mask` off the active layer into a floating
+/// selection. With `keep_original` a copy is lifted and the layer is left
+/// alone.
+fn lift_selection(
+    mode: &mut CurrentMode,
+    layers: &LayerStack,
+    history: &RefCell<History>,
+    selection: &RefCell<Option<Mask>>,
+    mask: Mask,
+    keep_original: bool,
+) {
+    let tool = SelectionTool::of(mode);
+    if mask.is_empty() {
+        // Clicking without outlining anything deselects.
+        *selection.borrow_mut() = None;
+        *mode = tool.idle();
+        return;
+    }
+
+    let context = layers.active_context();
+    let layer = layers.active_id();
+    if let Ok(mut region) = context.read(&mask.rect) {
+        mask.keep(&mut region);
+        if !keep_original {
+            record_region(history, layer, &context, &mask.rect);
+            clear_area(layers, layer, &context, &mask);
+        }
+        let transform = Transform::new(&mask.rect);
+        *selection.borrow_mut() = Some(mask);
+        *mode = CurrentMode::CropPlace(region, transform, tool);
+    }
+}
+
+/// Draws a floating selection into the active layer, leaving its selection
+/// tool ready for the next one. Does nothing when no selection is floating.
+fn settle_floating(
+    mode: &mut CurrentMode,
+    layers: &LayerStack,
+    history: &RefCell<History>,
+    selection: &RefCell<Option<Mask>>,
+) {
+    if let CurrentMode::CropPlace(region, transform, tool) = mode {
+        let context = layers.active_context();
+        let (width, height) = layers.size();
+        // Resampled edges may spill a pixel past the exact outline.
+        let touched = transform.bounds(1.0, width, height);
+        record_region(history, layers.active_id(), &context, &touched);
+        let _ = draw_transformed(&context, region, transform);
+
+        let mut selection = selection.borrow_mut();
+        let lifted = selection.take().unwrap_or_else(|| Mask::full(region.rect));
+        *selection = Some(lifted.transformed(transform, width, height));
+        *mode = tool.idle();
+    }
+}
+
+/// Keeps the text being typed on its layer as a text object, leaving the
+/// text tool ready for the next one. Emptied text is removed. Does nothing
+/// when no text is being typed.
+fn settle_text(mode: &mut CurrentMode, layers: &mut LayerStack, history: &RefCell<History>) {
+    let session = match mode {
+        CurrentMode::Text(value) => match value.session.take() {
+            Some(session) => session,
+            None => return,
+        },
+        _ => return,
+    };
+
+    let mut object = session.object;
+    object.content = session.edit.content().to_owned();
+    let (index, original) = session.original.unzip();
+    if original.as_ref() == Some(&object) {
+        let _ = layers.insert_text(session.layer, index, object);
+        return;
+    }
+    // The layer's text as it was before the text got opened.
+    let mut before = match layers.texts(session.layer) {
+        Some(texts) => texts.to_vec(),
+        None => return,
+    };
+    if let (Some(index), Some(original)) = (index, &original) {
+        before.insert(index.min(before.len()), original.clone());
+    }
+    let kept = !object.content.is_empty();
+    if kept {
+        let _ = layers.insert_text(session.layer, index, object);
+    }
+    if kept || original.is_some() {
+        history
+            .borrow_mut()
+            .record_entry(vec![(session.layer, Change::Texts(before))]);
+    }
+}
+
+/// Drops the text being typed, putting text opened again back as it was.
+fn drop_text(mode: &mut CurrentMode, layers: &mut LayerStack) {
+    if let CurrentMode::Text(value) = mode {
+        if let Some(session) = value.session.take() {
+            if let Some((index, original)) = session.original {
+                let _ = layers.insert_text(session.layer, Some(index), original);
+            }
+        }
+    }
+}
+
+/// Puts `shape` on top of the shapes of vector layer `layer`. Returns the
+/// area it covers, if it was kept.
+fn keep_shape(
+    layers: &mut LayerStack,
+    history: &RefCell<History>,
+    layer: u32,
+    shape: ShapeObject,
+) -> Option<Rect> {
+    let (start, end) = shape.bounds();
+    let (width, height) = layers.size();
+    let before = layers.shapes(layer)?.to_vec();
+    layers.insert_shape(layer, None, shape).ok()?;
+    history
+        .borrow_mut()
+        .record_entry(vec![(layer, Change::Shapes(before))]);
+    Some(Rect::from_points(start, end, 1.0, width, height))
+}
+
+/// Shapes of the layer of the picked shape, with its id, to record before
+/// changing them.
+fn picked_layer_shapes(mode: &CurrentMode, layers: &LayerStack) -> Option<(u32, Vec<ShapeObject>)> {
+    match mode {
+        CurrentMode::ObjectSelect(Some(picked)) => {
+            Some((picked.layer, layers.shapes(picked.layer)?.to_vec()))
+        }
+        _ => None,
+    }
+}
+
+/// Records `before` as the shapes of `layer` prior to a change, unless
+/// they are still the same. Returns whether they changed.
+fn record_shapes(
+    layers: &LayerStack,
+    history: &RefCell<History>,
+    layer: u32,
+    before: Vec<ShapeObject>,
+) -> bool {
+    if layers.shapes(layer) == Some(&before[..]) {
+        return false;
+    }
+    history
+        .borrow_mut()
+        .record_entry(vec![(layer, Change::Shapes(before))]);
+    true
+}
+
+/// Applies `edit` to the picked shape, if any, and frames it again.
+fn edit_picked_shape(
+    mode: &mut CurrentMode,
+    layers: &mut LayerStack,
+    edit: impl FnOnce(&mut ShapeObject),
+) {
+    if let CurrentMode::ObjectSelect(Some(picked)) = mode {
+        if let Some(shape) = layers.shape_mut(picked.layer, picked.index) {
+            edit(shape);
+            picked.frame = shape.bounds();
+        }
+    }
+}
+
+/// Takes the picked shape off its layer.
+fn delete_picked_shape(
+    mode: &mut CurrentMode,
+    layers: &mut LayerStack,
+    history: &RefCell<History>,
+) -> Option<ShapeObject> {
+    let picked = match mode {
+        CurrentMode::ObjectSelect(picked) => picked.take()?,
+        _ => return None,
+    };
+    let before = layers.shapes(picked.layer)?.to_vec();
+    let shape = layers.remove_shape(picked.layer, picked.index)?;
+    history
+        .borrow_mut()
+        .record_entry(vec![(picked.layer, Change::Shapes(before))]);
+    Some(shape)
+}
+
+/// Moves the hidden text field over the caret, where input methods show
+/// their candidates.
+fn place_text_input(
+    input: &HtmlTextAreaElement,
+    mode: &CurrentMode,
+    ctx: &CanvasRenderingContext2d,
+    viewport: &Viewport,
+) {
+    let session = match mode {
+        CurrentMode::Text(TextData {
+            session: Some(session),
+            ..
+        }) => session,
+        _ => return,
+    };
+    let object = &session.object;
+    ctx.set_font(&object.style.font());
+    let (x, y) = object
+        .style
+        .caret_point(&session.edit, object.position, text::measure(ctx));
+    let (x, y) = viewport.to_view(x, y);
+    let style = input.style();
+    let _ = style.set_property("left", &format!("{}px", x));
+    let _ = style.set_property("top", &format!("{}px", y));
+    let _ = style.set_property(
+        "font-size",
+        &format!("{}px", object.style.size * viewport.zoom),
+    );
+}
+
+/// Makes `region` the floating selection, over the area it was taken from.
+/// One already floating gets placed first instead of being lost.
+fn float_region(
+    mode: &mut CurrentMode,
+    layers: &LayerStack,
+    history: &RefCell<History>,
+    selection: &RefCell<Option<Mask>>,
+    region: Region,
+) {
+    settle_floating(mode, layers, history, selection);
+    let transform = Transform::new(&region.rect);
+    *selection.borrow_mut() = Some(Mask::full(region.rect));
+    *mode = CurrentMode::CropPlace(region, transform, SelectionTool::of(mode));
+}
+
+/// Redraws the floating selection and its handles, the outline being
+/// selected, the text being typed or, with a selection tool, the current
+/// selection on the preview canvas, e.g. after the view moved.
+fn draw_overlay(
+    mode: &CurrentMode,
+    selection: &Option<Mask>,
+    top: &CanvasRenderingContext2d,
+    viewport: &Viewport,
+) {
+    match mode {
+        CurrentMode::CropPlace(region, transform, _) => {
+            viewport.clear(top);
+            let _ = draw_transformed(top, region, transform);
+            transform.draw_frame(&mut top.clone(), viewport.zoom);
+        }
+        CurrentMode::Lasso(points) | CurrentMode::Polygon(points) if !points.is_empty() => {
+            viewport.clear(top);
+            let _ = draw_ants(top, points, viewport.zoom);
+        }
+        CurrentMode::Text(value) => {
+            viewport.clear(top);
+            if let Some(session) = &value.session {
+                let object = &session.object;
+                top.save();
+                top.set_fill_style_str(&object.color);
+                top.set_stroke_style_str(&object.color);
+                let _ = text::draw_edit(
+                    top,
+                    &session.edit,
+                    object.position,
+                    &object.style,
+                    viewport.zoom,
+                );
+                top.restore();
+            }
+        }
+        CurrentMode::ObjectSelect(picked) => {
+            viewport.clear(top);
+            if let Some(PickedShape {
+                frame: ((x0, y0), (x1, y1)),
+                ..
+            }) = picked
+            {
+                top.save();
+                top.set_global_alpha(1.0);
+                top.set_line_width(1.0 / viewport.zoom);
+                top.set_stroke_style_str("#1b95e0");
+                top.stroke_rect(*x0, *y0, x1 - x0, y1 - y0);
+                top.restore();
+            }
+        }
+        other if other.selects() => {
+            viewport.clear(top);
+            if let Some(mask) = selection {
+                let _ = draw_mask_ants(top, mask, viewport.zoom);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Puts back what `entry` holds, last change first, returning the entry
+/// that puts the document back as it was.
+fn apply_entry(layers: &mut LayerStack, entry: &Entry) -> Result<Entry, JsValue> {
+    let mut replaced = Vec::with_capacity(entry.len());
+    for (layer, change) in entry.iter().rev() {
+        replaced.push((*layer, layers.apply(*layer, change)?));
+    }
+    replaced.reverse();
+    Ok(replaced)
+}
+
+fn record_region<S: Surface>(history: &RefCell<History>, layer: u32, ctx: &S, rect: &Rect) {
+    if rect.is_empty() {
+        return;
+    }
+    if let Ok(region) = ctx.read(rect) {
+        history.borrow_mut().record(layer, region);
+    }
+}
+
+/// Like `record_region`, for `shape` about to be painted over `rect`.
+fn record_drawn<S: Surface>(
+    history: &RefCell<History>,
+    layer: u32,
+    ctx: &S,
+    rect: &Rect,
+    shape: ShapeObject,
+) {
+    if rect.is_empty() {
+        return;
+    }
+    if let Ok(region) = ctx.read(rect) {
+        history.borrow_mut().record_drawn(layer, region, shape);
+    }
+}
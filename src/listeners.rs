@@ -0,0 +1,69 @@
+//! Event listeners and timers an editor registers, kept so they can be
+//! removed when it goes away.
+
+use std::any::Any;
+
+use js_sys::Function;
+use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
+use web_sys::EventTarget;
+
+#[derive(Default)]
+pub struct Listeners {
+    attached: Vec<(EventTarget, &'static str, Function)>,
+    intervals: Vec<i32>,
+    /// Keeps the Rust side of every callback alive.
+    closures: Vec<Box<dyn Any>>,
+}
+
+impl Listeners {
+    /// Calls `closure` on each `name` event reaching `target`.
+    pub fn listen<T: ?Sized + 'static>(
+        &mut self,
+        target: &EventTarget,
+        name: &'static str,
+        closure: Closure<T>,
+    ) -> Result<(), JsValue> {
+        let function: Function = closure.as_ref().unchecked_ref::<Function>().clone();
+        target.add_event_listener_with_callback(name, &function)?;
+        self.attached.push((target.clone(), name, function));
+        self.closures.push(Box::new(closure));
+        Ok(())
+    }
+
+    /// Calls `closure` every `millis` milliseconds.
+    pub fn every(&mut self, millis: i32, closure: Closure<dyn FnMut()>) -> Result<(), JsValue> {
+        let id = web_sys::window()
+            .ok_or_else(|| JsValue::from_str("no window"))?
+            .set_interval_with_callback_and_timeout_and_arguments_0(
+                closure.as_ref().unchecked_ref(),
+                millis,
+            )?;
+        self.intervals.push(id);
+        self.closures.push(Box::new(closure));
+        Ok(())
+    }
+
+    /// Removes every listener and timer. The callbacks are freed once the
+    /// current event is handled, as this may run from inside one of them.
+    pub fn remove_all(&mut self) {
+        for (target, name, function) in self.attached.drain(..) {
+            let _ = target.remove_event_listener_with_callback(name, &function);
+        }
+        if let Some(window) = web_sys::window() {
+            for id in self.intervals.drain(..) {
+                window.clear_interval_with_handle(id);
+            }
+        }
+
+        let closures = std::mem::take(&mut self.closures);
+        if !closures.is_empty() {
+            wasm_bindgen_futures::spawn_local(async move { drop(closures) });
+        }
+    }
+}
+
+impl Drop for Listeners {
+    fn drop(&mut self) {
+        self.remove_all();
+    }
+}
@@ -0,0 +1,644 @@
+//! Native project files, keeping layers and editor state so a document can
+//! be reopened and edited further.
+//!
+//! A file starts with a magic, the version that wrote it and the oldest
+//! version able to read it, followed by chunks of `tag, length, payload`.
+//! Readers skip chunks they don't know and ignore bytes past the fields they
+//! know at the end of a chunk, so newer writers can add data without locking
+//! older readers out.
+
+use std::fmt;
+
+use wasm_bindgen::JsValue;
+
+use crate::{
+    history::DEFAULT_HISTORY_LIMIT,
+    shapes::{FillStyle, Shape, ShapeObject},
+    text::{TextAlign, TextBaseline, TextObject, TextStyle},
+    utils::{FillOptions, PenDynamics},
+};
+
+const MAGIC: &[u8; 4] = b"PNTA";
+pub const FORMAT_VERSION: u16 = 4;
+
+/// Largest document accepted, in pixels, about where browsers stop making
+/// canvases.
+const MAX_PIXELS: u64 = 16384 * 16384;
+
+const DOCUMENT: &[u8; 4] = b"DOC ";
+const LAYER: &[u8; 4] = b"LAYR";
+const SHAPE: &[u8; 4] = b"SHAP";
+const TEXT: &[u8; 4] = b"TEXT";
+const TOOLS: &[u8; 4] = b"TOOL";
+const PALETTE: &[u8; 4] = b"PALT";
+const HISTORY: &[u8; 4] = b"HIST";
+
+pub type Rgba = (u8, u8, u8, u8);
+
+#[derive(Debug, PartialEq)]
+pub enum ProjectError {
+    NotAProject,
+    /// The file needs a reader of at least this version.
+    UnsupportedVersion(u16),
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProjectError::NotAProject => write!(f, "not a project file"),
+            ProjectError::UnsupportedVersion(version) => write!(
+                f,
+                "project needs format version {} but only {} is supported",
+                version, FORMAT_VERSION
+            ),
+            ProjectError::Truncated => write!(f, "project file is truncated"),
+            ProjectError::Invalid(reason) => write!(f, "invalid project file: {}", reason),
+        }
+    }
+}
+
+impl From<ProjectError> for JsValue {
+    fn from(error: ProjectError) -> JsValue {
+        JsValue::from_str(&error.to_string())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerData {
+    pub id: u32,
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f64,
+    /// RGBA pixels covering the whole document.
+    pub pixels: Vec<u8>,
+    /// Whether shapes drawn on the layer are kept as objects.
+    pub vector: bool,
+    /// Shapes kept as objects over the pixels, bottom first.
+    pub shapes: Vec<ShapeObject>,
+    /// Text kept editable over the pixels and shapes, bottom first.
+    pub texts: Vec<TextObject>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToolSettings {
+    pub stroke_width: f64,
+    pub stroke_color: Rgba,
+    pub shape_style: FillStyle,
+    pub shape_fill: Option<Rgba>,
+    pub corner_radius: f64,
+    pub fill: FillOptions,
+}
+
+impl Default for ToolSettings {
+    fn default() -> ToolSettings {
+        ToolSettings {
+            stroke_width: 1.0,
+            stroke_color: (0, 0, 0, 255),
+            shape_style: FillStyle::Outline,
+            shape_fill: None,
+            corner_radius: 16.0,
+            fill: FillOptions::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Project {
+    pub width: u32,
+    pub height: u32,
+    /// Whether the background erases to transparent rather than white.
+    pub transparent: bool,
+    /// Bottom layer first.
+    pub layers: Vec<LayerData>,
+    pub active_layer: u32,
+    pub tools: ToolSettings,
+    pub palette: Vec<Rgba>,
+    pub history_limit: u64,
+}
+
+impl Project {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Writer(MAGIC.to_vec());
+        out.u16(FORMAT_VERSION);
+        out.u16(self.compatible_version());
+
+        out.chunk(DOCUMENT, |out| {
+            out.u32(self.width);
+            out.u32(self.height);
+            out.u32(self.active_layer);
+            out.bool(self.transparent);
+        });
+
+        for layer in &self.layers {
+            out.chunk(LAYER, |out| {
+                out.u32(layer.id);
+                out.bool(layer.visible);
+                out.f64(layer.opacity);
+                out.str(&layer.name);
+                out.pixels(&layer.pixels);
+                out.bool(layer.vector);
+            });
+        }
+
+        for layer in &self.layers {
+            for shape in &layer.shapes {
+                out.chunk(SHAPE, |out| {
+                    out.u32(layer.id);
+                    out.str(shape.shape.name());
+                    let geometry = match &shape.shape {
+                        Shape::Circle { cx, cy, radius } => vec![*cx, *cy, *radius],
+                        Shape::Ellipse { cx, cy, rx, ry } => vec![*cx, *cy, *rx, *ry],
+                        Shape::Rectangle { x, y, w, h } => vec![*x, *y, *w, *h],
+                        Shape::RoundedRectangle { x, y, w, h, radius } => {
+                            vec![*x, *y, *w, *h, *radius]
+                        }
+                        Shape::Line { x1, y1, x2, y2 } => vec![*x1, *y1, *x2, *y2],
+                        Shape::Pen { start, segments } => {
+                            out.u32(segments.len() as u32);
+                            let mut geometry = vec![start.0, start.1];
+                            for ((x, y), dynamics) in segments {
+                                geometry.extend([*x, *y, dynamics.width_factor, dynamics.alpha]);
+                            }
+                            geometry
+                        }
+                    };
+                    for value in geometry {
+                        out.f64(value);
+                    }
+                    out.str(shape.style.name());
+                    out.str(&shape.stroke);
+                    out.str(&shape.fill);
+                    out.f64(shape.line_width);
+                });
+            }
+        }
+
+        for layer in &self.layers {
+            for text in &layer.texts {
+                out.chunk(TEXT, |out| {
+                    let style = &text.style;
+                    out.u32(layer.id);
+                    out.str(&text.content);
+                    out.f64(text.position.0);
+                    out.f64(text.position.1);
+                    out.str(&text.color);
+                    out.str(&style.family);
+                    out.f64(style.size);
+                    out.u32(style.weight);
+                    out.bool(style.italic);
+                    out.str(style.align.name());
+                    out.str(style.baseline.name());
+                    out.f64(style.line_height);
+                    let (width, color) = style.outline.clone().unwrap_or_default();
+                    out.bool(style.outline.is_some());
+                    out.f64(width);
+                    out.str(&color);
+                });
+            }
+        }
+
+        out.chunk(TOOLS, |out| {
+            let tools = &self.tools;
+            out.f64(tools.stroke_width);
+            out.rgba(tools.stroke_color);
+            out.str(tools.shape_style.name());
+            out.bool(tools.shape_fill.is_some());
+            out.rgba(tools.shape_fill.unwrap_or((0, 0, 0, 0)));
+            out.f64(tools.corner_radius);
+            out.f64(tools.fill.tolerance);
+            out.bool(tools.fill.contiguous);
+            out.bool(tools.fill.antialias);
+        });
+
+        out.chunk(PALETTE, |out| {
+            out.u32(self.palette.len() as u32);
+            for color in &self.palette {
+                out.rgba(*color);
+            }
+        });
+
+        out.chunk(HISTORY, |out| out.u64(self.history_limit));
+
+        out.0
+    }
+
+    /// Oldest reader version that understands the file written for this
+    /// project. Text and shapes kept as objects live only in their own
+    /// chunks, added in versions 3 and 4, so older readers would open the
+    /// document without them. Anything else can be read since version 1.
+    fn compatible_version(&self) -> u16 {
+        if self
+            .layers
+            .iter()
+            .any(|layer| layer.vector || !layer.shapes.is_empty())
+        {
+            4
+        } else if self.layers.iter().any(|layer| !layer.texts.is_empty()) {
+            3
+        } else {
+            1
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Project, ProjectError> {
+        let mut input = Reader::new(bytes);
+        if input.take(4).ok().map(|magic| magic == MAGIC) != Some(true) {
+            return Err(ProjectError::NotAProject);
+        }
+        let _written_by = input.u16()?;
+        let compatible = input.u16()?;
+        if compatible > FORMAT_VERSION {
+            return Err(ProjectError::UnsupportedVersion(compatible));
+        }
+
+        let mut has_document = false;
+        let mut project = Project {
+            width: 0,
+            height: 0,
+            transparent: false,
+            layers: vec![],
+            active_layer: 0,
+            tools: ToolSettings::default(),
+            palette: vec![],
+            history_limit: DEFAULT_HISTORY_LIMIT as u64,
+        };
+
+        while !input.is_empty() {
+            let tag: [u8; 4] = input.array()?;
+            let length = input.u32()? as usize;
+            let mut chunk = Reader::new(input.take(length)?);
+
+            match &tag {
+                DOCUMENT => {
+                    let width = chunk.u32()?;
+                    let height = chunk.u32()?;
+                    if width == 0 || height == 0 {
+                        return Err(ProjectError::Invalid("empty document"));
+                    }
+                    if u64::from(width) * u64::from(height) > MAX_PIXELS {
+                        return Err(ProjectError::Invalid("document too large"));
+                    }
+                    project.width = width;
+                    project.height = height;
+                    project.active_layer = chunk.u32()?;
+                    // Version 1 documents always had a white background.
+                    project.transparent = !chunk.is_empty() && chunk.bool()?;
+                    has_document = true;
+                }
+                LAYER => {
+                    if !has_document {
+                        return Err(ProjectError::Invalid("layer before document"));
+                    }
+                    project.layers.push(LayerData {
+                        id: chunk.u32()?,
+                        visible: chunk.bool()?,
+                        opacity: chunk.size()?.min(1.0),
+                        name: chunk.str()?,
+                        pixels: chunk.pixels(
+                            (project.width as usize)
+                                .checked_mul(project.height as usize)
+                                .ok_or(ProjectError::Invalid("document too large"))?,
+                        )?,
+                        // Layers before version 4 painted their shapes.
+                        vector: !chunk.is_empty() && chunk.bool()?,
+                        shapes: vec![],
+                        texts: vec![],
+                    });
+                }
+                SHAPE => {
+                    let id = chunk.u32()?;
+                    let layer = project
+                        .layers
+                        .iter_mut()
+                        .find(|layer| layer.id == id)
+                        .ok_or(ProjectError::Invalid("shape on a missing layer"))?;
+                    let shape = match chunk.str()?.as_str() {
+                        "circle" => Shape::Circle {
+                            cx: chunk.f64()?,
+                            cy: chunk.f64()?,
+                            radius: chunk.size()?,
+                        },
+                        "ellipse" => Shape::Ellipse {
+                            cx: chunk.f64()?,
+                            cy: chunk.f64()?,
+                            rx: chunk.size()?,
+                            ry: chunk.size()?,
+                        },
+                        "rectangle" => Shape::Rectangle {
+                            x: chunk.f64()?,
+                            y: chunk.f64()?,
+                            w: chunk.f64()?,
+                            h: chunk.f64()?,
+                        },
+                        "rounded-rectangle" => Shape::RoundedRectangle {
+                            x: chunk.f64()?,
+                            y: chunk.f64()?,
+                            w: chunk.f64()?,
+                            h: chunk.f64()?,
+                            radius: chunk.size()?,
+                        },
+                        "line" => Shape::Line {
+                            x1: chunk.f64()?,
+                            y1: chunk.f64()?,
+                            x2: chunk.f64()?,
+                            y2: chunk.f64()?,
+                        },
+                        "pen" => {
+                            let count = chunk.u32()?;
+                            let start = (chunk.f64()?, chunk.f64()?);
+                            let mut segments = vec![];
+                            for _ in 0..count {
+                                let to = (chunk.f64()?, chunk.f64()?);
+                                let dynamics = PenDynamics {
+                                    width_factor: chunk.size()?,
+                                    alpha: chunk.size()?.min(1.0),
+                                };
+                                segments.push((to, dynamics));
+                            }
+                            Shape::Pen { start, segments }
+                        }
+                        // Kinds added by newer versions are left out.
+                        _ => continue,
+                    };
+                    layer.shapes.push(ShapeObject {
+                        shape,
+                        style: FillStyle::from_name(&chunk.str()?).unwrap_or(FillStyle::Outline),
+                        stroke: chunk.str()?,
+                        fill: chunk.str()?,
+                        line_width: chunk.size()?,
+                    });
+                }
+                TEXT => {
+                    let id = chunk.u32()?;
+                    let layer = project
+                        .layers
+                        .iter_mut()
+                        .find(|layer| layer.id == id)
+                        .ok_or(ProjectError::Invalid("text on a missing layer"))?;
+                    let content = chunk.str()?;
+                    let position = (chunk.f64()?, chunk.f64()?);
+                    let color = chunk.str()?;
+                    let mut style = TextStyle {
+                        family: chunk.str()?,
+                        size: chunk.size()?,
+                        weight: chunk.u32()?,
+                        italic: chunk.bool()?,
+                        ..TextStyle::default()
+                    };
+                    if let Some(align) = TextAlign::from_name(&chunk.str()?) {
+                        style.align = align;
+                    }
+                    if let Some(baseline) = TextBaseline::from_name(&chunk.str()?) {
+                        style.baseline = baseline;
+                    }
+                    style.line_height = chunk.size()?;
+                    let outlined = chunk.bool()?;
+                    let outline = (chunk.size()?, chunk.str()?);
+                    style.outline = Some(outline).filter(|_| outlined);
+                    layer.texts.push(TextObject {
+                        content,
+                        position,
+                        style,
+                        color,
+                    });
+                }
+                TOOLS => {
+                    let tools = &mut project.tools;
+                    tools.stroke_width = chunk.size()?;
+                    tools.stroke_color = chunk.rgba()?;
+                    // Styles added by newer versions fall back to the default.
+                    if let Some(style) = FillStyle::from_name(&chunk.str()?) {
+                        tools.shape_style = style;
+                    }
+                    let has_fill = chunk.bool()?;
+                    let fill = chunk.rgba()?;
+                    tools.shape_fill = if has_fill { Some(fill) } else { None };
+                    tools.corner_radius = chunk.size()?;
+                    tools.fill.tolerance = chunk.size()?;
+                    tools.fill.contiguous = chunk.bool()?;
+                    tools.fill.antialias = chunk.bool()?;
+                }
+                PALETTE => {
+                    let count = chunk.u32()?;
+                    for _ in 0..count {
+                        project.palette.push(chunk.rgba()?);
+                    }
+                }
+                HISTORY => project.history_limit = chunk.u64()?,
+                _ => (),
+            }
+        }
+
+        if !has_document {
+            return Err(ProjectError::Invalid("missing document"));
+        }
+        if project.layers.is_empty() {
+            return Err(ProjectError::Invalid("no layers"));
+        }
+        let mut ids: Vec<u32> = project.layers.iter().map(|layer| layer.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        if ids.len() != project.layers.len() {
+            return Err(ProjectError::Invalid("duplicate layer id"));
+        }
+        if !ids.contains(&project.active_layer) {
+            return Err(ProjectError::Invalid("active layer does not exist"));
+        }
+
+        Ok(project)
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn rgba(&mut self, (r, g, b, a): Rgba) {
+        self.0.extend_from_slice(&[r, g, b, a]);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    /// Run-length encodes RGBA pixels: a header byte below 128 is followed
+    /// by that many plus one literal pixels, otherwise by a single pixel
+    /// repeated header minus 126 times.
+    fn pixels(&mut self, data: &[u8]) {
+        let pixels: Vec<&[u8]> = data.chunks_exact(4).collect();
+        let mut i = 0;
+
+        while i < pixels.len() {
+            let mut run = 1;
+            while i + run < pixels.len() && run < 129 && pixels[i + run] == pixels[i] {
+                run += 1;
+            }
+            if run > 1 {
+                self.u8((run + 126) as u8);
+                self.0.extend_from_slice(pixels[i]);
+                i += run;
+                continue;
+            }
+
+            let start = i;
+            while i < pixels.len() && i - start < 128 {
+                if i + 1 < pixels.len() && pixels[i + 1] == pixels[i] {
+                    break;
+                }
+                i += 1;
+            }
+            self.u8((i - start - 1) as u8);
+            for pixel in &pixels[start..i] {
+                self.0.extend_from_slice(pixel);
+            }
+        }
+    }
+
+    /// Writes a chunk, filling in its length once `body` is done.
+    fn chunk(&mut self, tag: &[u8; 4], body: impl FnOnce(&mut Writer)) {
+        self.0.extend_from_slice(tag);
+        let length_at = self.0.len();
+        self.u32(0);
+        body(self);
+        let length = (self.0.len() - length_at - 4) as u32;
+        self.0[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ProjectError> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(ProjectError::Truncated)?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ProjectError> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.take(N)?);
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ProjectError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, ProjectError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> Result<u16, ProjectError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, ProjectError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ProjectError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    /// Reads a number, which has to be finite.
+    fn f64(&mut self) -> Result<f64, ProjectError> {
+        let value = f64::from_le_bytes(self.array()?);
+        if !value.is_finite() {
+            return Err(ProjectError::Invalid("number out of range"));
+        }
+        Ok(value)
+    }
+
+    /// Reads a number that can't be negative, like a width.
+    fn size(&mut self) -> Result<f64, ProjectError> {
+        let value = self.f64()?;
+        if value < 0.0 {
+            return Err(ProjectError::Invalid("negative size"));
+        }
+        Ok(value)
+    }
+
+    fn rgba(&mut self) -> Result<Rgba, ProjectError> {
+        let [r, g, b, a] = self.array()?;
+        Ok((r, g, b, a))
+    }
+
+    fn str(&mut self) -> Result<String, ProjectError> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.take(length)?.to_vec())
+            .map_err(|_| ProjectError::Invalid("text is not utf-8"))
+    }
+
+    /// Reads `count` run-length encoded pixels, see `Writer::pixels`. The
+    /// pixels are only allocated as the encoded data turns out to cover
+    /// them.
+    fn pixels(&mut self, count: usize) -> Result<Vec<u8>, ProjectError> {
+        let length = count
+            .checked_mul(4)
+            .ok_or(ProjectError::Invalid("document too large"))?;
+        // Each run of up to 129 pixels takes 5 bytes at best.
+        if count.div_ceil(129) * 5 > self.data.len() - self.position {
+            return Err(ProjectError::Truncated);
+        }
+
+        let mut data = vec![];
+        while data.len() < length {
+            let header = self.u8()? as usize;
+            if header < 128 {
+                data.extend_from_slice(self.take((header + 1) * 4)?);
+            } else {
+                let pixel = self.take(4)?;
+                for _ in 0..header - 126 {
+                    data.extend_from_slice(pixel);
+                }
+            }
+        }
+
+        if data.len() != length {
+            return Err(ProjectError::Invalid("layer size does not match document"));
+        }
+        Ok(data)
+    }
+}
@@ -0,0 +1,615 @@
+use std::f64::consts::PI;
+
+use wasm_bindgen::{Clamped, JsValue};
+use web_sys::{CanvasRenderingContext2d, ImageData};
+
+use crate::color;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Builds the smallest rect covering both points, grown by `padding` on
+    /// every side and clamped to a `width` x `height` canvas.
+    pub fn from_points(
+        start: (f64, f64),
+        end: (f64, f64),
+        padding: f64,
+        width: u32,
+        height: u32,
+    ) -> Rect {
+        let x0 = (start.0.min(end.0) - padding).floor().max(0.0);
+        let y0 = (start.1.min(end.1) - padding).floor().max(0.0);
+        let x1 = (start.0.max(end.0) + padding).ceil().min(width as f64);
+        let y1 = (start.1.max(end.1) + padding).ceil().min(height as f64);
+
+        Rect::new(
+            x0 as u32,
+            y0 as u32,
+            (x1 - x0).max(0.0) as u32,
+            (y1 - y0).max(0.0) as u32,
+        )
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+
+        if right <= x || bottom <= y {
+            return Rect::new(x, y, 0, 0);
+        }
+
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// Raw RGBA pixels of a rectangular area of a surface.
+#[derive(Clone, Debug)]
+pub struct Region {
+    pub rect: Rect,
+    pub data: Vec<u8>,
+}
+
+impl Region {
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Copies the part of this region covered by `rect` into a new region.
+    pub fn crop(&self, rect: &Rect) -> Region {
+        let rect = self.rect.intersect(rect);
+        let mut data = Vec::with_capacity((rect.width * rect.height * 4) as usize);
+
+        for row in rect.y..rect.y + rect.height {
+            let start =
+                (((row - self.rect.y) * self.rect.width + (rect.x - self.rect.x)) * 4) as usize;
+            data.extend_from_slice(&self.data[start..start + rect.width as usize * 4]);
+        }
+
+        Region { rect, data }
+    }
+
+    /// Copies the pixels of `other` over the part of this region it covers.
+    pub fn paste(&mut self, other: &Region) {
+        let rect = self.rect.intersect(&other.rect);
+        let length = rect.width as usize * 4;
+        for row in rect.y..rect.y + rect.height {
+            let from =
+                (((row - other.rect.y) * other.rect.width + (rect.x - other.rect.x)) * 4) as usize;
+            let to =
+                (((row - self.rect.y) * self.rect.width + (rect.x - self.rect.x)) * 4) as usize;
+            self.data[to..to + length].copy_from_slice(&other.data[from..from + length]);
+        }
+    }
+
+    /// Same pixels placed with their top-left corner at (`x`, `y`).
+    pub fn translated(&self, x: u32, y: u32) -> Region {
+        Region {
+            rect: Rect::new(x, y, self.rect.width, self.rect.height),
+            data: self.data.clone(),
+        }
+    }
+
+    /// Average colour of the region, each pixel weighted by its alpha so
+    /// transparent pixels don't darken it. `None` when fully transparent.
+    pub fn average(&self) -> Option<(u8, u8, u8, u8)> {
+        let mut sums = [0.0; 3];
+        let mut coverage = 0.0;
+        for pixel in self.data.chunks_exact(4) {
+            let alpha = pixel[3] as f64;
+            for (sum, value) in sums.iter_mut().zip(pixel) {
+                *sum += *value as f64 * alpha;
+            }
+            coverage += alpha;
+        }
+        if coverage == 0.0 {
+            return None;
+        }
+
+        let channel = |sum: f64| (sum / coverage).round() as u8;
+        let pixels = (self.data.len() / 4) as f64;
+        Some((
+            channel(sums[0]),
+            channel(sums[1]),
+            channel(sums[2]),
+            (coverage / pixels).round() as u8,
+        ))
+    }
+}
+
+/// Drawing operations the tools need, mirroring the subset of the 2d
+/// canvas API they use, so they run on a browser canvas or a `PixelBuffer`.
+pub trait Surface {
+    fn begin_path(&mut self);
+    fn move_to(&mut self, x: f64, y: f64);
+    fn line_to(&mut self, x: f64, y: f64);
+    fn arc(&mut self, x: f64, y: f64, radius: f64, start: f64, end: f64);
+    fn ellipse(&mut self, x: f64, y: f64, rx: f64, ry: f64, start: f64, end: f64);
+    fn rect(&mut self, x: f64, y: f64, w: f64, h: f64);
+    fn close_path(&mut self);
+    fn stroke(&mut self);
+    fn fill(&mut self);
+
+    fn line_width(&self) -> f64;
+    fn set_line_width(&mut self, width: f64);
+    fn set_line_join(&mut self, join: &str);
+    fn set_stroke_style(&mut self, color: &str);
+    fn set_fill_style(&mut self, color: &str);
+    fn set_global_alpha(&mut self, alpha: f64);
+    fn save(&mut self);
+    fn restore(&mut self);
+
+    fn fill_rect(&mut self, x: f64, y: f64, w: f64, h: f64);
+    fn clear_rect(&mut self, x: f64, y: f64, w: f64, h: f64);
+    fn read(&self, rect: &Rect) -> Result<Region, JsValue>;
+    fn write(&mut self, region: &Region) -> Result<(), JsValue>;
+}
+
+impl Surface for CanvasRenderingContext2d {
+    fn begin_path(&mut self) {
+        CanvasRenderingContext2d::begin_path(self);
+    }
+
+    fn move_to(&mut self, x: f64, y: f64) {
+        CanvasRenderingContext2d::move_to(self, x, y);
+    }
+
+    fn line_to(&mut self, x: f64, y: f64) {
+        CanvasRenderingContext2d::line_to(self, x, y);
+    }
+
+    fn arc(&mut self, x: f64, y: f64, radius: f64, start: f64, end: f64) {
+        let _ = CanvasRenderingContext2d::arc(self, x, y, radius, start, end);
+    }
+
+    fn ellipse(&mut self, x: f64, y: f64, rx: f64, ry: f64, start: f64, end: f64) {
+        let _ = CanvasRenderingContext2d::ellipse(self, x, y, rx, ry, 0.0, start, end);
+    }
+
+    fn rect(&mut self, x: f64, y: f64, w: f64, h: f64) {
+        CanvasRenderingContext2d::rect(self, x, y, w, h);
+    }
+
+    fn close_path(&mut self) {
+        CanvasRenderingContext2d::close_path(self);
+    }
+
+    fn stroke(&mut self) {
+        CanvasRenderingContext2d::stroke(self);
+    }
+
+    fn fill(&mut self) {
+        CanvasRenderingContext2d::fill(self);
+    }
+
+    fn line_width(&self) -> f64 {
+        CanvasRenderingContext2d::line_width(self)
+    }
+
+    fn set_line_width(&mut self, width: f64) {
+        CanvasRenderingContext2d::set_line_width(self, width);
+    }
+
+    fn set_line_join(&mut self, join: &str) {
+        CanvasRenderingContext2d::set_line_join(self, join);
+    }
+
+    fn set_stroke_style(&mut self, color: &str) {
+        self.set_stroke_style_str(color);
+    }
+
+    fn set_fill_style(&mut self, color: &str) {
+        self.set_fill_style_str(color);
+    }
+
+    fn set_global_alpha(&mut self, alpha: f64) {
+        CanvasRenderingContext2d::set_global_alpha(self, alpha);
+    }
+
+    fn save(&mut self) {
+        CanvasRenderingContext2d::save(self);
+    }
+
+    fn restore(&mut self) {
+        CanvasRenderingContext2d::restore(self);
+    }
+
+    fn fill_rect(&mut self, x: f64, y: f64, w: f64, h: f64) {
+        CanvasRenderingContext2d::fill_rect(self, x, y, w, h);
+    }
+
+    fn clear_rect(&mut self, x: f64, y: f64, w: f64, h: f64) {
+        CanvasRenderingContext2d::clear_rect(self, x, y, w, h);
+    }
+
+    fn read(&self, rect: &Rect) -> Result<Region, JsValue> {
+        let image = self.get_image_data(
+            rect.x as f64,
+            rect.y as f64,
+            rect.width as f64,
+            rect.height as f64,
+        )?;
+
+        Ok(Region {
+            rect: *rect,
+            data: image.data().0,
+        })
+    }
+
+    fn write(&mut self, region: &Region) -> Result<(), JsValue> {
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&region.data[..]),
+            region.rect.width,
+            region.rect.height,
+        )?;
+
+        self.put_image_data(&image, region.rect.x as f64, region.rect.y as f64)
+    }
+}
+
+/// Writes `region` back onto the surface and returns what it replaced.
+pub fn swap_region<S: Surface>(surface: &mut S, region: &Region) -> Result<Region, JsValue> {
+    let current = surface.read(&region.rect)?;
+    surface.write(region)?;
+
+    Ok(current)
+}
+
+/// Strokes a single segment with the surface's current line settings.
+pub fn stroke_line<S: Surface>(surface: &mut S, from: (f64, f64), to: (f64, f64)) {
+    surface.begin_path();
+    surface.move_to(from.0, from.1);
+    surface.line_to(to.0, to.1);
+    surface.stroke();
+    surface.begin_path();
+}
+
+#[derive(Clone, Copy)]
+struct DrawState {
+    line_width: f64,
+    stroke: (u8, u8, u8, u8),
+    fill: (u8, u8, u8, u8),
+    alpha: f64,
+}
+
+/// In-memory RGBA image implementing `Surface` without a browser. Strokes
+/// use round caps and joins and nothing is anti-aliased.
+pub struct PixelBuffer {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+    path: Vec<Vec<(f64, f64)>>,
+    state: DrawState,
+    saved: Vec<DrawState>,
+}
+
+impl PixelBuffer {
+    /// Creates a fully transparent buffer.
+    pub fn new(width: u32, height: u32) -> PixelBuffer {
+        PixelBuffer {
+            width,
+            height,
+            data: vec![0; (width * height * 4) as usize],
+            path: vec![],
+            state: DrawState {
+                line_width: 1.0,
+                stroke: (0, 0, 0, 255),
+                fill: (0, 0, 0, 255),
+                alpha: 1.0,
+            },
+            saved: vec![],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> (u8, u8, u8, u8) {
+        let index = ((y * self.width + x) * 4) as usize;
+        (
+            self.data[index],
+            self.data[index + 1],
+            self.data[index + 2],
+            self.data[index + 3],
+        )
+    }
+
+    fn blend(&mut self, x: u32, y: u32, color: (u8, u8, u8, u8)) {
+        let index = ((y * self.width + x) * 4) as usize;
+        let alpha = color.3 as f64 / 255.0 * self.state.alpha;
+        let dst_alpha = self.data[index + 3] as f64 / 255.0;
+        let out_alpha = alpha + dst_alpha * (1.0 - alpha);
+
+        if out_alpha == 0.0 {
+            self.data[index..index + 4].copy_from_slice(&[0, 0, 0, 0]);
+            return;
+        }
+
+        let channel = |src: u8, dst: u8| {
+            ((src as f64 * alpha + dst as f64 * dst_alpha * (1.0 - alpha)) / out_alpha).round()
+                as u8
+        };
+
+        self.data[index] = channel(color.0, self.data[index]);
+        self.data[index + 1] = channel(color.1, self.data[index + 1]);
+        self.data[index + 2] = channel(color.2, self.data[index + 2]);
+        self.data[index + 3] = (out_alpha * 255.0).round() as u8;
+    }
+
+    /// Pixels whose centers fall inside the given bounds, clipped to the
+    /// buffer.
+    fn pixel_range(&self, min: f64, max: f64, limit: u32) -> std::ops::Range<u32> {
+        let start = (min - 0.5).ceil().max(0.0) as u32;
+        let end = ((max - 0.5).floor() + 1.0).clamp(0.0, limit as f64) as u32;
+        start.min(end)..end
+    }
+
+    fn trace_arc(&mut self, x: f64, y: f64, rx: f64, ry: f64, start: f64, end: f64) {
+        let sweep = if end - start >= 2.0 * PI {
+            2.0 * PI
+        } else {
+            (end - start).rem_euclid(2.0 * PI)
+        };
+        let steps = ((rx.max(ry) * sweep).ceil() as usize).clamp(8, 1024);
+
+        for step in 0..=steps {
+            let angle = start + sweep * step as f64 / steps as f64;
+            let point = (x + rx * angle.cos(), y + ry * angle.sin());
+            if step == 0 {
+                self.line_to(point.0, point.1);
+            } else {
+                self.path.last_mut().unwrap().push(point);
+            }
+        }
+    }
+
+    /// Like the canvas, keeps the current colour when `css` is invalid.
+    fn color(css: &str, current: (u8, u8, u8, u8)) -> (u8, u8, u8, u8) {
+        color::parse(css).unwrap_or(current)
+    }
+}
+
+impl Surface for PixelBuffer {
+    fn begin_path(&mut self) {
+        self.path.clear();
+    }
+
+    fn move_to(&mut self, x: f64, y: f64) {
+        self.path.push(vec![(x, y)]);
+    }
+
+    fn line_to(&mut self, x: f64, y: f64) {
+        match self.path.last_mut() {
+            Some(subpath) => subpath.push((x, y)),
+            None => self.move_to(x, y),
+        }
+    }
+
+    fn arc(&mut self, x: f64, y: f64, radius: f64, start: f64, end: f64) {
+        self.trace_arc(x, y, radius, radius, start, end);
+    }
+
+    fn ellipse(&mut self, x: f64, y: f64, rx: f64, ry: f64, start: f64, end: f64) {
+        self.trace_arc(x, y, rx, ry, start, end);
+    }
+
+    fn rect(&mut self, x: f64, y: f64, w: f64, h: f64) {
+        self.path
+            .push(vec![(x, y), (x + w, y), (x + w, y + h), (x, y + h), (x, y)]);
+        self.move_to(x, y);
+    }
+
+    fn close_path(&mut self) {
+        if let Some(&first) = self.path.last().and_then(|subpath| subpath.first()) {
+            self.line_to(first.0, first.1);
+            self.move_to(first.0, first.1);
+        }
+    }
+
+    fn stroke(&mut self) {
+        let radius = self.state.line_width / 2.0;
+        let mut covered = vec![false; (self.width * self.height) as usize];
+
+        let segments: Vec<((f64, f64), (f64, f64))> = self
+            .path
+            .iter()
+            .filter(|subpath| subpath.len() > 1)
+            .flat_map(|subpath| subpath.windows(2).map(|pair| (pair[0], pair[1])))
+            .collect();
+
+        for (a, b) in segments {
+            let xs = self.pixel_range(a.0.min(b.0) - radius, a.0.max(b.0) + radius, self.width);
+            let ys = self.pixel_range(a.1.min(b.1) - radius, a.1.max(b.1) + radius, self.height);
+
+            for y in ys {
+                for x in xs.clone() {
+                    let point = (x as f64 + 0.5, y as f64 + 0.5);
+                    if segment_distance(point, a, b) <= radius {
+                        covered[(y * self.width + x) as usize] = true;
+                    }
+                }
+            }
+        }
+
+        let color = self.state.stroke;
+        for (index, _) in covered.iter().enumerate().filter(|(_, hit)| **hit) {
+            let index = index as u32;
+            self.blend(index % self.width, index / self.width, color);
+        }
+    }
+
+    fn fill(&mut self) {
+        let edges: Vec<((f64, f64), (f64, f64))> = self
+            .path
+            .iter()
+            .filter(|subpath| subpath.len() > 2)
+            .flat_map(|subpath| {
+                let closing = (*subpath.last().unwrap(), subpath[0]);
+                subpath
+                    .windows(2)
+                    .map(|pair| (pair[0], pair[1]))
+                    .chain(std::iter::once(closing))
+            })
+            .collect();
+
+        let color = self.state.fill;
+        for y in 0..self.height {
+            let center = y as f64 + 0.5;
+            let mut crossings: Vec<(f64, i32)> = edges
+                .iter()
+                .filter(|(a, b)| (a.1 <= center) != (b.1 <= center))
+                .map(|(a, b)| {
+                    let x = a.0 + (center - a.1) / (b.1 - a.1) * (b.0 - a.0);
+                    (x, if b.1 > a.1 { 1 } else { -1 })
+                })
+                .collect();
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                if winding != 0 {
+                    for x in self.pixel_range(pair[0].0, pair[1].0, self.width) {
+                        self.blend(x, y, color);
+                    }
+                }
+            }
+        }
+    }
+
+    fn line_width(&self) -> f64 {
+        self.state.line_width
+    }
+
+    fn set_line_width(&mut self, width: f64) {
+        self.state.line_width = width;
+    }
+
+    fn set_line_join(&mut self, _join: &str) {}
+
+    fn set_stroke_style(&mut self, color: &str) {
+        self.state.stroke = PixelBuffer::color(color, self.state.stroke);
+    }
+
+    fn set_fill_style(&mut self, color: &str) {
+        self.state.fill = PixelBuffer::color(color, self.state.fill);
+    }
+
+    fn set_global_alpha(&mut self, alpha: f64) {
+        self.state.alpha = alpha.clamp(0.0, 1.0);
+    }
+
+    fn save(&mut self) {
+        self.saved.push(self.state);
+    }
+
+    fn restore(&mut self) {
+        if let Some(state) = self.saved.pop() {
+            self.state = state;
+        }
+    }
+
+    fn fill_rect(&mut self, x: f64, y: f64, w: f64, h: f64) {
+        let color = self.state.fill;
+        for row in self.pixel_range(y, y + h, self.height) {
+            for column in self.pixel_range(x, x + w, self.width) {
+                self.blend(column, row, color);
+            }
+        }
+    }
+
+    fn clear_rect(&mut self, x: f64, y: f64, w: f64, h: f64) {
+        for row in self.pixel_range(y, y + h, self.height) {
+            for column in self.pixel_range(x, x + w, self.width) {
+                let index = ((row * self.width + column) * 4) as usize;
+                self.data[index..index + 4].copy_from_slice(&[0, 0, 0, 0]);
+            }
+        }
+    }
+
+    fn read(&self, rect: &Rect) -> Result<Region, JsValue> {
+        let mut data = vec![0; (rect.width * rect.height * 4) as usize];
+        let inside = rect.intersect(&Rect::new(0, 0, self.width, self.height));
+
+        for row in inside.y..inside.y + inside.height {
+            let src = ((row * self.width + inside.x) * 4) as usize;
+            let dst = (((row - rect.y) * rect.width + (inside.x - rect.x)) * 4) as usize;
+            let len = inside.width as usize * 4;
+            data[dst..dst + len].copy_from_slice(&self.data[src..src + len]);
+        }
+
+        Ok(Region { rect: *rect, data })
+    }
+
+    fn write(&mut self, region: &Region) -> Result<(), JsValue> {
+        let rect = region.rect;
+        let inside = rect.intersect(&Rect::new(0, 0, self.width, self.height));
+
+        for row in inside.y..inside.y + inside.height {
+            let dst = ((row * self.width + inside.x) * 4) as usize;
+            let src = (((row - rect.y) * rect.width + (inside.x - rect.x)) * 4) as usize;
+            let len = inside.width as usize * 4;
+            self.data[dst..dst + len].copy_from_slice(&region.data[src..src + len]);
+        }
+
+        Ok(())
+    }
+}
+
+fn segment_distance(point: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = dx * dx + dy * dy;
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        (((point.0 - a.0) * dx + (point.1 - a.1) * dy) / length).clamp(0.0, 1.0)
+    };
+
+    let closest = (a.0 + t * dx, a.1 + t * dy);
+    ((point.0 - closest.0).powi(2) + (point.1 - closest.1).powi(2)).sqrt()
+}
@@ -0,0 +1,355 @@
+//! Selection masks, the pixels a selection covers whatever its shape.
+
+use wasm_bindgen::JsValue;
+use web_sys::CanvasRenderingContext2d;
+
+use crate::{
+    raster::{Rect, Region},
+    transform::Transform,
+};
+
+/// Length of the marching ants dashes, in view pixels.
+const ANT_LENGTH: f64 = 4.0;
+/// How fast the ants march, in view pixels per second.
+const ANT_SPEED: f64 = 16.0;
+
+/// How a new selection merges with the existing one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Combine {
+    Add,
+    Subtract,
+    Intersect,
+}
+
+/// Coverage of the pixels of `rect`, one byte per pixel row by row, 255
+/// meaning fully selected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mask {
+    pub rect: Rect,
+    pub coverage: Vec<u8>,
+}
+
+impl Mask {
+    /// Selects all of `rect`.
+    pub fn full(rect: Rect) -> Mask {
+        Mask {
+            rect,
+            coverage: vec![255; (rect.width * rect.height) as usize],
+        }
+    }
+
+    /// Selects the pixels whose centers are inside the closed outline through
+    /// `points`, with the nonzero rule, clamped to a `width` x `height`
+    /// canvas.
+    pub fn polygon(points: &[(f64, f64)], width: u32, height: u32) -> Mask {
+        if points.len() < 3 {
+            return Mask::full(Rect::new(0, 0, 0, 0));
+        }
+
+        let min_x = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+        let min_y = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+        let max_x = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+        let max_y = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+        let rect = Rect::from_points((min_x, min_y), (max_x, max_y), 0.0, width, height);
+
+        let edges: Vec<((f64, f64), (f64, f64))> = points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(a, b)| (*a, *b))
+            .collect();
+
+        let mut coverage = vec![0; (rect.width * rect.height) as usize];
+        for row in 0..rect.height {
+            let center = (rect.y + row) as f64 + 0.5;
+            let mut crossings: Vec<(f64, i32)> = edges
+                .iter()
+                .filter(|(a, b)| (a.1 <= center) != (b.1 <= center))
+                .map(|(a, b)| {
+                    let x = a.0 + (center - a.1) / (b.1 - a.1) * (b.0 - a.0);
+                    (x, if b.1 > a.1 { 1 } else { -1 })
+                })
+                .collect();
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                if winding == 0 {
+                    continue;
+                }
+                for column in 0..rect.width {
+                    let x = (rect.x + column) as f64 + 0.5;
+                    if x >= pair[0].0 && x < pair[1].0 {
+                        coverage[(row * rect.width + column) as usize] = 255;
+                    }
+                }
+            }
+        }
+
+        Mask { rect, coverage }
+    }
+
+    /// Wraps a coverage mask of a whole `width` x `height` canvas, such as
+    /// one from `utils::region_mask`.
+    pub fn from_coverage(coverage: Vec<u8>, width: u32, height: u32) -> Mask {
+        Mask {
+            rect: Rect::new(0, 0, width, height),
+            coverage,
+        }
+        .trimmed()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coverage.iter().all(|value| *value == 0)
+    }
+
+    /// Coverage of document pixel (`x`, `y`), 0 outside the mask.
+    pub fn at(&self, x: u32, y: u32) -> u8 {
+        let rect = &self.rect;
+        if x < rect.x || y < rect.y || x >= rect.x + rect.width || y >= rect.y + rect.height {
+            return 0;
+        }
+        self.coverage[((y - rect.y) * rect.width + (x - rect.x)) as usize]
+    }
+
+    /// Same selection with `rect` shrunk to the selected pixels.
+    pub fn trimmed(&self) -> Mask {
+        let mut bounds = Rect::new(0, 0, 0, 0);
+        for (index, _) in self
+            .coverage
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| **value != 0)
+        {
+            let index = index as u32;
+            bounds = bounds.union(&Rect::new(
+                self.rect.x + index % self.rect.width,
+                self.rect.y + index / self.rect.width,
+                1,
+                1,
+            ));
+        }
+        self.cropped(&bounds)
+    }
+
+    /// Merges `other` into this selection.
+    pub fn combine(&self, other: &Mask, how: Combine) -> Mask {
+        let rect = match how {
+            Combine::Add => self.rect.union(&other.rect),
+            Combine::Subtract => self.rect,
+            Combine::Intersect => self.rect.intersect(&other.rect),
+        };
+        let mut combined = self.cropped(&rect);
+
+        for (index, value) in combined.coverage.iter_mut().enumerate() {
+            let index = index as u32;
+            let theirs = other.at(rect.x + index % rect.width, rect.y + index / rect.width) as u32;
+            let ours = *value as u32;
+            *value = match how {
+                Combine::Add => ours.max(theirs),
+                Combine::Subtract => ours * (255 - theirs) / 255,
+                Combine::Intersect => ours.min(theirs),
+            } as u8;
+        }
+
+        combined.trimmed()
+    }
+
+    /// Edges between selected and unselected pixels, counting pixels at
+    /// least half covered as selected, joined into as few segments as
+    /// possible.
+    pub fn outline(&self) -> Vec<((f64, f64), (f64, f64))> {
+        let rect = self.rect;
+        let inside = |x: i64, y: i64| {
+            x >= 0 && y >= 0 && self.at(rect.x + x as u32, rect.y + y as u32) >= 128
+        };
+        let (width, height) = (rect.width as i64, rect.height as i64);
+        let (left, top) = (rect.x as f64, rect.y as f64);
+        let mut segments = vec![];
+
+        for y in 0..=height {
+            let mut start = None;
+            for x in 0..=width {
+                let edge = x < width && inside(x, y - 1) != inside(x, y);
+                match (edge, start) {
+                    (true, None) => start = Some(x),
+                    (false, Some(from)) => {
+                        let y = top + y as f64;
+                        segments.push(((left + from as f64, y), (left + x as f64, y)));
+                        start = None;
+                    }
+                    _ => (),
+                }
+            }
+        }
+        for x in 0..=width {
+            let mut start = None;
+            for y in 0..=height {
+                let edge = y < height && inside(x - 1, y) != inside(x, y);
+                match (edge, start) {
+                    (true, None) => start = Some(y),
+                    (false, Some(from)) => {
+                        let x = left + x as f64;
+                        segments.push(((x, top + from as f64), (x, top + y as f64)));
+                        start = None;
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        segments
+    }
+
+    /// Makes the unselected pixels of `region` transparent.
+    pub fn keep(&self, region: &mut Region) {
+        self.each_pixel(region, |pixel, amount| {
+            pixel[3] = (pixel[3] as f64 * amount).round() as u8;
+        });
+    }
+
+    /// Empties the selected pixels of `region`, turning them into
+    /// `background` when given and transparent otherwise.
+    pub fn erase(&self, region: &mut Region, background: Option<(u8, u8, u8, u8)>) {
+        self.each_pixel(region, |pixel, amount| match background {
+            Some((r, g, b, a)) => {
+                for (channel, value) in [r, g, b, a].iter().enumerate() {
+                    let current = pixel[channel] as f64;
+                    pixel[channel] = (current + (*value as f64 - current) * amount).round() as u8;
+                }
+            }
+            None => pixel[3] = (pixel[3] as f64 * (1.0 - amount)).round() as u8,
+        });
+    }
+
+    /// Keeps the pixels of `changed` where selected and those of `original`
+    /// elsewhere, blending them along partially selected edges. Both cover
+    /// the same area.
+    pub fn mix(&self, original: &Region, changed: &mut Region) {
+        let rect = changed.rect;
+        for (index, (pixel, before)) in changed
+            .data
+            .chunks_exact_mut(4)
+            .zip(original.data.chunks_exact(4))
+            .enumerate()
+        {
+            let index = index as u32;
+            let amount =
+                self.at(rect.x + index % rect.width, rect.y + index / rect.width) as f64 / 255.0;
+            for channel in 0..4 {
+                let from = before[channel] as f64;
+                pixel[channel] = (from + (pixel[channel] as f64 - from) * amount).round() as u8;
+            }
+        }
+    }
+
+    /// Where this mask ends up once the pixels it covers are placed by
+    /// `transform`, clamped to a `width` x `height` canvas.
+    pub fn transformed(&self, transform: &Transform, width: u32, height: u32) -> Mask {
+        let rect = transform.bounds(0.0, width, height);
+        let mut coverage = vec![0; (rect.width * rect.height) as usize];
+
+        for row in 0..rect.height {
+            for column in 0..rect.width {
+                let point = ((rect.x + column) as f64 + 0.5, (rect.y + row) as f64 + 0.5);
+                let (x, y) = transform.to_local(point);
+                let source_x = x / transform.scale_x + transform.width / 2.0;
+                let source_y = y / transform.scale_y + transform.height / 2.0;
+                if source_x < 0.0
+                    || source_y < 0.0
+                    || source_x >= transform.width
+                    || source_y >= transform.height
+                {
+                    continue;
+                }
+
+                coverage[(row * rect.width + column) as usize] =
+                    self.at(self.rect.x + source_x as u32, self.rect.y + source_y as u32);
+            }
+        }
+
+        Mask { rect, coverage }
+    }
+
+    /// Copies the part of the mask inside `rect`, which may reach past it.
+    fn cropped(&self, rect: &Rect) -> Mask {
+        let mut coverage = Vec::with_capacity((rect.width * rect.height) as usize);
+        for y in rect.y..rect.y + rect.height {
+            for x in rect.x..rect.x + rect.width {
+                coverage.push(self.at(x, y));
+            }
+        }
+        Mask {
+            rect: *rect,
+            coverage,
+        }
+    }
+
+    /// Calls `update` with each pixel of `region` and the fraction of it
+    /// that is selected.
+    fn each_pixel(&self, region: &mut Region, mut update: impl FnMut(&mut [u8], f64)) {
+        let rect = region.rect;
+        for (index, pixel) in region.data.chunks_exact_mut(4).enumerate() {
+            let index = index as u32;
+            let coverage = self.at(rect.x + index % rect.width, rect.y + index / rect.width);
+            update(pixel, coverage as f64 / 255.0);
+        }
+    }
+}
+
+/// Outlines `points` with marching ants, dashes alternating black and white
+/// that crawl along as time passes. `zoom` keeps them the same size on
+/// screen.
+pub fn draw_ants(
+    ctx: &CanvasRenderingContext2d,
+    points: &[(f64, f64)],
+    zoom: f64,
+) -> Result<(), JsValue> {
+    let first = match points.first() {
+        Some(first) => first,
+        None => return Ok(()),
+    };
+
+    ctx.begin_path();
+    ctx.move_to(first.0, first.1);
+    for (x, y) in points.iter().skip(1) {
+        ctx.line_to(*x, *y);
+    }
+    ctx.close_path();
+    stroke_ants(ctx, zoom)
+}
+
+/// Outlines the selected area of `mask` with marching ants.
+pub fn draw_mask_ants(
+    ctx: &CanvasRenderingContext2d,
+    mask: &Mask,
+    zoom: f64,
+) -> Result<(), JsValue> {
+    ctx.begin_path();
+    for (from, to) in mask.outline() {
+        ctx.move_to(from.0, from.1);
+        ctx.line_to(to.0, to.1);
+    }
+    stroke_ants(ctx, zoom)
+}
+
+/// Strokes the current path as marching ants and clears it.
+fn stroke_ants(ctx: &CanvasRenderingContext2d, zoom: f64) -> Result<(), JsValue> {
+    let dash = ANT_LENGTH / zoom;
+    let offset = js_sys::Date::now() / 1000.0 * ANT_SPEED / zoom % (dash * 2.0);
+
+    ctx.save();
+    ctx.set_global_alpha(1.0);
+    ctx.set_line_width(1.0 / zoom);
+    ctx.set_stroke_style_str("white");
+    let dashed = ctx.set_line_dash(&js_sys::Array::of2(&dash.into(), &dash.into()));
+    ctx.set_line_dash_offset(-offset);
+    ctx.stroke();
+    ctx.set_stroke_style_str("black");
+    ctx.set_line_dash_offset(dash - offset);
+    ctx.stroke();
+    ctx.restore();
+
+    ctx.begin_path();
+    dashed
+}
@@ -0,0 +1,372 @@
+use std::f64::consts::PI;
+
+use crate::{
+    raster::Surface,
+    utils::{pen_stroke, two_point_distance, PenDynamics},
+    Color,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillStyle {
+    Outline,
+    Filled,
+    OutlineFill,
+}
+
+impl FillStyle {
+    pub fn from_name(style: &str) -> Option<FillStyle> {
+        match style {
+            "outline" => Some(FillStyle::Outline),
+            "filled" => Some(FillStyle::Filled),
+            "outline-fill" => Some(FillStyle::OutlineFill),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FillStyle::Outline => "outline",
+            FillStyle::Filled => "filled",
+            FillStyle::OutlineFill => "outline-fill",
+        }
+    }
+
+    fn strokes(&self) -> bool {
+        *self != FillStyle::Filled
+    }
+
+    fn fills(&self) -> bool {
+        *self != FillStyle::Outline
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Circle {
+        cx: f64,
+        cy: f64,
+        radius: f64,
+    },
+    Ellipse {
+        cx: f64,
+        cy: f64,
+        rx: f64,
+        ry: f64,
+    },
+    Rectangle {
+        x: f64,
+        y: f64,
+        w: f64,
+        h: f64,
+    },
+    RoundedRectangle {
+        x: f64,
+        y: f64,
+        w: f64,
+        h: f64,
+        radius: f64,
+    },
+    /// Straight line, only ever stroked.
+    Line {
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+    },
+    /// Freehand pen stroke, only ever stroked. Each segment runs from the
+    /// end of the previous one, or `start`, to its point, modulated by the
+    /// stylus as it was drawn.
+    Pen {
+        start: (f64, f64),
+        segments: Vec<((f64, f64), PenDynamics)>,
+    },
+}
+
+impl Shape {
+    /// Name of the kind of shape, as the matching tool is called.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shape::Circle { .. } => "circle",
+            Shape::Ellipse { .. } => "ellipse",
+            Shape::Rectangle { .. } => "rectangle",
+            Shape::RoundedRectangle { .. } => "rounded-rectangle",
+            Shape::Line { .. } => "line",
+            Shape::Pen { .. } => "pen",
+        }
+    }
+
+    /// Whether the shape is a line, which is stroked whatever the style.
+    fn is_line(&self) -> bool {
+        matches!(self, Shape::Line { .. } | Shape::Pen { .. })
+    }
+
+    /// Top-left and bottom-right corners of the area covered by the shape,
+    /// not counting the stroke.
+    pub fn bounds(&self) -> ((f64, f64), (f64, f64)) {
+        match *self {
+            Shape::Circle { cx, cy, radius } => {
+                ((cx - radius, cy - radius), (cx + radius, cy + radius))
+            }
+            Shape::Ellipse { cx, cy, rx, ry } => ((cx - rx, cy - ry), (cx + rx, cy + ry)),
+            Shape::Rectangle { x, y, w, h } | Shape::RoundedRectangle { x, y, w, h, .. } => {
+                ((x, y), (x + w, y + h))
+            }
+            Shape::Line { x1, y1, x2, y2 } => ((x1.min(x2), y1.min(y2)), (x1.max(x2), y1.max(y2))),
+            Shape::Pen {
+                start,
+                ref segments,
+            } => segments
+                .iter()
+                .fold((start, start), |((x0, y0), (x1, y1)), ((x, y), _)| {
+                    ((x0.min(*x), y0.min(*y)), (x1.max(*x), y1.max(*y)))
+                }),
+        }
+    }
+
+    /// The same shape moved by `dx`, `dy`.
+    pub fn translated(&self, dx: f64, dy: f64) -> Shape {
+        match *self {
+            Shape::Circle { cx, cy, radius } => Shape::Circle {
+                cx: cx + dx,
+                cy: cy + dy,
+                radius,
+            },
+            Shape::Ellipse { cx, cy, rx, ry } => Shape::Ellipse {
+                cx: cx + dx,
+                cy: cy + dy,
+                rx,
+                ry,
+            },
+            Shape::Rectangle { x, y, w, h } => Shape::Rectangle {
+                x: x + dx,
+                y: y + dy,
+                w,
+                h,
+            },
+            Shape::RoundedRectangle { x, y, w, h, radius } => Shape::RoundedRectangle {
+                x: x + dx,
+                y: y + dy,
+                w,
+                h,
+                radius,
+            },
+            Shape::Line { x1, y1, x2, y2 } => Shape::Line {
+                x1: x1 + dx,
+                y1: y1 + dy,
+                x2: x2 + dx,
+                y2: y2 + dy,
+            },
+            Shape::Pen {
+                start: (x, y),
+                ref segments,
+            } => Shape::Pen {
+                start: (x + dx, y + dy),
+                segments: segments
+                    .iter()
+                    .map(|&((x, y), dynamics)| ((x + dx, y + dy), dynamics))
+                    .collect(),
+            },
+        }
+    }
+
+    /// How far `point` is from the outline, and whether it is inside.
+    fn distance(&self, (px, py): (f64, f64)) -> (f64, bool) {
+        match *self {
+            Shape::Circle { cx, cy, radius } => {
+                let d = two_point_distance(px, py, cx, cy);
+                ((d - radius).abs(), d <= radius)
+            }
+            Shape::Ellipse { cx, cy, rx, ry } => {
+                // Measured on the ellipse scaled to a circle, which is close
+                // enough to pick it.
+                let (rx, ry) = (rx.max(f64::EPSILON), ry.max(f64::EPSILON));
+                let d = ((px - cx) / rx).hypot((py - cy) / ry);
+                ((d - 1.0).abs() * rx.min(ry), d <= 1.0)
+            }
+            Shape::Rectangle { x, y, w, h } | Shape::RoundedRectangle { x, y, w, h, .. } => {
+                let dx = (x - px).max(px - x - w);
+                let dy = (y - py).max(py - y - h);
+                let outside = dx.max(0.0).hypot(dy.max(0.0));
+                (outside.max(-dx.max(dy)), dx <= 0.0 && dy <= 0.0)
+            }
+            Shape::Line { x1, y1, x2, y2 } => {
+                (segment_distance((px, py), (x1, y1), (x2, y2)), false)
+            }
+            Shape::Pen {
+                start,
+                ref segments,
+            } => {
+                let mut from = start;
+                let mut nearest = two_point_distance(px, py, start.0, start.1);
+                for (to, _) in segments {
+                    nearest = nearest.min(segment_distance((px, py), from, *to));
+                    from = *to;
+                }
+                (nearest, false)
+            }
+        }
+    }
+
+    fn trace<S: Surface>(&self, surface: &mut S) {
+        surface.begin_path();
+        match *self {
+            Shape::Circle { cx, cy, radius } => {
+                surface.arc(cx, cy, radius, 0.0, 2.0 * PI);
+            }
+            Shape::Ellipse { cx, cy, rx, ry } => {
+                surface.ellipse(cx, cy, rx, ry, 0.0, 2.0 * PI);
+            }
+            Shape::Rectangle { x, y, w, h } => {
+                surface.rect(x, y, w, h);
+            }
+            Shape::Line { x1, y1, x2, y2 } => {
+                surface.move_to(x1, y1);
+                surface.line_to(x2, y2);
+            }
+            // Drawn segment by segment, see `draw`.
+            Shape::Pen { .. } => (),
+            Shape::RoundedRectangle { x, y, w, h, radius } => {
+                let r = radius.min(w / 2.0).min(h / 2.0).max(0.0);
+                surface.move_to(x + r, y);
+                surface.line_to(x + w - r, y);
+                surface.arc(x + w - r, y + r, r, -PI / 2.0, 0.0);
+                surface.line_to(x + w, y + h - r);
+                surface.arc(x + w - r, y + h - r, r, 0.0, PI / 2.0);
+                surface.line_to(x + r, y + h);
+                surface.arc(x + r, y + h - r, r, PI / 2.0, PI);
+                surface.line_to(x, y + r);
+                surface.arc(x + r, y + r, r, PI, 1.5 * PI);
+                surface.close_path();
+            }
+        }
+    }
+
+    /// Draws the shape with the surface's current stroke settings, filling
+    /// it with `fill_color` when the style asks for it.
+    pub fn draw<S: Surface>(&self, surface: &mut S, style: FillStyle, fill_color: &str) {
+        if let Shape::Pen { start, segments } = self {
+            pen_stroke(surface, *start, segments);
+            return;
+        }
+        let style = if self.is_line() {
+            FillStyle::Outline
+        } else {
+            style
+        };
+        self.trace(surface);
+
+        if style.fills() {
+            surface.save();
+            surface.set_fill_style(fill_color);
+            surface.fill();
+            surface.restore();
+        }
+        if style.strokes() {
+            surface.stroke();
+        }
+
+        surface.begin_path();
+    }
+}
+
+pub struct ShapeSettings {
+    pub style: FillStyle,
+    pub fill_color: Option<Color>,
+    pub corner_radius: f64,
+}
+
+impl Default for ShapeSettings {
+    fn default() -> ShapeSettings {
+        ShapeSettings {
+            style: FillStyle::Outline,
+            fill_color: None,
+            corner_radius: 16.0,
+        }
+    }
+}
+
+impl ShapeSettings {
+    /// Fill colour as css, following the stroke colour unless one was set.
+    pub fn fill_css(&self, stroke_color: &Color) -> String {
+        self.fill_color.as_ref().unwrap_or(stroke_color).to_css()
+    }
+
+    /// `shape` painted with these settings and a `line_width` wide stroke
+    /// of `stroke_color`, kept as an object.
+    pub fn object(&self, shape: Shape, stroke_color: &Color, line_width: f64) -> ShapeObject {
+        ShapeObject {
+            shape,
+            style: self.style,
+            stroke: stroke_color.to_css(),
+            fill: self.fill_css(stroke_color),
+            line_width,
+        }
+    }
+}
+
+/// Shape kept on a vector layer, drawn over its pixels and restyled or moved
+/// at will until the layer is merged down.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeObject {
+    pub shape: Shape,
+    pub style: FillStyle,
+    /// Stroke colour as css.
+    pub stroke: String,
+    /// Fill colour as css.
+    pub fill: String,
+    pub line_width: f64,
+}
+
+impl ShapeObject {
+    /// Draws the shape with its own stroke, leaving the surface's settings
+    /// as they were.
+    pub fn draw<S: Surface>(&self, surface: &mut S) {
+        surface.save();
+        surface.set_line_width(self.line_width);
+        surface.set_stroke_style(&self.stroke);
+        self.shape.draw(surface, self.style, &self.fill);
+        surface.restore();
+    }
+
+    /// Top-left and bottom-right corners of the area painted, stroke
+    /// included.
+    pub fn bounds(&self) -> ((f64, f64), (f64, f64)) {
+        let pad = self.stroke_reach();
+        let ((x0, y0), (x1, y1)) = self.shape.bounds();
+        ((x0 - pad, y0 - pad), (x1 + pad, y1 + pad))
+    }
+
+    /// Whether `point` is on the painted shape or within `reach` of it.
+    pub fn contains(&self, point: (f64, f64), reach: f64) -> bool {
+        let (distance, inside) = self.shape.distance(point);
+        let fills = self.style.fills() && !self.shape.is_line();
+        (fills && inside) || distance <= self.stroke_reach() + reach
+    }
+
+    /// How far the stroke reaches out of the outline.
+    fn stroke_reach(&self) -> f64 {
+        match &self.shape {
+            Shape::Pen { segments, .. } => {
+                let widest = segments
+                    .iter()
+                    .map(|(_, dynamics)| dynamics.width_factor)
+                    .fold(1.0, f64::max);
+                self.line_width * widest / 2.0
+            }
+            shape if shape.is_line() || self.style.strokes() => self.line_width / 2.0,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Distance from `point` to the segment running from `from` to `to`.
+fn segment_distance(point: (f64, f64), from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (vx, vy) = (to.0 - from.0, to.1 - from.1);
+    let length = vx * vx + vy * vy;
+    let t = if length > 0.0 {
+        (((point.0 - from.0) * vx + (point.1 - from.1) * vy) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    two_point_distance(point.0, point.1, from.0 + t * vx, from.1 + t * vy)
+}
@@ -0,0 +1,210 @@
+//! SVG export, keeping what the document knows the geometry of as vector
+//! elements: shapes and pen strokes, on vector layers or painted on raster
+//! layers since anything else changed their pixels, and text objects. The
+//! rest of the pixels of each layer, like fills and imported images, are
+//! embedded as images under them.
+
+use std::fmt::Write;
+
+use crate::{
+    shapes::{FillStyle, Shape, ShapeObject},
+    text::{TextAlign, TextBaseline, TextObject},
+    utils::pen_runs,
+};
+
+/// SVG document being written, layer by layer from the bottom.
+pub struct Svg(String);
+
+impl Svg {
+    pub fn new(width: u32, height: u32) -> Svg {
+        Svg(format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" \
+             xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+             width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n",
+            width, height
+        ))
+    }
+
+    /// Starts the group of a layer drawn at `opacity`, titled with its
+    /// name.
+    pub fn begin_layer(&mut self, name: &str, opacity: f64) {
+        self.0.push_str("<g");
+        if opacity < 1.0 {
+            let _ = write!(self.0, " opacity=\"{}\"", opacity);
+        }
+        let _ = writeln!(self.0, "><title>{}</title>", escape(name));
+    }
+
+    pub fn end_layer(&mut self) {
+        self.0.push_str("</g>\n");
+    }
+
+    /// Embeds pixels covering `width` x `height` from the top left, given
+    /// as a data url.
+    pub fn image(&mut self, width: u32, height: u32, data_url: &str) {
+        let _ = writeln!(
+            self.0,
+            "<image x=\"0\" y=\"0\" width=\"{}\" height=\"{}\" xlink:href=\"{}\"/>",
+            width,
+            height,
+            escape(data_url)
+        );
+    }
+
+    pub fn shape(&mut self, object: &ShapeObject) {
+        let paint = paint(object);
+        let _ = match object.shape {
+            Shape::Circle { cx, cy, radius } => writeln!(
+                self.0,
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\"{}/>",
+                cx, cy, radius, paint
+            ),
+            Shape::Ellipse { cx, cy, rx, ry } => writeln!(
+                self.0,
+                "<ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\"{}/>",
+                cx, cy, rx, ry, paint
+            ),
+            Shape::Rectangle { x, y, w, h } => writeln!(
+                self.0,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"{}/>",
+                x, y, w, h, paint
+            ),
+            Shape::RoundedRectangle { x, y, w, h, radius } => writeln!(
+                self.0,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{r}\" ry=\"{r}\"{}/>",
+                x,
+                y,
+                w,
+                h,
+                paint,
+                r = radius.min(w / 2.0).min(h / 2.0).max(0.0)
+            ),
+            Shape::Line { x1, y1, x2, y2 } => writeln!(
+                self.0,
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"{}/>",
+                x1, y1, x2, y2, paint
+            ),
+            Shape::Pen {
+                start,
+                ref segments,
+            } => {
+                // One path for each run of segments the stylus left alike.
+                for (from, run) in pen_runs(start, segments) {
+                    let dynamics = run[0].1;
+                    let mut path = format!("M{} {}", from.0, from.1);
+                    for ((x, y), _) in run {
+                        let _ = write!(path, " L{} {}", x, y);
+                    }
+
+                    let _ = write!(
+                        self.0,
+                        "<path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\" \
+                         stroke-linecap=\"round\" stroke-linejoin=\"round\"",
+                        path,
+                        escape(&object.stroke),
+                        object.line_width * dynamics.width_factor
+                    );
+                    if dynamics.alpha < 1.0 {
+                        let _ = write!(self.0, " opacity=\"{}\"", dynamics.alpha);
+                    }
+                    self.0.push_str("/>\n");
+                }
+                Ok(())
+            }
+        };
+    }
+
+    /// Writes `text` as a `<text>` with a line of it in each `<tspan>`.
+    pub fn text(&mut self, text: &TextObject) {
+        let style = &text.style;
+        let (x, y) = text.position;
+        let anchor = match style.align {
+            TextAlign::Left => "start",
+            TextAlign::Center => "middle",
+            TextAlign::Right => "end",
+        };
+        let baseline = match style.baseline {
+            TextBaseline::Top => "text-before-edge",
+            TextBaseline::Hanging => "hanging",
+            TextBaseline::Middle => "middle",
+            TextBaseline::Alphabetic => "alphabetic",
+            TextBaseline::Ideographic => "ideographic",
+            TextBaseline::Bottom => "text-after-edge",
+        };
+
+        let _ = write!(
+            self.0,
+            "<text font-family=\"{}\" font-size=\"{}\" font-weight=\"{}\" \
+             text-anchor=\"{}\" dominant-baseline=\"{}\" fill=\"{}\"",
+            escape(&style.family),
+            style.size,
+            style.weight,
+            anchor,
+            baseline,
+            escape(&text.color)
+        );
+        if style.italic {
+            self.0.push_str(" font-style=\"italic\"");
+        }
+        if let Some((width, color)) = &style.outline {
+            let _ = write!(
+                self.0,
+                " stroke=\"{}\" stroke-width=\"{}\" stroke-linejoin=\"round\"",
+                escape(color),
+                width
+            );
+        }
+        self.0.push_str(" xml:space=\"preserve\">");
+        for (index, line) in text.content.split('\n').enumerate() {
+            let _ = write!(
+                self.0,
+                "<tspan x=\"{}\" y=\"{}\">{}</tspan>",
+                x,
+                y + index as f64 * style.spacing(),
+                escape(line)
+            );
+        }
+        self.0.push_str("</text>\n");
+    }
+
+    pub fn finish(mut self) -> String {
+        self.0.push_str("</svg>\n");
+        self.0
+    }
+}
+
+/// Fill and stroke attributes of `object`, with a leading space.
+fn paint(object: &ShapeObject) -> String {
+    let style = match object.shape {
+        Shape::Line { .. } | Shape::Pen { .. } => FillStyle::Outline,
+        _ => object.style,
+    };
+    let fill = match style {
+        FillStyle::Outline => "none".to_owned(),
+        _ => escape(&object.fill),
+    };
+    let stroke = match style {
+        FillStyle::Filled => "none".to_owned(),
+        _ => escape(&object.stroke),
+    };
+    format!(
+        " fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\" stroke-linecap=\"round\"",
+        fill, stroke, object.line_width
+    )
+}
+
+/// Escapes `text` for use in attributes and character data.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,669 @@
+//! Text typed with the text tool: its content, caret and selection while
+//! typing, the objects it is kept as afterwards, and how it is laid out and
+//! drawn.
+
+use std::ops::Range;
+
+use js_sys::Array;
+use wasm_bindgen::JsValue;
+use web_sys::CanvasRenderingContext2d;
+
+use crate::raster::Rect;
+
+/// Distance between baselines as a multiple of the font size, unless set
+/// otherwise.
+pub const LINE_SPACING: f64 = 1.2;
+
+/// Colour behind selected text.
+const SELECTION_COLOR: &str = "rgba(0, 120, 215, 0.3)";
+/// How long the caret stays shown, then hidden, in milliseconds.
+const CARET_BLINK: f64 = 500.0;
+/// Length of the dashes framing text being typed, in view pixels.
+const FRAME_DASH: f64 = 4.0;
+
+/// Content of a text being edited, lines separated by `\n`, with a caret
+/// and a selection running from `anchor` to the caret. Offsets are byte
+/// offsets on char boundaries.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextEdit {
+    content: String,
+    caret: usize,
+    anchor: usize,
+    /// Text an input method is composing, shown at the caret but not yet
+    /// part of the content.
+    composition: Option<String>,
+}
+
+impl TextEdit {
+    pub fn new() -> TextEdit {
+        TextEdit::default()
+    }
+
+    /// Edits existing text, the caret at its end.
+    pub fn with_content(content: &str) -> TextEdit {
+        let mut edit = TextEdit::new();
+        edit.insert(content);
+        edit
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    /// Selected range of the content, `None` when nothing is selected.
+    pub fn selection(&self) -> Option<Range<usize>> {
+        match self.anchor.cmp(&self.caret) {
+            std::cmp::Ordering::Less => Some(self.anchor..self.caret),
+            std::cmp::Ordering::Greater => Some(self.caret..self.anchor),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    pub fn selected_text(&self) -> &str {
+        self.selection().map_or("", |range| &self.content[range])
+    }
+
+    pub fn composition(&self) -> Option<&str> {
+        self.composition.as_deref()
+    }
+
+    /// Shows `text` as being composed, or stops showing it with `None`.
+    pub fn set_composition(&mut self, text: Option<String>) {
+        self.composition = text
+            .map(|text| text.replace(['\r', '\n'], " "))
+            .filter(|text| !text.is_empty());
+    }
+
+    /// Types `text` at the caret, replacing the selection.
+    pub fn insert(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let range = self.selection().unwrap_or(self.caret..self.caret);
+        self.content.replace_range(range.clone(), &text);
+        self.caret = range.start + text.len();
+        self.anchor = self.caret;
+    }
+
+    /// Deletes the selection, or the character before the caret.
+    pub fn delete_backward(&mut self) {
+        if self.selection().is_none() {
+            self.anchor = self.previous(self.caret);
+        }
+        self.insert("");
+    }
+
+    /// Deletes the selection, or the character after the caret.
+    pub fn delete_forward(&mut self) {
+        if self.selection().is_none() {
+            self.anchor = self.next(self.caret);
+        }
+        self.insert("");
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = 0;
+        self.caret = self.content.len();
+    }
+
+    /// Moves the caret one character left, or to the start of the
+    /// selection. With `extend` the selection grows or shrinks instead.
+    pub fn move_left(&mut self, extend: bool) {
+        let to = match self.selection() {
+            Some(range) if !extend => range.start,
+            _ => self.previous(self.caret),
+        };
+        self.move_to(to, extend);
+    }
+
+    pub fn move_right(&mut self, extend: bool) {
+        let to = match self.selection() {
+            Some(range) if !extend => range.end,
+            _ => self.next(self.caret),
+        };
+        self.move_to(to, extend);
+    }
+
+    /// Moves the caret to the start of its line.
+    pub fn move_home(&mut self, extend: bool) {
+        let (line, _) = self.caret_line();
+        self.move_to(self.line_range(line).start, extend);
+    }
+
+    pub fn move_end(&mut self, extend: bool) {
+        let (line, _) = self.caret_line();
+        self.move_to(self.line_range(line).end, extend);
+    }
+
+    /// Moves the caret `lines` lines down, or up when negative, keeping it
+    /// as close as it can to the same horizontal position. `measure` gives
+    /// the width of some text.
+    pub fn move_vertical(&mut self, lines: isize, extend: bool, measure: impl Fn(&str) -> f64) {
+        let (line, column) = self.caret_line();
+        let target = line as isize + lines;
+        let to = if target < 0 {
+            0
+        } else if target as usize >= self.line_count() {
+            self.content.len()
+        } else {
+            let range = self.line_range(line);
+            let x = measure(&self.content[range.start..range.start + column]);
+            self.offset_at(target as usize, x, measure)
+        };
+        self.move_to(to, extend);
+    }
+
+    /// Puts the caret on `line` where it is closest to `x`, e.g. under a
+    /// click.
+    pub fn place_caret(
+        &mut self,
+        line: usize,
+        x: f64,
+        extend: bool,
+        measure: impl Fn(&str) -> f64,
+    ) {
+        let line = line.min(self.line_count() - 1);
+        let to = self.offset_at(line, x, measure);
+        self.move_to(to, extend);
+    }
+
+    /// Lines as displayed, with any composition shown at the caret in place
+    /// of the selection.
+    pub fn displayed_lines(&self) -> Vec<String> {
+        let mut shown = self.content.clone();
+        if let Some(composition) = &self.composition {
+            let range = self.selection().unwrap_or(self.caret..self.caret);
+            shown.replace_range(range, composition);
+        }
+        shown.split('\n').map(str::to_owned).collect()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.content.split('\n').count()
+    }
+
+    /// Line the caret is on and its offset within that line.
+    pub fn caret_line(&self) -> (usize, usize) {
+        self.line_of(self.caret)
+    }
+
+    /// Text of `line`, without its line break.
+    pub fn line(&self, line: usize) -> &str {
+        &self.content[self.line_range(line)]
+    }
+
+    /// Distance of the caret from the start of its line.
+    pub fn caret_x(&self, measure: impl Fn(&str) -> f64) -> f64 {
+        let (line, column) = self.caret_line();
+        let start = self.line_range(line).start;
+        measure(&self.content[start..start + column])
+    }
+
+    /// Line holding content offset `offset`, and the offset within it.
+    fn line_of(&self, offset: usize) -> (usize, usize) {
+        let before = &self.content[..offset];
+        let line = before.matches('\n').count();
+        let start = before.rfind('\n').map_or(0, |index| index + 1);
+        (line, offset - start)
+    }
+
+    /// Content range of `line`, without its line break.
+    fn line_range(&self, line: usize) -> Range<usize> {
+        let mut start = 0;
+        for (index, text) in self.content.split('\n').enumerate() {
+            if index == line {
+                return start..start + text.len();
+            }
+            start += text.len() + 1;
+        }
+        self.content.len()..self.content.len()
+    }
+
+    /// Offset on `line` whose position is closest to `x`.
+    fn offset_at(&self, line: usize, x: f64, measure: impl Fn(&str) -> f64) -> usize {
+        let range = self.line_range(line);
+        let text = &self.content[range.clone()];
+        let mut best = (range.start, x.abs());
+        for (index, c) in text.char_indices() {
+            let end = index + c.len_utf8();
+            let distance = (measure(&text[..end]) - x).abs();
+            if distance < best.1 {
+                best = (range.start + end, distance);
+            }
+        }
+        best.0
+    }
+
+    fn move_to(&mut self, offset: usize, extend: bool) {
+        self.caret = offset;
+        if !extend {
+            self.anchor = offset;
+        }
+    }
+
+    fn previous(&self, offset: usize) -> usize {
+        self.content[..offset]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(index, _)| index)
+    }
+
+    fn next(&self, offset: usize) -> usize {
+        self.content[offset..]
+            .chars()
+            .next()
+            .map_or(offset, |c| offset + c.len_utf8())
+    }
+}
+
+/// Where lines sit horizontally against the point text is placed at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+impl TextAlign {
+    pub fn from_name(align: &str) -> Option<TextAlign> {
+        match align {
+            "left" => Some(TextAlign::Left),
+            "center" => Some(TextAlign::Center),
+            "right" => Some(TextAlign::Right),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextAlign::Left => "left",
+            TextAlign::Center => "center",
+            TextAlign::Right => "right",
+        }
+    }
+
+    /// Part of a line's width left of the point.
+    fn share(&self) -> f64 {
+        match self {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => 0.5,
+            TextAlign::Right => 1.0,
+        }
+    }
+}
+
+/// Line of the first row of text going through the point it is placed at,
+/// named as in css.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextBaseline {
+    Top,
+    Hanging,
+    Middle,
+    Alphabetic,
+    Ideographic,
+    Bottom,
+}
+
+impl TextBaseline {
+    pub fn from_name(baseline: &str) -> Option<TextBaseline> {
+        match baseline {
+            "top" => Some(TextBaseline::Top),
+            "hanging" => Some(TextBaseline::Hanging),
+            "middle" => Some(TextBaseline::Middle),
+            "alphabetic" => Some(TextBaseline::Alphabetic),
+            "ideographic" => Some(TextBaseline::Ideographic),
+            "bottom" => Some(TextBaseline::Bottom),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextBaseline::Top => "top",
+            TextBaseline::Hanging => "hanging",
+            TextBaseline::Middle => "middle",
+            TextBaseline::Alphabetic => "alphabetic",
+            TextBaseline::Ideographic => "ideographic",
+            TextBaseline::Bottom => "bottom",
+        }
+    }
+
+    /// Rough distance from the top of a line down to this baseline, in
+    /// ems, used to place the caret and selection.
+    fn depth(&self) -> f64 {
+        match self {
+            TextBaseline::Top => 0.0,
+            TextBaseline::Hanging => 0.2,
+            TextBaseline::Middle => 0.5,
+            TextBaseline::Alphabetic => 1.0,
+            TextBaseline::Ideographic | TextBaseline::Bottom => 1.2,
+        }
+    }
+}
+
+/// Font and layout text is typed and drawn with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    pub family: String,
+    /// Font size in document pixels.
+    pub size: f64,
+    /// Css font weight, 400 for normal and 700 for bold.
+    pub weight: u32,
+    pub italic: bool,
+    pub align: TextAlign,
+    pub baseline: TextBaseline,
+    /// Distance between baselines as a multiple of the size.
+    pub line_height: f64,
+    /// Width and css colour of a stroke around the letters.
+    pub outline: Option<(f64, String)>,
+}
+
+impl Default for TextStyle {
+    fn default() -> TextStyle {
+        TextStyle {
+            family: "serif".to_owned(),
+            size: 48.0,
+            weight: 400,
+            italic: false,
+            align: TextAlign::Left,
+            baseline: TextBaseline::Alphabetic,
+            line_height: LINE_SPACING,
+            outline: None,
+        }
+    }
+}
+
+impl TextStyle {
+    /// The css `font` shorthand for this style.
+    pub fn font(&self) -> String {
+        let style = if self.italic { "italic " } else { "" };
+        format!("{}{} {}px {}", style, self.weight, self.size, self.family)
+    }
+
+    /// Distance between baselines.
+    pub fn spacing(&self) -> f64 {
+        self.size * self.line_height
+    }
+
+    /// Start of a line `width` wide placed at `x`.
+    pub fn line_left(&self, x: f64, width: f64) -> f64 {
+        x - self.align.share() * width
+    }
+
+    /// Top of `line` of text placed at `y`.
+    pub fn line_top(&self, y: f64, line: usize) -> f64 {
+        y + line as f64 * self.spacing() - self.baseline.depth() * self.size
+    }
+
+    /// Line of `edit`, placed at `origin`, at the height of `point` and how
+    /// far along that line `point` is. `measure` gives the width of some
+    /// text.
+    pub fn locate(
+        &self,
+        edit: &TextEdit,
+        origin: (f64, f64),
+        point: (f64, f64),
+        measure: impl Fn(&str) -> f64,
+    ) -> (usize, f64) {
+        let line = ((point.1 - self.line_top(origin.1, 0)) / self.spacing()).max(0.0) as usize;
+        let line = line.min(edit.line_count() - 1);
+        (
+            line,
+            point.0 - self.line_left(origin.0, measure(edit.line(line))),
+        )
+    }
+
+    /// Whether `point` is on one of the lines of `edit` placed at `origin`.
+    pub fn contains(
+        &self,
+        edit: &TextEdit,
+        origin: (f64, f64),
+        point: (f64, f64),
+        measure: impl Fn(&str) -> f64,
+    ) -> bool {
+        (0..edit.line_count()).any(|line| {
+            let width = measure(edit.line(line));
+            let left = self.line_left(origin.0, width);
+            let top = self.line_top(origin.1, line);
+            (left..=left + width).contains(&point.0)
+                && (top..top + self.spacing()).contains(&point.1)
+        })
+    }
+
+    /// Top of the caret of `edit` placed at `origin`.
+    pub fn caret_point(
+        &self,
+        edit: &TextEdit,
+        origin: (f64, f64),
+        measure: impl Fn(&str) -> f64,
+    ) -> (f64, f64) {
+        let (line, _) = edit.caret_line();
+        let left = self.line_left(origin.0, measure(edit.line(line)));
+        (left + edit.caret_x(measure), self.line_top(origin.1, line))
+    }
+
+    /// Moves the caret of `edit` `lines` lines down, or up when negative,
+    /// keeping it under the same point however the lines are aligned.
+    pub fn move_vertical(
+        &self,
+        edit: &mut TextEdit,
+        lines: isize,
+        extend: bool,
+        measure: impl Fn(&str) -> f64,
+    ) {
+        let (line, _) = edit.caret_line();
+        let target = line as isize + lines;
+        if target < 0 || target as usize >= edit.line_count() {
+            return edit.move_vertical(lines, extend, measure);
+        }
+
+        let target = target as usize;
+        let x = self.line_left(0.0, measure(edit.line(line))) + edit.caret_x(&measure);
+        let x = x - self.line_left(0.0, measure(edit.line(target)));
+        edit.place_caret(target, x, extend, measure);
+    }
+
+    /// Box around the lines of `edit` placed at `origin`, as its top-left
+    /// and bottom-right corners.
+    pub fn frame(
+        &self,
+        edit: &TextEdit,
+        origin: (f64, f64),
+        measure: impl Fn(&str) -> f64,
+    ) -> [(f64, f64); 2] {
+        let count = edit.line_count();
+        let (mut left, mut right) = (origin.0, origin.0);
+        for line in 0..count {
+            let width = measure(edit.line(line));
+            left = left.min(self.line_left(origin.0, width));
+            right = right.max(self.line_left(origin.0, width) + width);
+        }
+        [
+            (left, self.line_top(origin.1, 0)),
+            (right, self.line_top(origin.1, count)),
+        ]
+    }
+
+    /// Sets `ctx` up to draw and measure text in this style, until the
+    /// matching `restore()`.
+    fn begin(&self, ctx: &CanvasRenderingContext2d) {
+        ctx.save();
+        ctx.set_font(&self.font());
+        ctx.set_text_align("left");
+        ctx.set_text_baseline(self.baseline.name());
+    }
+}
+
+/// Text kept on a layer as typed, drawn over its pixels until flattened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextObject {
+    /// Lines separated by `\n`.
+    pub content: String,
+    /// Point the text is placed at, in document pixels.
+    pub position: (f64, f64),
+    pub style: TextStyle,
+    /// Fill colour as css.
+    pub color: String,
+}
+
+impl TextObject {
+    pub fn lines(&self) -> Vec<String> {
+        self.content.split('\n').map(str::to_owned).collect()
+    }
+
+    pub fn draw(&self, ctx: &CanvasRenderingContext2d) -> Result<(), JsValue> {
+        ctx.save();
+        ctx.set_fill_style_str(&self.color);
+        let drawn = draw_lines(ctx, &self.lines(), self.position, &self.style);
+        ctx.restore();
+        drawn
+    }
+
+    /// Area of the document the text covers, padded by a pixel and limited
+    /// to a `width` x `height` document.
+    pub fn rect(&self, ctx: &CanvasRenderingContext2d, width: u32, height: u32) -> Option<Rect> {
+        let [min, max] = bounds(ctx, &self.lines(), self.position, &self.style).ok()?;
+        Some(Rect::from_points(min, max, 1.0, width, height))
+    }
+
+    /// Whether `point` is on one of the lines, measured with `ctx`.
+    pub fn contains(&self, ctx: &CanvasRenderingContext2d, point: (f64, f64)) -> bool {
+        self.style.begin(ctx);
+        let edit = TextEdit::with_content(&self.content);
+        let hit = self
+            .style
+            .contains(&edit, self.position, point, measure(ctx));
+        ctx.restore();
+        hit
+    }
+}
+
+/// Width of text in the font set on `ctx`.
+pub fn measure(ctx: &CanvasRenderingContext2d) -> impl Fn(&str) -> f64 + '_ {
+    move |text| {
+        ctx.measure_text(text)
+            .map_or(0.0, |metrics| metrics.width())
+    }
+}
+
+/// Draws `lines` placed at `origin` in `style`, filled with the fill style
+/// set on `ctx`.
+pub fn draw_lines(
+    ctx: &CanvasRenderingContext2d,
+    lines: &[String],
+    origin: (f64, f64),
+    style: &TextStyle,
+) -> Result<(), JsValue> {
+    style.begin(ctx);
+    let width = measure(ctx);
+    let drawn = lines.iter().enumerate().try_for_each(|(index, line)| {
+        let x = style.line_left(origin.0, width(line));
+        let y = origin.1 + index as f64 * style.spacing();
+        ctx.fill_text(line, x, y)?;
+        if let Some((outline, color)) = &style.outline {
+            ctx.set_line_width(*outline);
+            ctx.set_line_join("round");
+            ctx.set_stroke_style_str(color);
+            ctx.stroke_text(line, x, y)?;
+        }
+        Ok(())
+    });
+    ctx.restore();
+    drawn
+}
+
+/// Box around `lines` drawn by `draw_lines`, as its top-left and
+/// bottom-right corners.
+pub fn bounds(
+    ctx: &CanvasRenderingContext2d,
+    lines: &[String],
+    origin: (f64, f64),
+    style: &TextStyle,
+) -> Result<[(f64, f64); 2], JsValue> {
+    let pad = style.outline.as_ref().map_or(0.0, |(width, _)| width / 2.0);
+    let (mut min, mut max) = (origin, origin);
+    style.begin(ctx);
+    let measured = lines.iter().enumerate().try_for_each(|(index, line)| {
+        let metrics = ctx.measure_text(line)?;
+        let x = style.line_left(origin.0, metrics.width());
+        let y = origin.1 + index as f64 * style.spacing();
+        min.0 = min.0.min(x - metrics.actual_bounding_box_left() - pad);
+        min.1 = min.1.min(y - metrics.actual_bounding_box_ascent() - pad);
+        max.0 = max.0.max(x + metrics.actual_bounding_box_right() + pad);
+        max.1 = max.1.max(y + metrics.actual_bounding_box_descent() + pad);
+        Ok(())
+    });
+    ctx.restore();
+    measured.map(|_| [min, max])
+}
+
+/// Draws the text being edited, placed at `origin`, with its selection,
+/// composition and blinking caret, using the fill and stroke style set on
+/// `ctx`. `zoom` keeps the caret one screen pixel wide.
+pub fn draw_edit(
+    ctx: &CanvasRenderingContext2d,
+    edit: &TextEdit,
+    origin: (f64, f64),
+    style: &TextStyle,
+    zoom: f64,
+) -> Result<(), JsValue> {
+    let lines = edit.displayed_lines();
+    draw_lines(ctx, &lines, origin, style)?;
+
+    style.begin(ctx);
+    let width = measure(ctx);
+    let left = |line: usize| style.line_left(origin.0, width(&lines[line]));
+    let top = |line: usize| style.line_top(origin.1, line);
+    ctx.set_line_width(1.0 / zoom);
+
+    // Dragging the frame moves the text.
+    let [(x0, y0), (x1, y1)] = style.frame(edit, origin, &width);
+    let dash: Array = vec![FRAME_DASH / zoom]
+        .into_iter()
+        .map(JsValue::from)
+        .collect();
+    let _ = ctx.set_line_dash(&dash);
+    ctx.stroke_rect(x0, y0, x1 - x0, y1 - y0);
+    let _ = ctx.set_line_dash(&Array::new());
+
+    if let Some(composition) = edit.composition() {
+        // Underlines the composed text where it replaces the selection, as
+        // input methods expect.
+        let (line, start) = edit.line_of(edit.selection().map_or(edit.caret, |range| range.start));
+        let text = &lines[line];
+        let from = left(line) + width(&text[..start]);
+        let to = left(line) + width(&text[..start + composition.len()]);
+        let y = top(line) + style.spacing() - 2.0 / zoom;
+        ctx.begin_path();
+        ctx.move_to(from, y);
+        ctx.line_to(to, y);
+        ctx.stroke();
+    } else if let Some(range) = edit.selection() {
+        ctx.set_fill_style_str(SELECTION_COLOR);
+        let (first, start) = edit.line_of(range.start);
+        let (last, end) = edit.line_of(range.end);
+        for (line, text) in lines.iter().enumerate().take(last + 1).skip(first) {
+            let from = if line == first {
+                width(&text[..start])
+            } else {
+                0.0
+            };
+            let to = if line == last {
+                width(&text[..end])
+            } else {
+                width(text)
+            };
+            ctx.fill_rect(left(line) + from, top(line), to - from, style.spacing());
+        }
+    } else if js_sys::Date::now() % (CARET_BLINK * 2.0) < CARET_BLINK {
+        let (x, y) = style.caret_point(edit, origin, &width);
+        ctx.begin_path();
+        ctx.move_to(x, y);
+        ctx.line_to(x, y + style.spacing());
+        ctx.stroke();
+    }
+    ctx.restore();
+    Ok(())
+}
@@ -0,0 +1,294 @@
+use std::f64::consts::PI;
+
+use crate::raster::{Rect, Surface};
+
+/// On-screen size of the handles, in view pixels.
+pub const HANDLE_SIZE: f64 = 8.0;
+/// How far above the top edge the rotation handle sits, in view pixels.
+pub const ROTATE_OFFSET: f64 = 24.0;
+const MIN_SCALE: f64 = 0.01;
+const ROTATION_STEP: f64 = PI / 12.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Handle {
+    Move,
+    Rotate,
+    /// Scales from the corner or edge in direction (`x`, `y`), each -1, 0
+    /// or 1 in the selection's own frame, keeping the opposite side still.
+    Scale(i8, i8),
+}
+
+/// Placement of a floating selection of `width` x `height` source pixels:
+/// scaled around its center, then rotated, then centered on `center`. A
+/// negative scale flips along that axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub center: (f64, f64),
+    pub width: f64,
+    pub height: f64,
+    pub scale_x: f64,
+    pub scale_y: f64,
+    pub rotation: f64,
+}
+
+impl Transform {
+    /// Places the selection exactly over `rect`.
+    pub fn new(rect: &Rect) -> Transform {
+        Transform {
+            center: (
+                rect.x as f64 + rect.width as f64 / 2.0,
+                rect.y as f64 + rect.height as f64 / 2.0,
+            ),
+            width: rect.width as f64,
+            height: rect.height as f64,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            rotation: 0.0,
+        }
+    }
+
+    /// Canvas `transform()` arguments mapping source pixels, (0, 0) being
+    /// the top-left one, to document coordinates.
+    pub fn matrix(&self) -> [f64; 6] {
+        let (sin, cos) = self.rotation.sin_cos();
+        let a = cos * self.scale_x;
+        let b = sin * self.scale_x;
+        let c = -sin * self.scale_y;
+        let d = cos * self.scale_y;
+        let half_w = self.width / 2.0;
+        let half_h = self.height / 2.0;
+
+        [
+            a,
+            b,
+            c,
+            d,
+            self.center.0 - (a * half_w + c * half_h),
+            self.center.1 - (b * half_w + d * half_h),
+        ]
+    }
+
+    /// Maps a point of the selection's own frame, unrotated and centered on
+    /// (0, 0), to the document.
+    pub fn to_document(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let (sin, cos) = self.rotation.sin_cos();
+        (
+            self.center.0 + x * cos - y * sin,
+            self.center.1 + x * sin + y * cos,
+        )
+    }
+
+    pub fn to_local(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (dx, dy) = (x - self.center.0, y - self.center.1);
+        (dx * cos + dy * sin, -dx * sin + dy * cos)
+    }
+
+    /// Half the on-document size, ignoring flips.
+    fn half_size(&self) -> (f64, f64) {
+        (
+            self.width * self.scale_x.abs() / 2.0,
+            self.height * self.scale_y.abs() / 2.0,
+        )
+    }
+
+    /// Corners in document coordinates, clockwise from the top-left one.
+    pub fn corners(&self) -> [(f64, f64); 4] {
+        let (w, h) = self.half_size();
+        [
+            self.to_document((-w, -h)),
+            self.to_document((w, -h)),
+            self.to_document((w, h)),
+            self.to_document((-w, h)),
+        ]
+    }
+
+    /// Where each handle is drawn, `zoom` keeping the rotation handle at
+    /// the same distance on screen.
+    pub fn handles(&self, zoom: f64) -> Vec<(Handle, (f64, f64))> {
+        let (w, h) = self.half_size();
+        let mut handles: Vec<(Handle, (f64, f64))> = [-1, 0, 1]
+            .iter()
+            .flat_map(|&x| [-1, 0, 1].iter().map(move |&y| (x, y)))
+            .filter(|&(x, y)| x != 0 || y != 0)
+            .map(|(x, y)| {
+                (
+                    Handle::Scale(x, y),
+                    self.to_document((x as f64 * w, y as f64 * h)),
+                )
+            })
+            .collect();
+        handles.push((
+            Handle::Rotate,
+            self.to_document((0.0, -h - ROTATE_OFFSET / zoom)),
+        ));
+        handles
+    }
+
+    /// Picks the handle under document point `point`, or `Move` when it is
+    /// inside the selection.
+    pub fn hit(&self, point: (f64, f64), zoom: f64) -> Option<Handle> {
+        let reach = HANDLE_SIZE / zoom;
+        let handle = self
+            .handles(zoom)
+            .into_iter()
+            .find(|(_, (x, y))| (point.0 - x).abs() <= reach && (point.1 - y).abs() <= reach);
+        if let Some((handle, _)) = handle {
+            return Some(handle);
+        }
+
+        let (x, y) = self.to_local(point);
+        let (w, h) = self.half_size();
+        if x.abs() <= w && y.abs() <= h {
+            Some(Handle::Move)
+        } else {
+            None
+        }
+    }
+
+    /// Result of dragging `handle` from `from` to `to`, starting from this
+    /// transform. `constrain` keeps the aspect ratio when scaling from a
+    /// corner and snaps rotation to 15 degree steps.
+    pub fn drag(
+        &self,
+        handle: Handle,
+        from: (f64, f64),
+        to: (f64, f64),
+        constrain: bool,
+    ) -> Transform {
+        let mut result = *self;
+
+        match handle {
+            Handle::Move => {
+                result.center = (self.center.0 + to.0 - from.0, self.center.1 + to.1 - from.1);
+            }
+            Handle::Rotate => {
+                let angle = |(x, y): (f64, f64)| (y - self.center.1).atan2(x - self.center.0);
+                let mut rotation = self.rotation + angle(to) - angle(from);
+                if constrain {
+                    rotation = (rotation / ROTATION_STEP).round() * ROTATION_STEP;
+                }
+                result.rotation = rotation;
+            }
+            Handle::Scale(ux, uy) => {
+                let (ux, uy) = (ux as f64, uy as f64);
+                let (w, h) = self.half_size();
+                let anchor = (-ux * w, -uy * h);
+                let (px, py) = self.to_local(to);
+
+                // Signed factors, negative once the pointer crosses the anchor.
+                let mut factor_x = if ux != 0.0 {
+                    (px - anchor.0) * ux / (2.0 * w)
+                } else {
+                    1.0
+                };
+                let mut factor_y = if uy != 0.0 {
+                    (py - anchor.1) * uy / (2.0 * h)
+                } else {
+                    1.0
+                };
+                if constrain && ux != 0.0 && uy != 0.0 {
+                    let factor = if factor_x.abs() > factor_y.abs() {
+                        factor_x
+                    } else {
+                        factor_y
+                    };
+                    factor_x = factor;
+                    factor_y = factor;
+                }
+
+                result.scale_x = limit_scale(self.scale_x * factor_x);
+                result.scale_y = limit_scale(self.scale_y * factor_y);
+
+                let (new_w, new_h) = result.half_size();
+                let flip_x = (result.scale_x * self.scale_x).signum();
+                let flip_y = (result.scale_y * self.scale_y).signum();
+                let local_center = (
+                    anchor.0 + ux * flip_x * new_w,
+                    anchor.1 + uy * flip_y * new_h,
+                );
+                result.center = self.to_document(local_center);
+            }
+        }
+
+        result
+    }
+
+    /// Outlines the selection and draws its handles, `zoom` keeping them the
+    /// same size on screen.
+    pub fn draw_frame<S: Surface>(&self, surface: &mut S, zoom: f64) {
+        let corners = self.corners();
+        let (_, h) = self.half_size();
+        let size = HANDLE_SIZE / zoom;
+
+        surface.save();
+        surface.set_global_alpha(1.0);
+        surface.set_line_width(1.0 / zoom);
+        surface.set_stroke_style("#1b95e0");
+        surface.set_fill_style("white");
+
+        surface.begin_path();
+        surface.move_to(corners[0].0, corners[0].1);
+        for (x, y) in corners.iter().skip(1) {
+            surface.line_to(*x, *y);
+        }
+        surface.close_path();
+        let top = self.to_document((0.0, -h));
+        let knob = self.to_document((0.0, -h - ROTATE_OFFSET / zoom));
+        surface.move_to(top.0, top.1);
+        surface.line_to(knob.0, knob.1);
+        surface.stroke();
+
+        for (handle, (x, y)) in self.handles(zoom) {
+            surface.begin_path();
+            if handle == Handle::Rotate {
+                surface.arc(x, y, size / 2.0, 0.0, 2.0 * PI);
+            } else {
+                surface.rect(x - size / 2.0, y - size / 2.0, size, size);
+            }
+            surface.fill();
+            surface.stroke();
+        }
+
+        surface.begin_path();
+        surface.restore();
+    }
+
+    pub fn flip_horizontal(&mut self) {
+        self.scale_x = -self.scale_x;
+    }
+
+    pub fn flip_vertical(&mut self) {
+        self.scale_y = -self.scale_y;
+    }
+
+    pub fn nudge(&mut self, dx: f64, dy: f64) {
+        self.center = (self.center.0 + dx, self.center.1 + dy);
+    }
+
+    /// Area the transformed selection covers, grown by `padding` for
+    /// resampled edges and clamped to a `width` x `height` document.
+    pub fn bounds(&self, padding: f64, width: u32, height: u32) -> Rect {
+        let corners = self.corners();
+        let min_x = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+        let min_y = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+        let max_x = corners
+            .iter()
+            .map(|c| c.0)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let max_y = corners
+            .iter()
+            .map(|c| c.1)
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        Rect::from_points((min_x, min_y), (max_x, max_y), padding, width, height)
+    }
+}
+
+fn limit_scale(scale: f64) -> f64 {
+    if scale.abs() < MIN_SCALE {
+        MIN_SCALE.copysign(scale)
+    } else {
+        scale
+    }
+}
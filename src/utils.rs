@@ -1,9 +1,11 @@
-use regex::Regex;
-use std::rc::Rc;
-use wasm_bindgen::{prelude::wasm_bindgen, Clamped, JsCast, JsValue};
-use web_sys::{CanvasRenderingContext2d, Document, Element, HtmlCanvasElement, ImageData};
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+use web_sys::{CanvasRenderingContext2d, Document, HtmlCanvasElement};
 
-use crate::Color;
+use crate::{
+    raster::{Rect, Region, Surface},
+    transform::Transform,
+    Color,
+};
 
 #[wasm_bindgen]
 extern "C" {
@@ -22,100 +24,323 @@ pub fn set_panic_hook() {
     console_error_panic_hook::set_once();
 }
 
-pub fn get_document() -> Document {
-    web_sys::window().unwrap().document().unwrap()
+pub fn get_document() -> Result<Document, JsValue> {
+    web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| JsValue::from_str("no document to draw in"))
 }
 
-pub fn get_client_canvas() -> Result<HtmlCanvasElement, Element> {
-    let document = get_document();
+/// Creates a detached canvas of the given size along with its context.
+pub fn create_canvas(
+    width: u32,
+    height: u32,
+) -> Result<(HtmlCanvasElement, CanvasRenderingContext2d), JsValue> {
+    let canvas = get_document()?
+        .create_element("canvas")?
+        .dyn_into::<HtmlCanvasElement>()?;
+    canvas.set_width(width);
+    canvas.set_height(height);
 
-    document
-        .get_element_by_id("canvas")
+    let context = canvas
+        .get_context("2d")?
         .unwrap()
-        .dyn_into::<web_sys::HtmlCanvasElement>()
+        .dyn_into::<CanvasRenderingContext2d>()?;
+
+    Ok((canvas, context))
+}
+
+/// Copies `region` onto a canvas of its size, its top-left pixel at (0, 0).
+pub fn region_canvas(region: &Region) -> Result<HtmlCanvasElement, JsValue> {
+    let (canvas, mut context) = create_canvas(region.rect.width, region.rect.height)?;
+    context.write(&region.translated(0, 0))?;
+    Ok(canvas)
+}
+
+/// Draws `region` placed by `transform`, resampled with the context's image
+/// smoothing.
+pub fn draw_transformed(
+    ctx: &CanvasRenderingContext2d,
+    region: &Region,
+    transform: &Transform,
+) -> Result<(), JsValue> {
+    let canvas = region_canvas(region)?;
+    let [a, b, c, d, e, f] = transform.matrix();
+
+    ctx.save();
+    let drawn = ctx
+        .transform(a, b, c, d, e, f)
+        .and_then(|_| ctx.draw_image_with_html_canvas_element(&canvas, 0.0, 0.0));
+    ctx.restore();
+    drawn
 }
 
 pub fn two_point_distance(x1: f64, y1: f64, x2: f64, y2: f64) -> f64 {
     ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt()
 }
 
-pub fn get_content_inside_rect(
-    ctx: Rc<CanvasRenderingContext2d>,
-    x: f64,
-    y: f64,
-    w: f64,
-    h: f64,
-) -> Result<ImageData, JsValue> {
-    ctx.get_image_data(x, y, w, h)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FillOptions {
+    /// Largest RGBA distance from the clicked colour still considered part
+    /// of the region.
+    pub tolerance: f64,
+    /// Grow the region from the clicked pixel instead of taking every
+    /// similar pixel of the image.
+    pub contiguous: bool,
+    /// Partially cover pixels just outside the region, blending into
+    /// anti-aliased edges.
+    pub antialias: bool,
 }
 
-pub fn fill(
-    ctx: Rc<CanvasRenderingContext2d>,
-    x: usize,
-    y: usize,
+impl Default for FillOptions {
+    fn default() -> FillOptions {
+        FillOptions {
+            tolerance: 30.0,
+            contiguous: true,
+            antialias: false,
+        }
+    }
+}
+
+/// Computes the region similar to the colour at (`x`, `y`) as a coverage
+/// mask with one byte per pixel, 255 meaning fully inside.
+pub fn region_mask(
+    data: &[u8],
     width: u32,
     height: u32,
-    color: &Color,
-) -> Result<(), JsValue> {
-    set_panic_hook();
-    let image = ctx.get_image_data(0.0, 0.0, width as f64, height as f64)?;
-    let mut data = image.data();
+    x: u32,
+    y: u32,
+    options: &FillOptions,
+) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let mut mask = vec![0; width * height];
+    if x as usize >= width || y as usize >= height {
+        return mask;
+    }
+
+    let pixel = |index: usize| {
+        let i = index * 4;
+        (data[i], data[i + 1], data[i + 2], data[i + 3])
+    };
+    let target = pixel(y as usize * width + x as usize);
+    let matches = |index: usize| euclidian_distance(target, pixel(index)) <= options.tolerance;
 
-    let replacement_color = color.value();
+    if options.contiguous {
+        let mut stack = vec![(x as usize, y as usize)];
 
-    let index = y * (width as usize * 4) + x * 4;
-    let replace_color = (
-        data[index],
-        data[index + 1],
-        data[index + 2],
-        data[index + 3],
-    );
+        while let Some((x, y)) = stack.pop() {
+            let row = y * width;
+            if mask[row + x] != 0 || !matches(row + x) {
+                continue;
+            }
 
-    if replacement_color == replace_color {
-        return Ok(());
+            let mut left = x;
+            while left > 0 && mask[row + left - 1] == 0 && matches(row + left - 1) {
+                left -= 1;
+            }
+            let mut right = x;
+            while right + 1 < width && mask[row + right + 1] == 0 && matches(row + right + 1) {
+                right += 1;
+            }
+            mask[row + left..=row + right].fill(255);
+
+            let neighbours = [
+                y.checked_sub(1),
+                Some(y + 1).filter(|&below| below < height),
+            ];
+            for ny in neighbours.iter().flatten().copied() {
+                let mut in_run = false;
+                for nx in left..=right {
+                    let index = ny * width + nx;
+                    let open = mask[index] == 0 && matches(index);
+                    if open && !in_run {
+                        stack.push((nx, ny));
+                    }
+                    in_run = open;
+                }
+            }
+        }
+    } else {
+        for (index, value) in mask.iter_mut().enumerate() {
+            if matches(index) {
+                *value = 255;
+            }
+        }
     }
 
-    let mut stack: Vec<usize> = vec![index];
+    if options.antialias {
+        let feather = options.tolerance.max(32.0);
+        let mut edges = vec![];
 
-    while !stack.is_empty() {
-        let node = stack.pop().unwrap();
+        for y in 0..height {
+            for x in 0..width {
+                let index = y * width + x;
+                if mask[index] != 0 {
+                    continue;
+                }
 
-        if node > (width * height * 4 - 4) as usize {
-            continue;
+                let touches = (x > 0 && mask[index - 1] == 255)
+                    || (x + 1 < width && mask[index + 1] == 255)
+                    || (y > 0 && mask[index - width] == 255)
+                    || (y + 1 < height && mask[index + width] == 255);
+                if !touches {
+                    continue;
+                }
+
+                let distance = euclidian_distance(target, pixel(index)) - options.tolerance;
+                let coverage = (1.0 - distance / feather).clamp(0.0, 1.0);
+                edges.push((index, (coverage * 254.0).round() as u8));
+            }
+        }
+
+        for (index, coverage) in edges {
+            mask[index] = coverage;
         }
+    }
 
-        let current_color = (data[node], data[node + 1], data[node + 2], data[node + 3]);
+    mask
+}
+
+/// Fills the region around (`x`, `y`) with `color` and returns what the
+/// pixels it changed held before, or `None` when nothing changed, like when
+/// every pixel of the region already has that colour.
+pub fn fill<S: Surface>(
+    surface: &mut S,
+    x: usize,
+    y: usize,
+    width: u32,
+    height: u32,
+    color: &Color,
+    options: &FillOptions,
+) -> Result<Option<Region>, JsValue> {
+    set_panic_hook();
+    if x >= width as usize || y >= height as usize {
+        return Ok(None);
+    }
+    let (r, g, b, a) = color.value();
+    let replacement = [r, g, b, a];
+    // Only pixels exactly like the clicked one would be filled, so nothing
+    // can change. Otherwise similar ones still might.
+    if options.contiguous && options.tolerance == 0.0 && !options.antialias {
+        let seed = surface.read(&Rect::new(x as u32, y as u32, 1, 1))?;
+        if seed.data[..] == replacement {
+            return Ok(None);
+        }
+    }
 
-        if current_color != replace_color
-            && euclidian_distance(replacement_color, current_color) < 30.0
-        {
-            continue;
+    let mut image = surface.read(&Rect::new(0, 0, width, height))?;
+    let mask = region_mask(&image.data, width, height, x as u32, y as u32, options);
+    let blended = |data: &[u8], index: usize, coverage: u8| -> [u8; 4] {
+        let amount = coverage as f64 / 255.0;
+        let mut pixel = [0; 4];
+        for (channel, value) in replacement.iter().enumerate() {
+            let current = data[index * 4 + channel] as f64;
+            pixel[channel] = (current + (*value as f64 - current) * amount).round() as u8;
         }
+        pixel
+    };
 
-        data[node] = replacement_color.0;
-        data[node + 1] = replacement_color.1;
-        data[node + 2] = replacement_color.2;
-        data[node + 3] = replacement_color.3;
+    let mut changed = Rect::new(0, 0, 0, 0);
+    for (index, coverage) in mask.iter().enumerate().filter(|(_, value)| **value != 0) {
+        if blended(&image.data, index, *coverage)[..] != image.data[index * 4..index * 4 + 4] {
+            let index = index as u32;
+            changed = changed.union(&Rect::new(index % width, index / width, 1, 1));
+        }
+    }
+    if changed.is_empty() {
+        return Ok(None);
+    }
+    let before = image.crop(&changed);
 
-        stack.push(node + 4);
-        stack.push(node - 4);
-        stack.push(node - width as usize * 4);
-        stack.push(node + width as usize * 4);
+    for (index, coverage) in mask.iter().enumerate().filter(|(_, value)| **value != 0) {
+        let pixel = blended(&image.data, index, *coverage);
+        image.data[index * 4..index * 4 + 4].copy_from_slice(&pixel);
     }
+    surface.write(&image.crop(&changed))?;
 
-    ctx.put_image_data(
-        &ImageData::new_with_u8_clamped_array_and_sh(Clamped(&mut data.0[..]), width, height)?,
-        0.0,
-        0.0,
-    )
+    Ok(Some(before))
 }
 
 fn euclidian_distance(target_color: (u8, u8, u8, u8), replace_color: (u8, u8, u8, u8)) -> f64 {
     let r = (target_color.0 as f64 - replace_color.0 as f64).powi(2);
     let g = (target_color.1 as f64 - replace_color.1 as f64).powi(2);
     let b = (target_color.2 as f64 - replace_color.2 as f64).powi(2);
+    let a = (target_color.3 as f64 - replace_color.3 as f64).powi(2);
+
+    (r + g + b + a).sqrt()
+}
 
-    (r + g + b).sqrt()
+/// How a pen stroke segment is modulated by stylus input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PenDynamics {
+    pub width_factor: f64,
+    pub alpha: f64,
+}
+
+impl PenDynamics {
+    pub const NONE: PenDynamics = PenDynamics {
+        width_factor: 1.0,
+        alpha: 1.0,
+    };
+
+    /// Maps pointer pressure (0 to 1) and tilt (-90 to 90 degrees) to a
+    /// width and opacity. Only pens report meaningful values, so mouse and
+    /// touch input draw at full width and opacity.
+    pub fn from_input(pointer_type: &str, pressure: f32, tilt_x: i32, tilt_y: i32) -> PenDynamics {
+        if pointer_type != "pen" {
+            return PenDynamics::NONE;
+        }
+
+        let pressure = (pressure as f64).clamp(0.0, 1.0);
+        let tilt = (tilt_x.abs().max(tilt_y.abs()) as f64 / 90.0).min(1.0);
+
+        PenDynamics {
+            width_factor: (0.2 + 0.8 * pressure) * (1.0 + tilt),
+            alpha: 0.3 + 0.7 * pressure,
+        }
+    }
+}
+
+/// Point a pen segment ends at, with the dynamics it was drawn with.
+pub type PenSegment = ((f64, f64), PenDynamics);
+
+/// Splits the segments of a pen stroke from `start` into runs the stylus
+/// left alike, each with the point it starts from.
+pub fn pen_runs(start: (f64, f64), segments: &[PenSegment]) -> Vec<((f64, f64), &[PenSegment])> {
+    let mut runs = vec![];
+    let mut from = start;
+    let mut rest = segments;
+    while let Some((_, dynamics)) = rest.first() {
+        let run = rest
+            .iter()
+            .take_while(|(_, other)| other == dynamics)
+            .count();
+        runs.push((from, &rest[..run]));
+        from = rest[run - 1].0;
+        rest = &rest[run..];
+    }
+    runs
+}
+
+/// Strokes a pen stroke from `start` at the surface's line width scaled by
+/// the dynamics of each segment. Each run of alike segments is one path, so
+/// a translucent stroke covers the pixels where its segments meet once.
+pub fn pen_stroke<S: Surface>(surface: &mut S, start: (f64, f64), segments: &[PenSegment]) {
+    let width = surface.line_width();
+    for (from, run) in pen_runs(start, segments) {
+        let dynamics = run[0].1;
+        surface.save();
+        surface.set_line_width(width * dynamics.width_factor);
+        surface.set_line_join("round");
+        surface.set_global_alpha(dynamics.alpha);
+        surface.begin_path();
+        surface.move_to(from.0, from.1);
+        for ((x, y), _) in run {
+            surface.line_to(*x, *y);
+        }
+        surface.stroke();
+        surface.begin_path();
+        surface.restore();
+    }
 }
 
 pub fn define_postition(line_start: f64, offset: f64) -> f64 {
@@ -129,7 +354,3 @@ pub fn define_postition(line_start: f64, offset: f64) -> f64 {
 pub fn define_distance(line_start: f64, offset: f64) -> f64 {
     (line_start - offset).abs()
 }
-
-pub fn match_input(input: &str) -> bool {
-    Regex::new(r"^[a-zA-z0-9 ]$").unwrap().is_match(input)
-}
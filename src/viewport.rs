@@ -0,0 +1,87 @@
+use web_sys::CanvasRenderingContext2d;
+
+pub const MIN_ZOOM: f64 = 0.05;
+pub const MAX_ZOOM: f64 = 32.0;
+
+/// Maps document coordinates to the on-screen canvases: a view point is
+/// `document * zoom + pan`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub zoom: f64,
+    pub pan_x: f64,
+    pub pan_y: f64,
+    view_width: f64,
+    view_height: f64,
+    doc_width: f64,
+    doc_height: f64,
+}
+
+impl Viewport {
+    pub fn new(view_width: u32, view_height: u32, doc_width: u32, doc_height: u32) -> Viewport {
+        Viewport {
+            zoom: 1.0,
+            pan_x: 0.0,
+            pan_y: 0.0,
+            view_width: view_width as f64,
+            view_height: view_height as f64,
+            doc_width: doc_width as f64,
+            doc_height: doc_height as f64,
+        }
+    }
+
+    /// Updates the document size, e.g. after loading a project.
+    pub fn set_document_size(&mut self, width: u32, height: u32) {
+        self.doc_width = width as f64;
+        self.doc_height = height as f64;
+    }
+
+    pub fn to_document(&self, x: f64, y: f64) -> (f64, f64) {
+        ((x - self.pan_x) / self.zoom, (y - self.pan_y) / self.zoom)
+    }
+
+    pub fn to_view(&self, x: f64, y: f64) -> (f64, f64) {
+        (x * self.zoom + self.pan_x, y * self.zoom + self.pan_y)
+    }
+
+    /// Sets the zoom level to `zoom` while keeping the document point under
+    /// view point (`x`, `y`) in place.
+    pub fn zoom_to(&mut self, x: f64, y: f64, zoom: f64) {
+        let (doc_x, doc_y) = self.to_document(x, y);
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.pan_x = x - doc_x * self.zoom;
+        self.pan_y = y - doc_y * self.zoom;
+    }
+
+    pub fn pan_by(&mut self, dx: f64, dy: f64) {
+        self.pan_x += dx;
+        self.pan_y += dy;
+    }
+
+    /// Scales the whole document to fit the view and centers it.
+    pub fn zoom_to_fit(&mut self) {
+        self.zoom = (self.view_width / self.doc_width)
+            .min(self.view_height / self.doc_height)
+            .clamp(MIN_ZOOM, MAX_ZOOM);
+        self.pan_x = (self.view_width - self.doc_width * self.zoom) / 2.0;
+        self.pan_y = (self.view_height - self.doc_height * self.zoom) / 2.0;
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= 0.0 && y >= 0.0 && x < self.doc_width && y < self.doc_height
+    }
+
+    /// Makes `ctx` draw in document coordinates.
+    pub fn apply(&self, ctx: &CanvasRenderingContext2d) {
+        let _ = ctx.set_transform(self.zoom, 0.0, 0.0, self.zoom, self.pan_x, self.pan_y);
+        // Show individual pixels when zoomed in instead of blurring them.
+        ctx.set_image_smoothing_enabled(self.zoom < 1.0);
+    }
+
+    /// Clears the whole canvas behind `ctx`, whatever its transform.
+    pub fn clear(&self, ctx: &CanvasRenderingContext2d) {
+        ctx.save();
+        let _ = ctx.reset_transform();
+        ctx.clear_rect(0.0, 0.0, self.view_width, self.view_height);
+        ctx.restore();
+    }
+}
@@ -0,0 +1,56 @@
+//! Parsing css colours.
+
+use paint_assemble::{
+    color::{parse, ParseColorError},
+    Color,
+};
+
+#[test]
+fn named_colours_match_the_css_table() {
+    assert_eq!(parse("gray"), Ok((128, 128, 128, 255)));
+    assert_eq!(parse("Pink"), Ok((255, 192, 203, 255)));
+    assert_eq!(parse(" rebeccapurple "), Ok((102, 51, 153, 255)));
+    assert_eq!(parse("transparent"), Ok((0, 0, 0, 0)));
+}
+
+#[test]
+fn hex_colours_of_every_length() {
+    assert_eq!(parse("#f80"), Ok((255, 136, 0, 255)));
+    assert_eq!(parse("#f808"), Ok((255, 136, 0, 136)));
+    assert_eq!(parse("#1B95E0"), Ok((27, 149, 224, 255)));
+    assert_eq!(parse("#1b95e080"), Ok((27, 149, 224, 128)));
+}
+
+#[test]
+fn colour_functions_in_both_syntaxes() {
+    assert_eq!(parse("rgb(255, 0, 128)"), Ok((255, 0, 128, 255)));
+    assert_eq!(parse("rgba(100%, 0%, 50%, 0.5)"), Ok((255, 0, 128, 128)));
+    assert_eq!(parse("rgb(255 0 128 / 25%)"), Ok((255, 0, 128, 64)));
+    assert_eq!(parse("hsl(120, 100%, 25%)"), Ok((0, 128, 0, 255)));
+    assert_eq!(parse("hsla(0.5turn 100% 50% / 1)"), Ok((0, 255, 255, 255)));
+    assert_eq!(parse("hwb(0 0% 0%)"), Ok((255, 0, 0, 255)));
+
+    assert_eq!("red".parse(), Ok(Color::Custom(255, 0, 0, 255)));
+    assert_eq!("#fff".parse(), Ok(Color::White));
+}
+
+#[test]
+fn bad_colours_are_reported() {
+    assert_eq!(
+        parse("blurple"),
+        Err(ParseColorError::Unknown("blurple".to_owned()))
+    );
+    assert_eq!(
+        parse("#12345"),
+        Err(ParseColorError::BadHex("#12345".to_owned()))
+    );
+    assert!(matches!(
+        parse("rgb(255, 0%, 0)"),
+        Err(ParseColorError::BadArguments(_))
+    ));
+    assert!(matches!(
+        parse("rgb(inf 0 0)"),
+        Err(ParseColorError::BadArguments(_))
+    ));
+    assert!(parse("lab(50% 40 59)").is_err());
+}
@@ -0,0 +1,124 @@
+//! Flood fill behaviour on the in-memory pixel buffer.
+
+use paint_assemble::{
+    raster::{PixelBuffer, Rect, Surface},
+    utils::{fill, FillOptions},
+    Color,
+};
+
+const RED: (u8, u8, u8, u8) = (255, 0, 0, 255);
+const WHITE: (u8, u8, u8, u8) = (255, 255, 255, 255);
+const BLACK: (u8, u8, u8, u8) = (0, 0, 0, 255);
+
+/// White 10x10 buffer split by a black vertical line at x = 5.
+fn split_buffer() -> PixelBuffer {
+    let mut buffer = PixelBuffer::new(10, 10);
+    buffer.set_fill_style("white");
+    buffer.fill_rect(0.0, 0.0, 10.0, 10.0);
+    buffer.set_fill_style("black");
+    buffer.fill_rect(5.0, 0.0, 1.0, 10.0);
+    buffer
+}
+
+#[test]
+fn contiguous_fill_stops_at_borders_and_edges() {
+    let mut buffer = split_buffer();
+    let red = Color::Custom(255, 0, 0, 255);
+
+    let before = fill(&mut buffer, 9, 0, 10, 10, &red, &FillOptions::default()).unwrap();
+
+    assert_eq!(before.unwrap().rect, Rect::new(6, 0, 4, 10));
+    assert_eq!(buffer.pixel(9, 9), RED);
+    assert_eq!(buffer.pixel(5, 5), BLACK);
+    assert_eq!(buffer.pixel(0, 0), WHITE);
+    assert_eq!(buffer.pixel(4, 9), WHITE);
+}
+
+#[test]
+fn global_fill_replaces_every_similar_pixel() {
+    let mut buffer = split_buffer();
+    let red = Color::Custom(255, 0, 0, 255);
+    let options = FillOptions {
+        contiguous: false,
+        ..FillOptions::default()
+    };
+
+    fill(&mut buffer, 0, 0, 10, 10, &red, &options).unwrap();
+
+    assert_eq!(buffer.pixel(0, 0), RED);
+    assert_eq!(buffer.pixel(9, 9), RED);
+    assert_eq!(buffer.pixel(5, 5), BLACK);
+}
+
+#[test]
+fn tolerance_decides_which_colours_join_the_region() {
+    let mut buffer = split_buffer();
+    buffer.set_fill_style("#f0f0f0");
+    buffer.fill_rect(0.0, 0.0, 2.0, 2.0);
+    let red = Color::Custom(255, 0, 0, 255);
+
+    let strict = FillOptions {
+        tolerance: 0.0,
+        ..FillOptions::default()
+    };
+    fill(&mut buffer, 3, 3, 10, 10, &red, &strict).unwrap();
+    assert_eq!(buffer.pixel(0, 0), (240, 240, 240, 255));
+
+    fill(&mut buffer, 0, 0, 10, 10, &red, &FillOptions::default()).unwrap();
+    assert_eq!(buffer.pixel(0, 0), RED);
+}
+
+#[test]
+fn seed_outside_the_image_is_ignored() {
+    let mut buffer = split_buffer();
+    let red = Color::Custom(255, 0, 0, 255);
+
+    let before = fill(&mut buffer, 10, 3, 10, 10, &red, &FillOptions::default()).unwrap();
+
+    assert!(before.is_none());
+    assert_eq!(buffer.pixel(9, 3), WHITE);
+}
+
+#[test]
+fn filling_with_the_clicked_colour_changes_nothing() {
+    let mut buffer = split_buffer();
+    let white = Color::Custom(255, 255, 255, 255);
+
+    let before = fill(&mut buffer, 0, 0, 10, 10, &white, &FillOptions::default()).unwrap();
+
+    assert!(before.is_none());
+}
+
+#[test]
+fn global_fill_from_the_clicked_colour_still_replaces_similar_pixels() {
+    let mut buffer = split_buffer();
+    buffer.set_fill_style("#f0f0f0");
+    buffer.fill_rect(8.0, 8.0, 2.0, 2.0);
+    let white = Color::Custom(255, 255, 255, 255);
+    let options = FillOptions {
+        contiguous: false,
+        ..FillOptions::default()
+    };
+
+    let before = fill(&mut buffer, 0, 0, 10, 10, &white, &options).unwrap();
+
+    assert_eq!(before.unwrap().rect, Rect::new(8, 8, 2, 2));
+    assert_eq!(buffer.pixel(9, 9), WHITE);
+    assert_eq!(buffer.pixel(5, 5), BLACK);
+}
+
+#[test]
+fn fill_hands_back_what_it_painted_over() {
+    let mut buffer = split_buffer();
+    let red = Color::Custom(255, 0, 0, 255);
+
+    let before = fill(&mut buffer, 0, 0, 10, 10, &red, &FillOptions::default())
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(before.rect, Rect::new(0, 0, 5, 10));
+    assert!(before
+        .data
+        .chunks_exact(4)
+        .all(|pixel| pixel == [255, 255, 255, 255]));
+}
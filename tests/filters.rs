@@ -0,0 +1,75 @@
+//! Image filters applied to raw RGBA buffers.
+
+use paint_assemble::filters::Filter;
+
+fn pixels(colors: &[(u8, u8, u8, u8)]) -> Vec<u8> {
+    colors
+        .iter()
+        .flat_map(|&(r, g, b, a)| vec![r, g, b, a])
+        .collect()
+}
+
+#[test]
+fn color_filters_keep_alpha() {
+    let mut data = pixels(&[(255, 0, 0, 255), (10, 20, 30, 128)]);
+    Filter::Invert.apply(&mut data, 2, 1);
+    assert_eq!(data, pixels(&[(0, 255, 255, 255), (245, 235, 225, 128)]));
+
+    let mut data = pixels(&[(255, 0, 0, 255)]);
+    Filter::Grayscale.apply(&mut data, 1, 1);
+    assert_eq!(data, pixels(&[(54, 54, 54, 255)]));
+
+    let mut data = pixels(&[(100, 100, 100, 255)]);
+    Filter::Sepia.apply(&mut data, 1, 1);
+    assert_eq!(data, pixels(&[(135, 120, 94, 255)]));
+}
+
+#[test]
+fn brightness_and_contrast() {
+    let mut data = pixels(&[(100, 100, 100, 255), (200, 200, 200, 255)]);
+    Filter::BrightnessContrast {
+        brightness: 0.0,
+        contrast: 0.0,
+    }
+    .apply(&mut data, 2, 1);
+    assert_eq!(data, pixels(&[(100, 100, 100, 255), (200, 200, 200, 255)]));
+
+    Filter::BrightnessContrast {
+        brightness: 0.1,
+        contrast: -1.0,
+    }
+    .apply(&mut data, 2, 1);
+    assert_eq!(data, pixels(&[(153, 153, 153, 255), (153, 153, 153, 255)]));
+}
+
+#[test]
+fn blur_spreads_color_without_darkening_transparent_edges() {
+    let mut data = vec![0; 9 * 4];
+    data[4 * 4..5 * 4].copy_from_slice(&[255, 0, 0, 255]);
+
+    Filter::GaussianBlur { radius: 1.0 }.apply(&mut data, 3, 3);
+
+    let center = &data[4 * 4..5 * 4];
+    let corner = &data[0..4];
+    assert!(center[3] < 255 && center[3] > corner[3]);
+    assert!(corner[3] > 0);
+    assert_eq!(&corner[..3], &[255, 0, 0]);
+}
+
+#[test]
+fn sharpen_increases_edge_contrast() {
+    let mut data = pixels(&[
+        (50, 50, 50, 255),
+        (50, 50, 50, 255),
+        (200, 200, 200, 255),
+        (200, 200, 200, 255),
+    ]);
+
+    Filter::from_name("sharpen", |_| None)
+        .unwrap()
+        .apply(&mut data, 4, 1);
+
+    assert!(data[4] < 50);
+    assert!(data[8] > 200);
+    assert_eq!(Filter::from_name("emboss", |_| None), None);
+}
@@ -0,0 +1,240 @@
+//! Saving and loading the native project format.
+
+use std::convert::TryInto;
+
+use paint_assemble::{
+    project::{LayerData, Project, ProjectError, ToolSettings, FORMAT_VERSION},
+    shapes::{FillStyle, Shape, ShapeObject},
+    text::{TextAlign, TextObject, TextStyle},
+};
+
+fn sample() -> Project {
+    let mut background = vec![255; 4 * 3 * 4];
+    background[20..24].copy_from_slice(&[10, 20, 30, 255]);
+
+    Project {
+        width: 4,
+        height: 3,
+        transparent: true,
+        layers: vec![
+            LayerData {
+                id: 0,
+                name: "Background".to_owned(),
+                visible: true,
+                opacity: 1.0,
+                pixels: background,
+                vector: false,
+                shapes: vec![],
+                texts: vec![],
+            },
+            LayerData {
+                id: 3,
+                name: "Ink ✎".to_owned(),
+                visible: false,
+                opacity: 0.5,
+                pixels: (0..48).collect(),
+                vector: true,
+                shapes: vec![
+                    ShapeObject {
+                        shape: Shape::RoundedRectangle {
+                            x: 0.5,
+                            y: 1.0,
+                            w: 2.0,
+                            h: 1.5,
+                            radius: 0.25,
+                        },
+                        style: FillStyle::OutlineFill,
+                        stroke: "rgba(255, 0, 0, 1)".to_owned(),
+                        fill: "rgba(0, 0, 255, 1)".to_owned(),
+                        line_width: 3.0,
+                    },
+                    ShapeObject {
+                        shape: Shape::Line {
+                            x1: 0.0,
+                            y1: 0.0,
+                            x2: 4.0,
+                            y2: 3.0,
+                        },
+                        style: FillStyle::Outline,
+                        stroke: "rgba(0, 0, 0, 1)".to_owned(),
+                        fill: "rgba(0, 0, 0, 1)".to_owned(),
+                        line_width: 1.0,
+                    },
+                ],
+                texts: vec![TextObject {
+                    content: "first\nsecond ✓".to_owned(),
+                    position: (1.5, 2.0),
+                    style: TextStyle {
+                        family: "Georgia, serif".to_owned(),
+                        weight: 700,
+                        align: TextAlign::Right,
+                        outline: Some((2.0, "#ffffff".to_owned())),
+                        ..TextStyle::default()
+                    },
+                    color: "rgba(0, 0, 0, 0.5)".to_owned(),
+                }],
+            },
+        ],
+        active_layer: 3,
+        tools: ToolSettings {
+            stroke_width: 8.0,
+            stroke_color: (255, 0, 0, 255),
+            shape_style: FillStyle::OutlineFill,
+            shape_fill: Some((0, 0, 255, 255)),
+            ..ToolSettings::default()
+        },
+        palette: vec![(1, 2, 3, 255), (4, 5, 6, 128)],
+        history_limit: 1024,
+    }
+}
+
+#[test]
+fn project_round_trips() {
+    let project = sample();
+    assert_eq!(Project::decode(&project.encode()), Ok(project));
+}
+
+#[test]
+fn unknown_chunks_and_trailing_fields_are_skipped() {
+    let project = sample();
+    let mut bytes = project.encode();
+
+    // A newer writer appending a chunk and a field to the palette chunk.
+    let palette_at = bytes.windows(4).position(|tag| tag == b"PALT").unwrap();
+    let length = u32::from_le_bytes(bytes[palette_at + 4..palette_at + 8].try_into().unwrap());
+    bytes[palette_at + 4..palette_at + 8].copy_from_slice(&(length + 2).to_le_bytes());
+    bytes.splice(
+        palette_at + 8 + length as usize..palette_at + 8 + length as usize,
+        [7, 7],
+    );
+    bytes.extend_from_slice(b"NEW!");
+    bytes.extend_from_slice(&3u32.to_le_bytes());
+    bytes.extend_from_slice(&[1, 2, 3]);
+
+    assert_eq!(Project::decode(&bytes), Ok(project));
+}
+
+#[test]
+fn files_needing_a_newer_reader_are_rejected() {
+    let mut bytes = sample().encode();
+    bytes[6..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+
+    assert_eq!(
+        Project::decode(&bytes),
+        Err(ProjectError::UnsupportedVersion(FORMAT_VERSION + 1))
+    );
+}
+
+#[test]
+fn files_with_objects_need_a_reader_that_knows_their_chunks() {
+    let bytes = sample().encode();
+
+    assert_eq!(u16::from_le_bytes([bytes[6], bytes[7]]), 4);
+}
+
+#[test]
+fn files_without_objects_stay_readable_by_older_readers() {
+    let mut project = sample();
+    for layer in &mut project.layers {
+        layer.vector = false;
+        layer.shapes.clear();
+    }
+    let bytes = project.encode();
+    assert_eq!(u16::from_le_bytes([bytes[6], bytes[7]]), 3);
+
+    for layer in &mut project.layers {
+        layer.texts.clear();
+    }
+    let bytes = project.encode();
+    assert_eq!(u16::from_le_bytes([bytes[6], bytes[7]]), 1);
+    assert_eq!(Project::decode(&bytes), Ok(project));
+}
+
+#[test]
+fn broken_files_are_rejected() {
+    let bytes = sample().encode();
+
+    assert_eq!(Project::decode(b"\x89PNG"), Err(ProjectError::NotAProject));
+    assert_eq!(
+        Project::decode(&bytes[..bytes.len() - 3]),
+        Err(ProjectError::Truncated)
+    );
+}
+
+/// Offset of the body of the first `tag` chunk of `bytes`, and its length.
+fn chunk_at(bytes: &[u8], tag: &[u8; 4]) -> (usize, usize) {
+    let at = bytes.windows(4).position(|found| found == tag).unwrap();
+    let length = u32::from_le_bytes(bytes[at + 4..at + 8].try_into().unwrap());
+    (at + 8, length as usize)
+}
+
+#[test]
+fn numbers_out_of_range_are_rejected() {
+    let mut bytes = sample().encode();
+    let (layer_at, _) = chunk_at(&bytes, b"LAYR");
+    // The opacity follows the id and the visibility flag.
+    bytes[layer_at + 5..layer_at + 13].copy_from_slice(&f64::NAN.to_le_bytes());
+    assert_eq!(
+        Project::decode(&bytes),
+        Err(ProjectError::Invalid("number out of range"))
+    );
+
+    let mut bytes = sample().encode();
+    let (shape_at, length) = chunk_at(&bytes, b"SHAP");
+    // The line width ends the chunk.
+    let end = shape_at + length;
+    bytes[end - 8..end].copy_from_slice(&(-2f64).to_le_bytes());
+    assert_eq!(
+        Project::decode(&bytes),
+        Err(ProjectError::Invalid("negative size"))
+    );
+}
+
+#[test]
+fn documents_without_a_transparency_flag_are_opaque() {
+    // Version 1 files end the document chunk after the active layer.
+    let mut bytes = sample().encode();
+    let document_at = bytes.windows(4).position(|tag| tag == b"DOC ").unwrap();
+    bytes[document_at + 4..document_at + 8].copy_from_slice(&12u32.to_le_bytes());
+    bytes.remove(document_at + 8 + 12);
+
+    let project = Project::decode(&bytes).unwrap();
+    assert!(!project.transparent);
+    assert_eq!(project.layers, sample().layers);
+}
+
+/// A file with a document chunk of the given size, followed by a layer
+/// chunk holding a single run of pixels.
+fn claimed_size(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = b"PNTA".to_vec();
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+
+    bytes.extend_from_slice(b"DOC ");
+    bytes.extend_from_slice(&12u32.to_le_bytes());
+    for value in [width, height, 0].iter() {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let mut layer = 0u32.to_le_bytes().to_vec();
+    layer.push(1);
+    layer.extend_from_slice(&1f64.to_le_bytes());
+    layer.extend_from_slice(&0u32.to_le_bytes());
+    layer.extend_from_slice(&[255, 0, 0, 0, 255]);
+    bytes.extend_from_slice(b"LAYR");
+    bytes.extend_from_slice(&(layer.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&layer);
+    bytes
+}
+
+#[test]
+fn sizes_the_data_cannot_back_are_rejected() {
+    assert_eq!(
+        Project::decode(&claimed_size(200_000, 200_000)),
+        Err(ProjectError::Invalid("document too large"))
+    );
+    assert_eq!(
+        Project::decode(&claimed_size(16384, 16384)),
+        Err(ProjectError::Truncated)
+    );
+}
@@ -0,0 +1,360 @@
+//! Drawing tools exercised against the in-memory pixel buffer.
+
+use paint_assemble::{
+    history::{Change, History},
+    project::LayerData,
+    raster::{stroke_line, swap_region, PixelBuffer, Rect, Region, Surface},
+    shapes::{FillStyle, Shape, ShapeObject},
+    utils::{pen_stroke, PenDynamics},
+};
+
+const BLACK: (u8, u8, u8, u8) = (0, 0, 0, 255);
+const WHITE: (u8, u8, u8, u8) = (255, 255, 255, 255);
+const CLEAR: (u8, u8, u8, u8) = (0, 0, 0, 0);
+
+/// Layer and pixels of the undo entry of a single drawing operation.
+fn undo_pixels(history: &mut History) -> (u32, Region) {
+    match history.take_undo().unwrap().pop() {
+        Some((layer, Change::Pixels(region))) => (layer, region),
+        _ => panic!("not a drawing operation"),
+    }
+}
+
+fn white_buffer(width: u32, height: u32) -> PixelBuffer {
+    let mut buffer = PixelBuffer::new(width, height);
+    buffer.set_fill_style("white");
+    buffer.fill_rect(0.0, 0.0, width as f64, height as f64);
+    buffer
+}
+
+#[test]
+fn stroke_line_covers_line_width() {
+    let mut buffer = white_buffer(20, 20);
+    buffer.set_line_width(4.0);
+    buffer.set_stroke_style("black");
+
+    stroke_line(&mut buffer, (5.0, 10.0), (15.0, 10.0));
+
+    assert_eq!(buffer.pixel(10, 10), BLACK);
+    assert_eq!(buffer.pixel(10, 8), BLACK);
+    assert_eq!(buffer.pixel(10, 11), BLACK);
+    assert_eq!(buffer.pixel(10, 13), WHITE);
+    assert_eq!(buffer.pixel(0, 10), WHITE);
+}
+
+#[test]
+fn pen_pressure_thins_and_fades_strokes() {
+    let mut buffer = white_buffer(20, 20);
+    buffer.set_line_width(8.0);
+    buffer.set_stroke_style("black");
+
+    assert_eq!(
+        PenDynamics::from_input("mouse", 0.5, 0, 0),
+        PenDynamics::NONE
+    );
+
+    let light = PenDynamics::from_input("pen", 0.0, 0, 0);
+    pen_stroke(&mut buffer, (5.0, 10.0), &[((15.0, 10.0), light)]);
+
+    assert_eq!(buffer.line_width(), 8.0);
+    assert_eq!(buffer.pixel(10, 11), WHITE);
+    assert_eq!(buffer.pixel(10, 12), WHITE);
+
+    let (r, _, _, a) = buffer.pixel(10, 10);
+    assert!(r > 150 && r < 200);
+    assert_eq!(a, 255);
+}
+
+#[test]
+fn faded_pen_strokes_cover_their_joints_once() {
+    let mut buffer = white_buffer(20, 20);
+    buffer.set_line_width(4.0);
+    buffer.set_stroke_style("black");
+
+    // Doubles back over itself, so the joints and the overlap would
+    // come out darker if each segment were stroked on its own.
+    let light = PenDynamics::from_input("pen", 0.3, 0, 0);
+    pen_stroke(
+        &mut buffer,
+        (3.0, 10.0),
+        &[
+            ((8.0, 10.0), light),
+            ((13.0, 10.0), light),
+            ((17.0, 10.0), light),
+            ((6.0, 10.0), light),
+        ],
+    );
+
+    let middle = buffer.pixel(10, 10);
+    assert!(middle.0 > 0 && middle.0 < 255);
+    assert_eq!(buffer.pixel(8, 10), middle);
+    assert_eq!(buffer.pixel(13, 10), middle);
+    assert_eq!(buffer.pixel(16, 10), middle);
+}
+
+#[test]
+fn translucent_pen_strokes_blend_once_wherever_they_cross() {
+    let mut buffer = white_buffer(20, 20);
+    let pen = ShapeObject {
+        shape: Shape::Pen {
+            start: (3.0, 10.0),
+            segments: vec![
+                ((10.0, 10.0), PenDynamics::NONE),
+                ((10.0, 3.0), PenDynamics::NONE),
+                ((10.0, 17.0), PenDynamics::NONE),
+            ],
+        },
+        style: FillStyle::Outline,
+        stroke: "rgba(0, 0, 0, 0.5)".to_owned(),
+        fill: "black".to_owned(),
+        line_width: 3.0,
+    };
+
+    pen.draw(&mut buffer);
+
+    let middle = buffer.pixel(6, 10);
+    assert_eq!(middle, (127, 127, 127, 255));
+    assert_eq!(buffer.pixel(10, 10), middle);
+    assert_eq!(buffer.pixel(10, 5), middle);
+}
+
+#[test]
+fn filled_rectangle_with_outline() {
+    let mut buffer = white_buffer(20, 20);
+    buffer.set_line_width(2.0);
+    buffer.set_stroke_style("black");
+
+    let shape = Shape::Rectangle {
+        x: 4.0,
+        y: 4.0,
+        w: 12.0,
+        h: 12.0,
+    };
+    shape.draw(&mut buffer, FillStyle::OutlineFill, "#ff0000");
+
+    assert_eq!(buffer.pixel(4, 10), BLACK);
+    assert_eq!(buffer.pixel(10, 10), (255, 0, 0, 255));
+    assert_eq!(buffer.pixel(1, 1), WHITE);
+}
+
+#[test]
+fn outlined_circle_leaves_center_untouched() {
+    let mut buffer = white_buffer(30, 30);
+    buffer.set_line_width(2.0);
+    buffer.set_stroke_style("black");
+
+    let shape = Shape::Circle {
+        cx: 15.0,
+        cy: 15.0,
+        radius: 10.0,
+    };
+    shape.draw(&mut buffer, FillStyle::Outline, "black");
+
+    assert_eq!(buffer.pixel(15, 15), WHITE);
+    assert_eq!(buffer.pixel(25, 15), BLACK);
+    assert_eq!(buffer.pixel(15, 5), BLACK);
+}
+
+#[test]
+fn clear_rect_and_regions_round_trip() {
+    let mut buffer = white_buffer(10, 10);
+    let before = buffer.read(&Rect::new(2, 2, 4, 4)).unwrap();
+
+    buffer.clear_rect(2.0, 2.0, 4.0, 4.0);
+    assert_eq!(buffer.pixel(3, 3), CLEAR);
+
+    let cleared = swap_region(&mut buffer, &before).unwrap();
+    assert_eq!(buffer.pixel(3, 3), WHITE);
+    assert!(cleared.data.iter().all(|value| *value == 0));
+}
+
+#[test]
+fn region_average_ignores_transparent_pixels() {
+    let mut buffer = white_buffer(4, 1);
+    buffer.set_fill_style("#ff0000");
+    buffer.fill_rect(1.0, 0.0, 1.0, 1.0);
+    buffer.clear_rect(2.0, 0.0, 2.0, 1.0);
+
+    let sample = buffer.read(&Rect::new(0, 0, 4, 1)).unwrap();
+    assert_eq!(sample.average(), Some((255, 128, 128, 128)));
+    assert_eq!(sample.crop(&Rect::new(2, 0, 2, 1)).average(), None);
+}
+
+#[test]
+fn undo_restores_only_the_touched_area() {
+    let mut buffer = white_buffer(20, 20);
+    let mut history = History::new(1024 * 1024);
+    buffer.set_line_width(2.0);
+    buffer.set_stroke_style("black");
+
+    history.begin(0);
+    history
+        .touch(
+            &buffer,
+            Rect::from_points((5.0, 5.0), (15.0, 5.0), 2.0, 20, 20),
+        )
+        .unwrap();
+    stroke_line(&mut buffer, (5.0, 5.0), (15.0, 5.0));
+    assert_eq!(history.commit(), Some(Rect::new(3, 3, 14, 4)));
+
+    let (layer, region) = undo_pixels(&mut history);
+    assert_eq!(layer, 0);
+    assert_eq!(region.rect, Rect::new(3, 3, 14, 4));
+
+    swap_region(&mut buffer, &region).unwrap();
+    assert_eq!(buffer.pixel(10, 5), WHITE);
+}
+
+#[test]
+fn strokes_keep_what_was_under_them_before_they_crossed_it() {
+    let mut buffer = white_buffer(20, 20);
+    let mut history = History::new(1024 * 1024);
+    buffer.set_line_width(2.0);
+    buffer.set_stroke_style("black");
+
+    history.begin(0);
+    let first = Rect::from_points((5.0, 5.0), (15.0, 5.0), 2.0, 20, 20);
+    history.touch(&buffer, first).unwrap();
+    stroke_line(&mut buffer, (5.0, 5.0), (15.0, 5.0));
+    // Going back over the first segment must not read its black pixels.
+    let second = Rect::from_points((15.0, 5.0), (10.0, 15.0), 2.0, 20, 20);
+    history.touch(&buffer, second).unwrap();
+    stroke_line(&mut buffer, (15.0, 5.0), (10.0, 15.0));
+    assert_eq!(history.commit(), Some(first.union(&second)));
+
+    let (_, region) = undo_pixels(&mut history);
+    swap_region(&mut buffer, &region).unwrap();
+    assert_eq!(buffer.pixel(10, 5), WHITE);
+    assert_eq!(buffer.pixel(15, 5), WHITE);
+    assert_eq!(buffer.pixel(12, 10), WHITE);
+}
+
+#[test]
+fn history_drops_oldest_entries_over_budget() {
+    let buffer = white_buffer(10, 10);
+    let region = buffer.read(&Rect::new(0, 0, 5, 5)).unwrap();
+    let mut history = History::new(region.size() * 2);
+
+    history.record(0, region.clone());
+    history.record(0, region.clone());
+    history.record(0, region);
+
+    assert!(history.take_undo().is_some());
+    assert!(history.take_undo().is_some());
+    assert!(!history.can_undo());
+}
+
+#[test]
+fn layer_snapshots_count_their_objects_against_the_budget() {
+    let pen = ShapeObject {
+        shape: Shape::Pen {
+            start: (0.0, 0.0),
+            segments: vec![((1.0, 1.0), PenDynamics::NONE); 1000],
+        },
+        style: FillStyle::Outline,
+        stroke: "black".to_owned(),
+        fill: "black".to_owned(),
+        line_width: 1.0,
+    };
+    let layer = LayerData {
+        id: 1,
+        name: "Ink".to_owned(),
+        visible: true,
+        opacity: 1.0,
+        pixels: vec![],
+        vector: true,
+        shapes: vec![pen],
+        texts: vec![],
+    };
+    let mut history = History::new(16 * 1024);
+
+    history.record_entry(vec![(1, Change::Layer(Some((1, layer))))]);
+
+    assert!(!history.can_undo());
+}
+
+#[test]
+fn history_revision_changes_with_the_document() {
+    let buffer = white_buffer(10, 10);
+    let mut history = History::new(1024 * 1024);
+    let start = history.revision();
+
+    history.begin(0);
+    assert_eq!(history.commit(), None);
+    assert_eq!(history.revision(), start);
+
+    history.record(0, buffer.read(&Rect::new(0, 0, 5, 5)).unwrap());
+    let recorded = history.revision();
+    assert_ne!(recorded, start);
+
+    let entry = history.take_undo().unwrap();
+    history.push_redo(entry);
+    assert_ne!(history.revision(), recorded);
+}
+
+#[test]
+fn history_keeps_what_was_painted_since_the_pixels_last_changed_otherwise() {
+    let buffer = white_buffer(10, 10);
+    let region = |x| buffer.read(&Rect::new(x, 0, 2, 2)).unwrap();
+    let dot = |x| ShapeObject {
+        shape: Shape::Line {
+            x1: x,
+            y1: 1.0,
+            x2: x,
+            y2: 1.0,
+        },
+        style: FillStyle::Outline,
+        stroke: "black".to_owned(),
+        fill: "black".to_owned(),
+        line_width: 1.0,
+    };
+    let mut history = History::new(1024 * 1024);
+
+    history.record(1, region(0));
+    history.record_drawn(1, region(2), dot(3.0));
+    history.record_entry(vec![(1, Change::Shapes(vec![]))]);
+    history.record(2, region(4));
+    history.record_drawn(1, region(6), dot(7.0));
+
+    let drawn = history.drawn(1);
+    assert_eq!(drawn.len(), 2);
+    assert_eq!(
+        (drawn[0].0.rect, drawn[0].1),
+        (Rect::new(6, 0, 2, 2), &dot(7.0))
+    );
+    assert_eq!(drawn[1].1, &dot(3.0));
+    assert!(history.drawn(2).is_empty());
+
+    // Undoing the last one leaves the one before.
+    let entry = history.take_undo().unwrap();
+    history.push_redo(entry);
+    assert_eq!(history.drawn(1).len(), 1);
+
+    history.record(1, region(8));
+    assert!(history.drawn(1).is_empty());
+}
+
+#[test]
+fn cancelled_operations_hand_back_the_touched_area() {
+    let mut buffer = white_buffer(20, 20);
+    let mut history = History::new(1024 * 1024);
+    buffer.set_line_width(2.0);
+    buffer.set_stroke_style("black");
+
+    history.begin(0);
+    history
+        .touch(
+            &buffer,
+            Rect::from_points((5.0, 5.0), (15.0, 5.0), 2.0, 20, 20),
+        )
+        .unwrap();
+    stroke_line(&mut buffer, (5.0, 5.0), (15.0, 5.0));
+
+    let (layer, region) = history.cancel().unwrap();
+    assert_eq!((layer, region.rect), (0, Rect::new(3, 3, 14, 4)));
+    buffer.write(&region).unwrap();
+    assert_eq!(buffer.pixel(10, 5), WHITE);
+
+    assert!(!history.can_undo());
+    assert_eq!(history.commit(), None);
+}
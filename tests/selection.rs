@@ -0,0 +1,109 @@
+//! Selection masks for rectangle, lasso and polygon selections.
+
+use paint_assemble::{
+    raster::{Rect, Region},
+    selection::{Combine, Mask},
+    transform::Transform,
+    utils::{region_mask, FillOptions},
+};
+
+#[test]
+fn polygon_selects_pixel_centers_inside_the_outline() {
+    let mask = Mask::polygon(&[(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)], 10, 10);
+
+    assert_eq!(mask.rect, Rect::new(0, 0, 4, 4));
+    let rows: Vec<&[u8]> = mask.coverage.chunks(4).collect();
+    assert_eq!(rows[0], &[255, 255, 255, 0]);
+    assert_eq!(rows[3], &[0, 0, 0, 0]);
+    assert_eq!(mask.at(0, 2), 255);
+    assert_eq!(mask.at(9, 9), 0);
+
+    assert!(Mask::polygon(&[(0.0, 0.0), (4.0, 4.0)], 10, 10).is_empty());
+}
+
+#[test]
+fn keep_and_erase_split_a_region_along_the_mask() {
+    let mask = Mask::polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)], 2, 1);
+    let region = Region {
+        rect: Rect::new(0, 0, 2, 1),
+        data: vec![10, 20, 30, 255, 40, 50, 60, 255],
+    };
+
+    let mut kept = region.clone();
+    mask.keep(&mut kept);
+    assert_eq!(kept.data, vec![10, 20, 30, 255, 40, 50, 60, 0]);
+
+    let mut erased = region.clone();
+    mask.erase(&mut erased, None);
+    assert_eq!(erased.data, vec![10, 20, 30, 0, 40, 50, 60, 255]);
+
+    let mut erased = region;
+    mask.erase(&mut erased, Some((255, 255, 255, 255)));
+    assert_eq!(erased.data, vec![255, 255, 255, 255, 40, 50, 60, 255]);
+}
+
+#[test]
+fn mask_follows_a_moved_and_flipped_selection() {
+    // An L shape: the left column and the bottom row of a 2 x 2 area.
+    let mask = Mask {
+        rect: Rect::new(1, 1, 2, 2),
+        coverage: vec![255, 0, 255, 255],
+    };
+    let mut transform = Transform::new(&mask.rect);
+    transform.flip_horizontal();
+    transform.nudge(3.0, 0.0);
+
+    let moved = mask.transformed(&transform, 10, 10);
+
+    assert_eq!(moved.rect, Rect::new(4, 1, 2, 2));
+    assert_eq!(moved.coverage, vec![0, 255, 255, 255]);
+}
+
+#[test]
+fn wand_selection_is_trimmed_to_the_matching_area() {
+    // A 4 x 2 image whose right half is red.
+    let mut data = vec![255; 4 * 2 * 4];
+    for pixel in [2, 3, 6, 7].iter() {
+        data[pixel * 4..pixel * 4 + 4].copy_from_slice(&[255, 0, 0, 255]);
+    }
+
+    let coverage = region_mask(&data, 4, 2, 3, 0, &FillOptions::default());
+    let mask = Mask::from_coverage(coverage, 4, 2);
+
+    assert_eq!(mask.rect, Rect::new(2, 0, 2, 2));
+    assert_eq!(mask.coverage, vec![255; 4]);
+}
+
+#[test]
+fn selections_add_subtract_and_intersect() {
+    let left = Mask::full(Rect::new(0, 0, 2, 1));
+    let right = Mask::full(Rect::new(1, 0, 2, 1));
+
+    let added = left.combine(&right, Combine::Add);
+    assert_eq!(added, Mask::full(Rect::new(0, 0, 3, 1)));
+
+    let subtracted = left.combine(&right, Combine::Subtract);
+    assert_eq!(subtracted, Mask::full(Rect::new(0, 0, 1, 1)));
+
+    let intersected = left.combine(&right, Combine::Intersect);
+    assert_eq!(intersected, Mask::full(Rect::new(1, 0, 1, 1)));
+
+    assert!(left.combine(&left, Combine::Subtract).is_empty());
+}
+
+#[test]
+fn outline_joins_pixel_edges() {
+    let square = Mask::full(Rect::new(1, 2, 3, 3));
+    let mut outline = square.outline();
+    outline.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    assert_eq!(
+        outline,
+        vec![
+            ((1.0, 2.0), (1.0, 5.0)),
+            ((1.0, 2.0), (4.0, 2.0)),
+            ((1.0, 5.0), (4.0, 5.0)),
+            ((4.0, 2.0), (4.0, 5.0)),
+        ]
+    );
+}
@@ -0,0 +1,71 @@
+//! Shapes kept as objects on vector layers.
+
+use paint_assemble::shapes::{FillStyle, Shape, ShapeObject};
+
+fn object(shape: Shape, style: FillStyle) -> ShapeObject {
+    ShapeObject {
+        shape,
+        style,
+        stroke: "rgba(0, 0, 0, 1)".to_owned(),
+        fill: "rgba(255, 0, 0, 1)".to_owned(),
+        line_width: 4.0,
+    }
+}
+
+#[test]
+fn outlines_are_picked_on_their_stroke_only() {
+    let ring = object(
+        Shape::Circle {
+            cx: 50.0,
+            cy: 50.0,
+            radius: 20.0,
+        },
+        FillStyle::Outline,
+    );
+    assert!(ring.contains((71.0, 50.0), 0.0));
+    assert!(ring.contains((74.0, 50.0), 2.0));
+    assert!(!ring.contains((50.0, 50.0), 2.0));
+
+    let disc = ShapeObject {
+        style: FillStyle::Filled,
+        ..ring.clone()
+    };
+    assert!(disc.contains((50.0, 50.0), 0.0));
+    assert!(!disc.contains((71.0, 50.0), 0.0));
+}
+
+#[test]
+fn lines_are_picked_along_the_segment() {
+    let line = object(
+        Shape::Line {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 100.0,
+            y2: 0.0,
+        },
+        FillStyle::Filled,
+    );
+    assert!(line.contains((50.0, 1.5), 0.0));
+    assert!(!line.contains((50.0, 5.0), 0.0));
+    assert!(!line.contains((105.0, 0.0), 2.0));
+    assert_eq!(line.bounds(), ((-2.0, -2.0), (102.0, 2.0)));
+}
+
+#[test]
+fn moved_shapes_keep_their_size() {
+    let rect = object(
+        Shape::RoundedRectangle {
+            x: 10.0,
+            y: 20.0,
+            w: 30.0,
+            h: 40.0,
+            radius: 5.0,
+        },
+        FillStyle::OutlineFill,
+    );
+    let moved = rect.shape.translated(-10.0, 5.0);
+    assert_eq!(moved.bounds(), ((0.0, 25.0), (30.0, 65.0)));
+    assert!(rect.contains((25.0, 40.0), 0.0));
+    assert!(rect.contains((8.5, 40.0), 0.0));
+    assert!(!rect.contains((5.0, 40.0), 0.0));
+}
@@ -0,0 +1,99 @@
+//! Exporting shapes and text as SVG.
+
+use paint_assemble::{
+    shapes::{FillStyle, Shape, ShapeObject},
+    svg::Svg,
+    text::{TextAlign, TextObject, TextStyle},
+    utils::PenDynamics,
+};
+
+fn export(draw: impl FnOnce(&mut Svg)) -> String {
+    let mut svg = Svg::new(40, 30);
+    svg.begin_layer("Ink & paint", 0.5);
+    draw(&mut svg);
+    svg.end_layer();
+    svg.finish()
+}
+
+#[test]
+fn layers_become_groups() {
+    let svg = export(|_| ());
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+    assert!(svg.contains("width=\"40\" height=\"30\" viewBox=\"0 0 40 30\""));
+    assert!(svg.contains("<g opacity=\"0.5\"><title>Ink &amp; paint</title>\n</g>\n"));
+    assert!(svg.ends_with("</svg>\n"));
+}
+
+#[test]
+fn shapes_keep_their_style() {
+    let svg = export(|svg| {
+        svg.shape(&ShapeObject {
+            shape: Shape::RoundedRectangle {
+                x: 1.0,
+                y: 2.0,
+                w: 10.0,
+                h: 4.0,
+                radius: 5.0,
+            },
+            style: FillStyle::Filled,
+            stroke: "black".to_owned(),
+            fill: "red".to_owned(),
+            line_width: 3.0,
+        })
+    });
+    assert!(svg.contains(
+        "<rect x=\"1\" y=\"2\" width=\"10\" height=\"4\" rx=\"2\" ry=\"2\" \
+         fill=\"red\" stroke=\"none\" stroke-width=\"3\" stroke-linecap=\"round\"/>"
+    ));
+}
+
+#[test]
+fn pen_strokes_split_where_pressure_changes() {
+    let light = PenDynamics {
+        width_factor: 0.5,
+        alpha: 0.5,
+    };
+    let svg = export(|svg| {
+        svg.shape(&ShapeObject {
+            shape: Shape::Pen {
+                start: (0.0, 0.0),
+                segments: vec![
+                    ((1.0, 1.0), PenDynamics::NONE),
+                    ((2.0, 1.0), PenDynamics::NONE),
+                    ((3.0, 0.0), light),
+                ],
+            },
+            style: FillStyle::Filled,
+            stroke: "blue".to_owned(),
+            fill: "blue".to_owned(),
+            line_width: 4.0,
+        })
+    });
+    assert!(
+        svg.contains("<path d=\"M0 0 L1 1 L2 1\" fill=\"none\" stroke=\"blue\" stroke-width=\"4\"")
+    );
+    assert!(svg.contains("<path d=\"M2 1 L3 0\" fill=\"none\" stroke=\"blue\" stroke-width=\"2\""));
+    assert!(svg.contains("opacity=\"0.5\"/>"));
+}
+
+#[test]
+fn text_keeps_its_lines_and_alignment() {
+    let svg = export(|svg| {
+        svg.text(&TextObject {
+            content: "a < b\nc".to_owned(),
+            position: (20.0, 10.0),
+            style: TextStyle {
+                size: 10.0,
+                line_height: 1.5,
+                align: TextAlign::Center,
+                ..TextStyle::default()
+            },
+            color: "green".to_owned(),
+        })
+    });
+    assert!(svg.contains("font-size=\"10\""));
+    assert!(svg.contains("text-anchor=\"middle\" dominant-baseline=\"alphabetic\" fill=\"green\""));
+    assert!(svg.contains(
+        "<tspan x=\"20\" y=\"10\">a &lt; b</tspan><tspan x=\"20\" y=\"25\">c</tspan></text>"
+    ));
+}
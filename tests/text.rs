@@ -0,0 +1,112 @@
+//! Editing text typed with the text tool.
+
+use paint_assemble::text::{TextAlign, TextBaseline, TextEdit, TextStyle};
+
+/// Every character ten pixels wide.
+fn measure(text: &str) -> f64 {
+    text.chars().count() as f64 * 10.0
+}
+
+#[test]
+fn typing_replaces_the_selection() {
+    let mut edit = TextEdit::with_content("hello world");
+    edit.move_home(false);
+    for _ in 0..5 {
+        edit.move_right(true);
+    }
+    assert_eq!(edit.selected_text(), "hello");
+
+    edit.insert("goodbye");
+    assert_eq!(edit.content(), "goodbye world");
+    assert_eq!(edit.selection(), None);
+    assert_eq!(edit.caret(), 7);
+}
+
+#[test]
+fn deleting_steps_over_whole_characters() {
+    let mut edit = TextEdit::with_content("naïve 日本");
+    edit.delete_backward();
+    assert_eq!(edit.content(), "naïve 日");
+
+    edit.move_home(false);
+    edit.move_right(false);
+    edit.move_right(false);
+    edit.delete_forward();
+    assert_eq!(edit.content(), "nave 日");
+}
+
+#[test]
+fn caret_keeps_its_column_across_lines() {
+    let mut edit = TextEdit::with_content("first line\nab\nthird line");
+    assert_eq!(edit.caret_line(), (2, 10));
+
+    edit.move_vertical(-1, false, measure);
+    assert_eq!(edit.caret_line(), (1, 2));
+    edit.move_vertical(-1, true, measure);
+    assert_eq!(edit.caret_line(), (0, 2));
+    assert_eq!(edit.selected_text(), "rst line\nab");
+
+    edit.place_caret(2, 34.0, false, measure);
+    assert_eq!(edit.caret_line(), (2, 3));
+    assert_eq!(edit.caret_x(measure), 30.0);
+}
+
+#[test]
+fn compositions_show_without_changing_the_content() {
+    let mut edit = TextEdit::with_content("ab\ncd");
+    edit.set_composition(Some("にほん".to_owned()));
+    assert_eq!(edit.displayed_lines(), vec!["ab", "cdにほん"]);
+    assert_eq!(edit.content(), "ab\ncd");
+
+    edit.set_composition(None);
+    edit.insert("日本");
+    assert_eq!(edit.displayed_lines(), vec!["ab", "cd日本"]);
+}
+
+#[test]
+fn styles_give_the_css_font_and_align_lines() {
+    let style = TextStyle {
+        family: "Georgia, serif".to_owned(),
+        size: 20.0,
+        weight: 700,
+        italic: true,
+        align: TextAlign::Center,
+        baseline: TextBaseline::Top,
+        line_height: 1.5,
+        ..TextStyle::default()
+    };
+    assert_eq!(style.font(), "italic 700 20px Georgia, serif");
+
+    // Centred lines start half their width left of the point.
+    let mut edit = TextEdit::with_content("abcd\nab");
+    assert_eq!(
+        style.locate(&edit, (100.0, 0.0), (105.0, 35.0), measure),
+        (1, 15.0)
+    );
+    assert!(style.contains(&edit, (100.0, 0.0), (85.0, 29.0), measure));
+    assert!(!style.contains(&edit, (100.0, 0.0), (75.0, 29.0), measure));
+
+    // Going up keeps the caret under the same point of the page.
+    style.move_vertical(&mut edit, -1, false, measure);
+    assert_eq!(edit.caret_line(), (0, 3));
+    assert_eq!(
+        style.caret_point(&edit, (100.0, 0.0), measure),
+        (110.0, 0.0)
+    );
+}
+
+#[test]
+fn frame_encloses_every_line() {
+    let style = TextStyle {
+        size: 10.0,
+        align: TextAlign::Right,
+        baseline: TextBaseline::Top,
+        line_height: 2.0,
+        ..TextStyle::default()
+    };
+    let edit = TextEdit::with_content("abc\nabcdef");
+    assert_eq!(
+        style.frame(&edit, (100.0, 50.0), measure),
+        [(40.0, 50.0), (100.0, 90.0)]
+    );
+}
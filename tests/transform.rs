@@ -0,0 +1,59 @@
+//! Moving, scaling, rotating and flipping a floating selection.
+
+use std::f64::consts::PI;
+
+use paint_assemble::{
+    raster::Rect,
+    transform::{Handle, Transform},
+};
+
+fn close(a: (f64, f64), b: (f64, f64)) -> bool {
+    (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9
+}
+
+fn selection() -> Transform {
+    Transform::new(&Rect::new(10, 20, 40, 20))
+}
+
+#[test]
+fn untransformed_selection_only_translates() {
+    assert_eq!(selection().matrix(), [1.0, 0.0, 0.0, 1.0, 10.0, 20.0]);
+    assert_eq!(selection().hit((30.0, 30.0), 1.0), Some(Handle::Move));
+    assert_eq!(
+        selection().hit((50.0, 40.0), 1.0),
+        Some(Handle::Scale(1, 1))
+    );
+    assert_eq!(selection().hit((30.0, -4.0), 1.0), Some(Handle::Rotate));
+    assert_eq!(selection().hit((80.0, 80.0), 1.0), None);
+}
+
+#[test]
+fn scaling_keeps_the_opposite_corner_still() {
+    let scaled = selection().drag(Handle::Scale(1, 1), (50.0, 40.0), (90.0, 50.0), false);
+    assert!(close(scaled.corners()[0], (10.0, 20.0)));
+    assert!(close(scaled.corners()[2], (90.0, 50.0)));
+
+    // Dragging past the anchor flips the selection.
+    let flipped = selection().drag(Handle::Scale(1, 0), (50.0, 30.0), (0.0, 30.0), false);
+    assert!(flipped.scale_x < 0.0);
+    assert_eq!(flipped.bounds(0.0, 100, 100), Rect::new(0, 20, 10, 20));
+}
+
+#[test]
+fn constrained_drags_keep_aspect_and_snap_rotation() {
+    let scaled = selection().drag(Handle::Scale(1, 1), (50.0, 40.0), (90.0, 45.0), true);
+    assert_eq!(scaled.scale_x, scaled.scale_y);
+    assert_eq!(scaled.scale_x, 2.0);
+
+    let rotated = selection().drag(Handle::Rotate, (30.0, 0.0), (60.0, 31.0), true);
+    assert!((rotated.rotation - PI / 2.0).abs() < 1e-9);
+}
+
+#[test]
+fn flips_and_nudges() {
+    let mut transform = selection();
+    transform.flip_horizontal();
+    transform.nudge(5.0, -5.0);
+
+    assert_eq!(transform.matrix(), [-1.0, 0.0, 0.0, 1.0, 55.0, 15.0]);
+}
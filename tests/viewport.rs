@@ -0,0 +1,28 @@
+//! Mapping between on-screen and document coordinates.
+
+use paint_assemble::viewport::Viewport;
+
+#[test]
+fn zoom_keeps_the_point_under_the_cursor() {
+    let mut viewport = Viewport::new(800, 600, 800, 600);
+    let before = viewport.to_document(200.0, 150.0);
+
+    viewport.zoom_to(200.0, 150.0, 4.0);
+
+    assert_eq!(viewport.zoom, 4.0);
+    assert_eq!(viewport.to_document(200.0, 150.0), before);
+    assert_eq!(viewport.to_view(before.0, before.1), (200.0, 150.0));
+}
+
+#[test]
+fn pan_and_fit_map_back_to_the_document() {
+    let mut viewport = Viewport::new(400, 400, 800, 200);
+
+    viewport.zoom_to_fit();
+    assert_eq!(viewport.zoom, 0.5);
+    assert_eq!(viewport.to_document(0.0, 150.0), (0.0, 0.0));
+    assert!(!viewport.contains(-1.0, 0.0));
+
+    viewport.pan_by(10.0, -50.0);
+    assert_eq!(viewport.to_document(10.0, 100.0), (0.0, 0.0));
+}